@@ -0,0 +1,714 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use file_sorter_lib::processor::options::{
+    AlphaFormat, AnimationPolicy, ChromaSubsampling, CollisionPolicy, ColorProfilePolicy, ConversionSettings, DerivativeLayout,
+    DerivativeSettings, DerivativeSize, DuplicatePolicy, DuplicateSettings, ErrorPolicy, JobOptions, NestedZipMode,
+    NonImagePolicy, OutputDestination, OutputMode, OutputSettings, RawPolicy, ResizeFilter, ResizeMode, ResizeSettings,
+    SortSettings, TargetFormat, TransparencyPolicy, default_sort_template,
+};
+use file_sorter_lib::processor::input::group_paths;
+use file_sorter_lib::processor::progress::ProgressReporter;
+use file_sorter_lib::processor::rules;
+use file_sorter_lib::processor::zip_handler::{process_job, OutputExists};
+use file_sorter_lib::processor::{JobInfo, ProcessingPhase, ProgressInfo};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[command(name = "file-sorter-cli", version, about = "Convert images in zip files from the command line")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert one or more zip files
+    Convert {
+        /// Input zip files and folders, processed in order. Loose image files are
+        /// gathered into one extra job.
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Folder to write converted zips into
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+
+        /// Output file name without `.zip`; `{stem}`, `{date}` and `{job_id}` are filled in
+        #[arg(long, default_value_t = OutputSettings::default().filename_template)]
+        name: String,
+
+        /// Write each result as a folder instead of a zip
+        #[arg(long)]
+        folder: bool,
+
+        /// What to do when the output already exists
+        #[arg(long, value_enum, default_value_t = OnConflict::Suffix)]
+        on_conflict: OnConflict,
+
+        /// What to do with entries that are not images
+        #[arg(long, value_enum, default_value_t = NonImages::Passthrough)]
+        non_images: NonImages,
+
+        /// What to do when an image can't be converted
+        #[arg(long, value_enum, default_value_t = OnError::Fail)]
+        on_error: OnError,
+
+        /// How to handle zip files inside the input zip
+        #[arg(long, value_enum, default_value_t = NestedZips::Ignore)]
+        nested_zips: NestedZips,
+
+        /// How many levels of nested zips to descend into
+        #[arg(long, default_value_t = JobOptions::default().max_nested_depth)]
+        max_nested_depth: usize,
+
+        /// Number of images to convert at once (defaults to the number of CPU cores)
+        #[arg(short = 'j', long)]
+        threads: Option<usize>,
+
+        /// Format converted images are written in
+        #[arg(long, value_enum, default_value_t = Format::Jpeg)]
+        format: Format,
+
+        /// Encoder quality from 1 to 100 (ignored for PNG and lossless WebP)
+        #[arg(short, long, default_value_t = ConversionSettings::default().quality,
+              value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+
+        /// Chroma subsampling for JPEG output
+        #[arg(long, value_enum, default_value_t = Chroma::Yuv444)]
+        chroma: Chroma,
+
+        /// Also re-encode JPEG, PNG and files already in the target format
+        #[arg(long)]
+        reencode: bool,
+
+        /// What to do with animated GIFs and WebPs
+        #[arg(long, value_enum, default_value_t = Animated::Keep)]
+        animated: Animated,
+
+        /// How to turn camera RAW files (DNG, CR2, NEF, ARW) into images
+        #[arg(long, value_enum, default_value_t = Raw::Ignore)]
+        raw: Raw,
+
+        /// Copy RAW files into the output as well as the images made from them
+        #[arg(long)]
+        keep_raw: bool,
+
+        /// Rotate copied JPEGs upright following their EXIF orientation, without re-encoding
+        #[arg(long)]
+        rotate_jpegs: bool,
+
+        /// What to do with ICC colour profiles (Display P3, Adobe RGB) in converted images
+        #[arg(long, value_enum, default_value_t = ColorProfile::Embed)]
+        color_profile: ColorProfile,
+
+        /// Write images with more than 8 bits per channel as 16-bit PNG instead of dithering them
+        #[arg(long)]
+        high_bit_depth_png: bool,
+
+        /// What to do with transparent images when the format can't store alpha
+        #[arg(long, value_enum, default_value_t = Transparency::Composite)]
+        transparency: Transparency,
+
+        /// Background colour for --transparency composite, as RRGGBB
+        #[arg(long, default_value = "ffffff", value_parser = parse_color)]
+        background: [u8; 3],
+
+        /// Scale converted images so their longest side is at most this many pixels
+        #[arg(long)]
+        max_long_edge: Option<u32>,
+
+        /// Maximum width in pixels of converted images
+        #[arg(long)]
+        max_width: Option<u32>,
+
+        /// Maximum height in pixels of converted images
+        #[arg(long)]
+        max_height: Option<u32>,
+
+        /// Maximum size of converted images in megapixels
+        #[arg(long)]
+        max_megapixels: Option<f32>,
+
+        /// How images are brought to the size limits (fill and exact need --max-width and --max-height)
+        #[arg(long, value_enum, default_value_t = Resize::Fit)]
+        resize_mode: Resize,
+
+        /// Resampling filter used when resizing
+        #[arg(long, value_enum, default_value_t = Filter::Lanczos3)]
+        resize_filter: Filter,
+
+        /// Enlarge images smaller than the size limits
+        #[arg(long)]
+        upscale: bool,
+
+        /// Also resize JPEGs, PNGs and files already in the target format, re-encoding them
+        #[arg(long)]
+        resize_copied: bool,
+
+        /// Also write a smaller copy of every image, as NAME=PIXELS for its longest side
+        /// (e.g. thumb=256); repeat for several sizes
+        #[arg(long = "derivative", value_name = "NAME=PIXELS", value_parser = parse_derivative)]
+        derivatives: Vec<DerivativeSize>,
+
+        /// Where derivatives go: a folder tree per size, or next to each image with the size as a suffix
+        #[arg(long, value_enum, default_value_t = Layout::Folders)]
+        derivative_layout: Layout,
+
+        /// Sort the output by capture date into paths like {year}/{month}/{day}/{filename};
+        /// files without an EXIF date use their modified time
+        #[arg(long, value_name = "TEMPLATE")]
+        sort: Option<String>,
+
+        /// Sort rules to try before the template, from a TOML or JSON file; the first
+        /// rule matching a file picks its folder
+        #[arg(long, value_name = "FILE")]
+        rules: Option<PathBuf>,
+
+        /// What to do with images whose bytes match an earlier one's, in the same input
+        /// or a zip inside it
+        #[arg(long, value_enum, default_value_t = Duplicates::KeepAll)]
+        duplicates: Duplicates,
+
+        /// Also list images that look alike, such as resized or re-saved copies, in report.json
+        #[arg(long)]
+        near_duplicates: bool,
+
+        /// How many of the 64 perceptual hash bits may differ for near duplicates
+        #[arg(long, value_name = "BITS", default_value_t = 5)]
+        near_distance: u32,
+
+        /// List where each file would go without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NonImages {
+    /// Copy them into the output unchanged
+    Passthrough,
+    /// Leave them out and list them in report.json
+    Report,
+    /// Leave them out silently
+    Drop,
+}
+
+impl From<NonImages> for NonImagePolicy {
+    fn from(value: NonImages) -> Self {
+        match value {
+            NonImages::Passthrough => NonImagePolicy::Passthrough,
+            NonImages::Report => NonImagePolicy::DropAndReport,
+            NonImages::Drop => NonImagePolicy::DropSilently,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OnConflict {
+    /// Add a number to the new file's name
+    Suffix,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep the existing file and skip the input
+    Skip,
+}
+
+impl From<OnConflict> for CollisionPolicy {
+    fn from(value: OnConflict) -> Self {
+        match value {
+            OnConflict::Suffix => CollisionPolicy::Suffix,
+            OnConflict::Overwrite => CollisionPolicy::Overwrite,
+            OnConflict::Skip => CollisionPolicy::Skip,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OnError {
+    /// Stop and write nothing for that input
+    Fail,
+    /// Leave the image out and list it in report.json
+    Skip,
+    /// Copy the original image unchanged and list it in report.json
+    CopyOriginal,
+}
+
+impl From<OnError> for ErrorPolicy {
+    fn from(value: OnError) -> Self {
+        match value {
+            OnError::Fail => ErrorPolicy::FailFast,
+            OnError::Skip => ErrorPolicy::SkipAndReport,
+            OnError::CopyOriginal => ErrorPolicy::CopyOriginalAndReport,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Duplicates {
+    /// Write every copy without looking for duplicates
+    KeepAll,
+    /// Write every copy and list duplicates in report.json
+    Report,
+    /// Write only the first copy and list the others in report.json
+    KeepOne,
+}
+
+impl From<Duplicates> for DuplicatePolicy {
+    fn from(value: Duplicates) -> Self {
+        match value {
+            Duplicates::KeepAll => DuplicatePolicy::KeepAll,
+            Duplicates::Report => DuplicatePolicy::KeepAllAndReport,
+            Duplicates::KeepOne => DuplicatePolicy::KeepOne,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NestedZips {
+    /// Treat them like any other non-image entry
+    Ignore,
+    /// Convert their contents into a folder named after the zip
+    Flatten,
+    /// Convert their contents and pack them back into a zip
+    Repack,
+}
+
+impl From<NestedZips> for NestedZipMode {
+    fn from(value: NestedZips) -> Self {
+        match value {
+            NestedZips::Ignore => NestedZipMode::Ignore,
+            NestedZips::Flatten => NestedZipMode::Flatten,
+            NestedZips::Repack => NestedZipMode::Repack,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Jpeg,
+    Png,
+    /// Lossless WebP
+    Webp,
+    /// Lossy WebP, using --quality
+    WebpLossy,
+    Avif,
+}
+
+impl From<Format> for TargetFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Jpeg => TargetFormat::Jpeg,
+            Format::Png => TargetFormat::Png,
+            Format::Webp => TargetFormat::WebpLossless,
+            Format::WebpLossy => TargetFormat::WebpLossy,
+            Format::Avif => TargetFormat::Avif,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Animated {
+    /// Copy them unchanged
+    Keep,
+    /// Re-encode as animated WebP for WebP output, otherwise as animated GIF
+    Convert,
+    /// Convert only the first frame to the target format
+    FirstFrame,
+}
+
+impl From<Animated> for AnimationPolicy {
+    fn from(value: Animated) -> Self {
+        match value {
+            Animated::Keep => AnimationPolicy::Keep,
+            Animated::Convert => AnimationPolicy::ConvertAnimated,
+            Animated::FirstFrame => AnimationPolicy::FirstFrame,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Raw {
+    /// Treat them like any other non-image file
+    Ignore,
+    /// Use the JPEG preview the camera embedded, developing files without one
+    Preview,
+    /// Develop the sensor data
+    Develop,
+}
+
+impl From<Raw> for RawPolicy {
+    fn from(value: Raw) -> Self {
+        match value {
+            Raw::Ignore => RawPolicy::Ignore,
+            Raw::Preview => RawPolicy::EmbeddedPreview,
+            Raw::Develop => RawPolicy::Develop,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorProfile {
+    /// Embed the source profile in the output (AVIF output is converted to sRGB)
+    Embed,
+    /// Convert the colours to sRGB and leave the profile out
+    Srgb,
+}
+
+impl From<ColorProfile> for ColorProfilePolicy {
+    fn from(value: ColorProfile) -> Self {
+        match value {
+            ColorProfile::Embed => ColorProfilePolicy::Embed,
+            ColorProfile::Srgb => ColorProfilePolicy::ConvertToSrgb,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Transparency {
+    /// Flatten onto the --background colour
+    Composite,
+    /// Write transparent images as PNG instead
+    KeepPng,
+    /// Write transparent images as lossless WebP instead
+    KeepWebp,
+    /// Count transparent images as failed
+    Fail,
+}
+
+impl Transparency {
+    fn policy(self, background: [u8; 3]) -> TransparencyPolicy {
+        match self {
+            Transparency::Composite => TransparencyPolicy::Composite { background },
+            Transparency::KeepPng => TransparencyPolicy::KeepAlpha { format: AlphaFormat::Png },
+            Transparency::KeepWebp => TransparencyPolicy::KeepAlpha { format: AlphaFormat::WebpLossless },
+            Transparency::Fail => TransparencyPolicy::Fail,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Layout {
+    /// thumb/photos/a.jpg
+    Folders,
+    /// photos/a_thumb.jpg
+    Suffix,
+}
+
+impl From<Layout> for DerivativeLayout {
+    fn from(value: Layout) -> Self {
+        match value {
+            Layout::Folders => DerivativeLayout::Folders,
+            Layout::Suffix => DerivativeLayout::Suffix,
+        }
+    }
+}
+
+/// Parse a derivative size given as `NAME=PIXELS`
+fn parse_derivative(value: &str) -> Result<DerivativeSize, String> {
+    let (name, pixels) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=PIXELS, got {}", value))?;
+    let max_long_edge = pixels.parse().map_err(|e| format!("{}: {}", value, e))?;
+    Ok(DerivativeSize {
+        name: name.to_string(),
+        max_long_edge,
+    })
+}
+
+/// Parse an `RRGGBB` colour, with or without a leading `#`
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("expected a colour as RRGGBB, got {}", value));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| format!("{}: {}", value, e));
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Resize {
+    /// Fit within every limit, keeping the aspect ratio
+    Fit,
+    /// Cover the box and crop the overflow from the centre
+    Fill,
+    /// Stretch to exactly the box
+    Exact,
+}
+
+impl From<Resize> for ResizeMode {
+    fn from(value: Resize) -> Self {
+        match value {
+            Resize::Fit => ResizeMode::Fit,
+            Resize::Fill => ResizeMode::Fill,
+            Resize::Exact => ResizeMode::Exact,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Filter {
+    Nearest,
+    /// Bilinear
+    Triangle,
+    /// Bicubic
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<Filter> for ResizeFilter {
+    fn from(value: Filter) -> Self {
+        match value {
+            Filter::Nearest => ResizeFilter::Nearest,
+            Filter::Triangle => ResizeFilter::Triangle,
+            Filter::CatmullRom => ResizeFilter::CatmullRom,
+            Filter::Gaussian => ResizeFilter::Gaussian,
+            Filter::Lanczos3 => ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Chroma {
+    #[value(name = "444")]
+    Yuv444,
+    #[value(name = "422")]
+    Yuv422,
+    #[value(name = "420")]
+    Yuv420,
+}
+
+impl From<Chroma> for ChromaSubsampling {
+    fn from(value: Chroma) -> Self {
+        match value {
+            Chroma::Yuv444 => ChromaSubsampling::Yuv444,
+            Chroma::Yuv422 => ChromaSubsampling::Yuv422,
+            Chroma::Yuv420 => ChromaSubsampling::Yuv420,
+        }
+    }
+}
+
+/// Prints progress to stderr so stdout only carries output paths
+struct TerminalReporter {
+    cancel_flag: Arc<AtomicBool>,
+}
+
+impl ProgressReporter for TerminalReporter {
+    fn report(&self, _job_id: &str, progress: ProgressInfo) {
+        match progress.phase {
+            ProcessingPhase::Scanning => {
+                eprintln!("Scanning {} entries...", progress.total_files);
+            }
+            ProcessingPhase::Converting => {
+                eprintln!(
+                    "[{}/{}] {}",
+                    progress.current_file, progress.total_files, progress.current_filename
+                );
+            }
+            ProcessingPhase::Packaging => {
+                eprintln!("Packaging {} files...", progress.total_files);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::SeqCst)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Convert {
+            inputs,
+            output,
+            name,
+            folder,
+            on_conflict,
+            non_images,
+            on_error,
+            nested_zips,
+            max_nested_depth,
+            threads,
+            format,
+            quality,
+            chroma,
+            reencode,
+            animated,
+            raw,
+            keep_raw,
+            rotate_jpegs,
+            color_profile,
+            high_bit_depth_png,
+            transparency,
+            background,
+            max_long_edge,
+            max_width,
+            max_height,
+            max_megapixels,
+            resize_mode,
+            resize_filter,
+            upscale,
+            resize_copied,
+            derivatives,
+            derivative_layout,
+            sort,
+            rules,
+            duplicates,
+            near_duplicates,
+            near_distance,
+            dry_run,
+        } => {
+            let output_settings = OutputSettings {
+                mode: if folder { OutputMode::Directory } else { OutputMode::Zip },
+                destination: OutputDestination::Folder {
+                    path: output.to_string_lossy().to_string(),
+                },
+                filename_template: name,
+                collision_policy: on_conflict.into(),
+            };
+            if let Err(e) = output_settings.validate() {
+                eprintln!("{:#}", e);
+                return ExitCode::FAILURE;
+            }
+
+            let rules = match rules.as_deref().map(rules::load).transpose() {
+                Ok(rules) => rules.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let sorting = (sort.is_some() || !rules.is_empty()).then(|| SortSettings {
+                template: sort.unwrap_or_else(default_sort_template),
+                rules,
+            });
+
+            let options = JobOptions {
+                non_image_policy: non_images.into(),
+                nested_zip_mode: nested_zips.into(),
+                max_nested_depth,
+                worker_threads: threads,
+                conversion: ConversionSettings {
+                    target_format: format.into(),
+                    quality,
+                    chroma_subsampling: chroma.into(),
+                    reencode_supported: reencode,
+                    animation: animated.into(),
+                    raw: raw.into(),
+                    keep_raw,
+                    rotate_copied_jpegs: rotate_jpegs,
+                    color_profile: color_profile.into(),
+                    high_bit_depth_png,
+                    transparency: transparency.policy(background),
+                    resize: ResizeSettings {
+                        max_long_edge,
+                        max_width,
+                        max_height,
+                        max_megapixels,
+                        mode: resize_mode.into(),
+                        filter: resize_filter.into(),
+                        allow_upscale: upscale,
+                        include_copied: resize_copied,
+                    },
+                },
+                derivatives: DerivativeSettings {
+                    sizes: derivatives,
+                    layout: derivative_layout.into(),
+                },
+                sorting,
+                duplicates: DuplicateSettings {
+                    policy: duplicates.into(),
+                    near_duplicates,
+                    max_distance: near_distance,
+                },
+                dry_run,
+                error_policy: on_error.into(),
+                output: Some(output_settings),
+            };
+            convert(inputs, output, options).await
+        }
+    }
+}
+
+async fn convert(inputs: Vec<PathBuf>, output: PathBuf, options: JobOptions) -> ExitCode {
+    if !output.is_dir() {
+        eprintln!("Output folder does not exist: {}", output.display());
+        return ExitCode::FAILURE;
+    }
+
+    let reporter = TerminalReporter {
+        cancel_flag: Arc::new(AtomicBool::new(false)),
+    };
+
+    // Stop at the next file boundary on Ctrl+C, same as the Cancel button
+    let cancel_flag = Arc::clone(&reporter.cancel_flag);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            cancel_flag.store(true, Ordering::SeqCst);
+        }
+    });
+
+    let mut failures = 0;
+    let paths = inputs
+        .iter()
+        .map(|input| input.to_string_lossy().to_string())
+        .collect();
+    let grouped = group_paths(paths);
+    for path in &grouped.ignored {
+        eprintln!("Skipping {}: not a zip, folder or image", path);
+    }
+    for (input_path, job_input) in grouped.jobs {
+        let input = PathBuf::from(&input_path);
+        let job = JobInfo::with_input(input_path, job_input, options.clone());
+        eprintln!("Processing {}", input.display());
+
+        match process_job(&reporter, &job, &output).await {
+            Ok(output) if options.dry_run => {
+                for record in &output.report.sorted {
+                    match &record.rule {
+                        Some(rule) => println!("{} -> {} ({})", record.original_path, record.output_path, rule),
+                        None => println!("{} -> {}", record.original_path, record.output_path),
+                    }
+                }
+                println!(
+                    "Would write {} file(s) to {}",
+                    output.report.stats.files_included,
+                    output.output_path
+                );
+            }
+            Ok(output) => {
+                let warnings = output.report.warning_count();
+                if warnings > 0 {
+                    eprintln!(
+                        "{} warning(s) for {}, see report.json",
+                        warnings,
+                        input.display()
+                    );
+                }
+                println!("{}", output.output_path);
+            }
+            Err(e) if e.is::<OutputExists>() => eprintln!("Skipped {}: {}", input.display(), e),
+            Err(e) => {
+                eprintln!("Failed to process {}: {:#}", input.display(), e);
+                failures += 1;
+            }
+        }
+
+        if reporter.is_cancelled() {
+            eprintln!("Cancelled");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -1,17 +1,33 @@
-use crate::processor::{JobInfo, ProcessorState};
+use crate::preferences::PreferencesStore;
+use crate::processor::options::{JobOptions, OutputSettings};
+use crate::processor::rules::{self, SortRule};
+use crate::processor::{Enqueued, JobInfo, ProcessorState};
 use crate::processor::queue::start_queue_processor;
+use crate::report::ProcessingReport;
 use tauri::{AppHandle, State};
+use std::path::Path;
 use std::sync::Arc;
 
+/// Queue zips, folders and loose image files. Each zip and folder becomes a job of
+/// its own; loose images are gathered into one job, and other files are returned
+/// as ignored.
 #[tauri::command]
 pub async fn enqueue_zips(
     app: AppHandle,
     state: State<'_, ProcessorState>,
+    preferences: State<'_, PreferencesStore>,
     paths: Vec<String>,
-) -> Result<Vec<JobInfo>, String> {
+    options: Option<JobOptions>,
+) -> Result<Enqueued, String> {
+    // Jobs without their own output settings use the saved preferences. They're
+    // filled in now so changing preferences later doesn't affect queued jobs.
+    let mut options = options.unwrap_or_default();
+    let output = options.output.get_or_insert_with(|| preferences.get().output);
+    output.validate().map_err(|e| format!("Invalid output settings: {:#}", e))?;
+
     // Add jobs to queue
     let jobs = state
-        .add_jobs(paths)
+        .add_jobs(paths, options)
         .map_err(|e| format!("Failed to enqueue jobs: {}", e))?;
 
     // Start processing queue if not already running
@@ -33,6 +49,55 @@ pub async fn clear_finished(state: State<'_, ProcessorState>) -> Result<(), Stri
     Ok(())
 }
 
+#[tauri::command]
+pub async fn list_jobs(state: State<'_, ProcessorState>) -> Result<Vec<JobInfo>, String> {
+    Ok(state.list_jobs())
+}
+
+/// Finished jobs with their output paths and summaries, most recent first
+#[tauri::command]
+pub async fn list_job_history(state: State<'_, ProcessorState>) -> Result<Vec<JobInfo>, String> {
+    Ok(state.list_history())
+}
+
+/// Full report of a job that ran successfully, loaded when it's asked for
+#[tauri::command]
+pub async fn get_job_report(
+    state: State<'_, ProcessorState>,
+    job_id: String,
+) -> Result<Option<ProcessingReport>, String> {
+    state
+        .job_report(&job_id)
+        .map_err(|e| format!("Failed to load report: {:#}", e))
+}
+
+/// Read sort rules from a TOML or JSON rules file, for a job's `sorting` option
+#[tauri::command]
+pub async fn load_sort_rules(path: String) -> Result<Vec<SortRule>, String> {
+    let rules = rules::load(Path::new(&path)).map_err(|e| format!("{:#}", e))?;
+    for rule in &rules {
+        rule.validate().map_err(|e| format!("{:#}", e))?;
+    }
+    Ok(rules)
+}
+
+#[tauri::command]
+pub async fn get_output_settings(
+    preferences: State<'_, PreferencesStore>,
+) -> Result<OutputSettings, String> {
+    Ok(preferences.get().output)
+}
+
+#[tauri::command]
+pub async fn set_output_settings(
+    preferences: State<'_, PreferencesStore>,
+    settings: OutputSettings,
+) -> Result<(), String> {
+    preferences
+        .set_output(settings)
+        .map_err(|e| format!("Failed to save output settings: {}", e))
+}
+
 #[tauri::command]
 pub async fn open_in_folder(path: String) -> Result<(), String> {
     use std::process::Command;
@@ -1,4 +1,5 @@
 pub mod commands;
+pub mod preferences;
 pub mod processor;
 pub mod report;
 pub mod utils;
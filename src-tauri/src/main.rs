@@ -1,17 +1,43 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use file_sorter_lib::{commands, processor::ProcessorState};
+use file_sorter_lib::commands;
+use file_sorter_lib::preferences::PreferencesStore;
+use file_sorter_lib::processor::job_store::JobStore;
+use file_sorter_lib::processor::queue::start_queue_processor;
+use file_sorter_lib::processor::ProcessorState;
+use std::sync::Arc;
+use tauri::Manager;
 
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_shell::init())
-        .manage(ProcessorState::new())
+        .setup(|app| {
+            // Restore the saved queue and pick up where the last session left off
+            let data_dir = app.path().app_data_dir()?;
+            let state = ProcessorState::with_store(JobStore::new(data_dir.join("jobs.json")));
+            app.manage(state.clone());
+
+            let config_dir = app.path().app_config_dir()?;
+            app.manage(PreferencesStore::load(config_dir.join("preferences.json")));
+
+            if state.has_pending() {
+                let handle = app.handle().clone();
+                tauri::async_runtime::spawn(start_queue_processor(handle, Arc::new(state)));
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::enqueue_zips,
             commands::cancel_current,
             commands::clear_finished,
+            commands::list_jobs,
+            commands::list_job_history,
+            commands::get_job_report,
+            commands::load_sort_rules,
+            commands::get_output_settings,
+            commands::set_output_settings,
             commands::open_in_folder,
         ])
         .run(tauri::generate_context!())
@@ -0,0 +1,61 @@
+use crate::processor::options::OutputSettings;
+use crate::utils::disk;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Settings chosen once in the app and applied to every new job
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Preferences {
+    pub output: OutputSettings,
+}
+
+/// User preferences, saved as JSON in the app config directory
+pub struct PreferencesStore {
+    path: PathBuf,
+    preferences: Mutex<Preferences>,
+}
+
+impl PreferencesStore {
+    /// Read saved preferences, falling back to defaults if none have been saved yet.
+    /// An unreadable file is set aside as `preferences.json.corrupt`.
+    pub fn load(path: PathBuf) -> Self {
+        let preferences = disk::load_json(&path);
+        Self {
+            path,
+            preferences: Mutex::new(preferences),
+        }
+    }
+
+    pub fn get(&self) -> Preferences {
+        self.preferences.lock().unwrap().clone()
+    }
+
+    pub fn set_output(&self, output: OutputSettings) -> Result<()> {
+        output.validate()?;
+
+        let mut preferences = self.preferences.lock().unwrap();
+        let mut updated = preferences.clone();
+        updated.output = output;
+        self.save(&updated)?;
+        *preferences = updated;
+        Ok(())
+    }
+
+    fn save(&self, preferences: &Preferences) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("Failed to create app config directory")?;
+        }
+
+        let json = serde_json::to_string_pretty(preferences)?;
+        let temp_path = self.path.with_extension("json.tmp");
+        fs::write(&temp_path, json)
+            .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+        fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("Failed to replace {}", self.path.display()))?;
+        Ok(())
+    }
+}
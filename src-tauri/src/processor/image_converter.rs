@@ -1,10 +1,37 @@
-use anyhow::{Context, Result, anyhow};
-use image::{DynamicImage, ImageFormat, GenericImageView};
+use crate::processor::options::{
+    AnimationPolicy, ChromaSubsampling, ColorProfilePolicy, ConversionSettings, DerivativeSize, RawPolicy,
+    ResizeSettings, TargetFormat, TransparencyPolicy,
+};
+use crate::utils::animation;
+use crate::utils::color;
+use crate::utils::depth;
+use crate::utils::duplicates;
+use crate::utils::format::SourceFormat;
+use crate::utils::hdr::{self, HdrFormat, Primaries};
+use crate::utils::jpeg_transform;
+use crate::utils::metadata::MetadataHandler;
+use crate::utils::raw;
+use crate::utils::resize;
+use anyhow::{Context, Result};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::{WebPDecoder, WebPEncoder};
+use image::metadata::Orientation;
+use image::{
+    AnimationDecoder, DynamicImage, Frame, ImageDecoder, ImageEncoder, ImageFormat, ImageReader, GenericImageView,
+};
+use jpeg_encoder::SamplingFactor;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::io::BufReader;
+use std::path::Path;
+use std::io::{BufWriter, Cursor, Write};
 use std::sync::Once;
 
+/// rav1e speed preset (1 slowest - 10 fastest); 6 keeps batch conversions practical
+const AVIF_SPEED: u8 = 6;
+
 static REGISTER_HEIF_HOOKS: Once = Once::new();
 
 fn register_heif_decoding_hooks() {
@@ -14,33 +41,338 @@ fn register_heif_decoding_hooks() {
 }
 
 pub enum ConversionResult {
-    /// File was copied as-is (JPEG, PNG, GIF, or animated)
+    /// File was copied as-is (JPEG, PNG, or already in the target format)
     Copied,
-    /// File was converted to JPEG
-    Converted { original_format: String },
+    /// File was converted to the job's target format
+    Converted {
+        original_format: String,
+        /// Whether the source's EXIF block was written into the output
+        exif: ExifOutcome,
+        /// The ICC profile the source came with, if any, and what was done with it
+        color_profile: Option<SourceProfile>,
+        /// Set for sources with more than 8 bits per channel or HDR
+        bit_depth: Option<DepthConversion>,
+        /// Set for images with see-through pixels
+        transparency: Option<TransparencyHandling>,
+        dimensions: Dimensions,
+        /// Extension of the written file; `png` for 16-bit PNG output
+        extension: String,
+    },
+    /// Animated GIF or WebP, handled according to the animation policy
+    Animated {
+        original_format: String,
+        frames: usize,
+        /// What was done with it; `Keep` if it was copied as-is
+        handling: AnimationPolicy,
+        /// Extension of the written file
+        extension: String,
+    },
+    /// Camera RAW file turned into the job's target format
+    Raw {
+        original_format: String,
+        /// How the image was made: `EmbeddedPreview` or `Develop`
+        method: RawPolicy,
+        metadata_preserved: bool,
+        dimensions: Dimensions,
+    },
+    /// JPEG that would have been copied, rotated upright without re-encoding
+    Reoriented {
+        /// EXIF Orientation value it had, 2-8
+        orientation: u8,
+        /// Whether a partial block row or column was cut off an edge
+        trimmed: bool,
+        /// Why it couldn't be rotated, in which case it was copied unchanged
+        error: Option<String>,
+    },
+}
+
+/// An ICC profile found in a converted image
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceProfile {
+    /// The profile's description, e.g. `Display P3`
+    pub name: Option<String>,
+    pub handling: ProfileHandling,
+}
+
+/// What was done with a converted image's ICC profile
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProfileHandling {
+    /// Written into the output
+    Embedded,
+    /// The pixels were converted to sRGB and the profile left out
+    ConvertedToSrgb,
+    /// Neither could be done, so the colours may be off
+    Dropped,
+}
+
+/// How a source with more than 8 bits per channel, or an HDR source, was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthConversion {
+    /// Bits per channel of the source: 10, 12 or 16, or 32 for floating point
+    pub source_bits: u8,
+    /// Set for HDR sources; PQ and HLG images were tone mapped to SDR
+    pub hdr: Option<HdrFormat>,
+    /// 8, or 16 for 16-bit PNG output
+    pub output_bits: u8,
+    /// Whether the reduction to 8 bits was dithered
+    pub dithered: bool,
+}
+
+/// What was done with an image that has see-through pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransparencyHandling {
+    /// The target format stores alpha
+    Kept,
+    /// Written as PNG or lossless WebP instead of the target format, to keep alpha
+    ChangedFormat,
+    /// Flattened onto the job's background colour
+    Composited,
+}
+
+/// What became of a source's EXIF block when it was converted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExifOutcome {
+    /// Written into the output
+    Carried,
+    /// The source had none
+    None,
+    /// The source had one, but it was too large or failed to write
+    Dropped,
+}
+
+/// Width and height of a converted image, upright, before and after resizing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dimensions {
+    pub original: (u32, u32),
+    pub output: (u32, u32),
+}
+
+/// Precision and HDR encoding of the source of a decoded image
+#[derive(Clone, Copy)]
+struct SourceDepth {
+    bits: u8,
+    hdr: Option<HdrFormat>,
+}
+
+impl SourceDepth {
+    fn of(img: &DynamicImage) -> Self {
+        Self {
+            bits: depth::bits_per_channel(img),
+            hdr: None,
+        }
+    }
+}
+
+/// What libheif reports about a HEIC or AVIF image besides its pixels
+struct HeifInfo {
+    bits: u8,
+    hdr: Option<HdrFormat>,
+    primaries: Primaries,
+    icc: Option<Vec<u8>>,
+}
+
+/// What [`ImageConverter::encode`] managed to carry over from the source
+struct Encoded {
+    exif: ExifOutcome,
+    color_profile: Option<SourceProfile>,
+    bit_depth: Option<DepthConversion>,
+    transparency: Option<TransparencyHandling>,
+    dimensions: Dimensions,
+    extension: &'static str,
+}
+
+impl Encoded {
+    fn into_result(self, format_name: &str) -> ConversionResult {
+        ConversionResult::Converted {
+            original_format: format_name.to_string(),
+            exif: self.exif,
+            color_profile: self.color_profile,
+            bit_depth: self.bit_depth,
+            transparency: self.transparency,
+            dimensions: self.dimensions,
+            extension: self.extension.to_string(),
+        }
+    }
+}
+
+/// What an encoder wrote besides the pixels
+struct Written {
+    exif: bool,
+    icc: bool,
+}
+
+/// A decoded, upright image and what it carries besides the pixels
+struct Decoded {
+    img: DynamicImage,
+    exif: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+    source: SourceDepth,
+}
+
+/// A smaller copy of an image, made for one of the job's derivative sizes
+pub struct Derivative {
+    /// Name of the size, e.g. `thumb`
+    pub name: String,
+    pub data: Vec<u8>,
+    pub extension: &'static str,
+    /// Width and height of the derivative
+    pub dimensions: (u32, u32),
+}
+
+/// What is made from an image's decoded pixels alongside its conversion
+#[derive(Default)]
+pub struct Extras {
+    /// The job's derivative sizes of the image
+    pub derivatives: Vec<Derivative>,
+    /// Difference hash of the image as it's shown, when asked for and the image
+    /// could be read
+    pub perceptual_hash: Option<u64>,
+}
+
+/// Makes [`Extras`] from the first decode of an image, so the conversion, the
+/// derivatives and the hash share it
+struct ExtrasMaker<'a> {
+    /// The job's converter, whose settings and sizes the derivatives follow
+    converter: &'a ImageConverter,
+    derivatives: bool,
+    hash: bool,
+    made: Option<Extras>,
+}
+
+impl<'a> ExtrasMaker<'a> {
+    fn new(converter: &'a ImageConverter, derivatives: bool, hash: bool) -> Self {
+        Self {
+            converter,
+            derivatives: derivatives && !converter.derivatives.is_empty(),
+            hash,
+            made: None,
+        }
+    }
+
+    fn wanted(&self) -> bool {
+        self.derivatives || self.hash
+    }
+
+    /// Make the extras from `decoded` unless they already are
+    fn take(&mut self, decoded: &Decoded) -> Result<()> {
+        if self.made.is_none() && self.wanted() {
+            self.made = Some(self.make(decoded)?);
+        }
+        Ok(())
+    }
+
+    /// The extras, decoding the image for them if the conversion didn't (copied
+    /// files, kept animations). Images that can't be read are only an error when
+    /// derivatives are made of them.
+    fn finish(self, name: &Path, data: &[u8]) -> Result<Extras> {
+        if let Some(extras) = self.made {
+            return Ok(extras);
+        }
+        if !self.wanted() {
+            return Ok(Extras::default());
+        }
+        match self.converter.decode_any(name, data) {
+            Ok(decoded) => self.make(&decoded),
+            Err(_) if !self.derivatives => Ok(Extras::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn make(&self, decoded: &Decoded) -> Result<Extras> {
+        let derivatives = if self.derivatives {
+            self.converter.make_derivatives(decoded)?
+        } else {
+            Vec::new()
+        };
+        Ok(Extras {
+            derivatives,
+            perceptual_hash: self.hash.then(|| duplicates::difference_hash(&decoded.img)),
+        })
+    }
 }
 
 pub struct ImageConverter {
-    jpeg_quality: u8,
+    settings: ConversionSettings,
+    derivatives: Vec<DerivativeSize>,
 }
 
 impl ImageConverter {
     pub fn new() -> Self {
+        Self::with_settings(ConversionSettings::default())
+    }
+
+    pub fn with_settings(settings: ConversionSettings) -> Self {
         register_heif_decoding_hooks();
-        Self { jpeg_quality: 95 }
+        Self {
+            settings,
+            derivatives: Vec::new(),
+        }
+    }
+
+    /// Also make these derivative sizes of every image, see
+    /// [`process_bytes_with_extras`](Self::process_bytes_with_extras)
+    pub fn with_derivatives(mut self, sizes: Vec<DerivativeSize>) -> Self {
+        self.derivatives = sizes;
+        self
     }
 
     /// Detect if a file is an image and determine if it needs conversion
     pub fn should_process(&self, path: &Path) -> bool {
-        if let Some(ext) = path.extension() {
-            let ext_lower = ext.to_string_lossy().to_lowercase();
-            matches!(
-                ext_lower.as_str(),
-                "jpg" | "jpeg" | "png" | "gif" | "heic" | "heif" | "webp" | "tiff" | "tif" | "bmp" | "avif"
-            )
-        } else {
-            false
+        SourceFormat::from_path(path).is_some_and(|format| self.accepts(format))
+    }
+
+    /// Whether files in `format` are handled as images. RAW files are only taken
+    /// when the job's RAW policy does something with them.
+    pub fn accepts(&self, format: SourceFormat) -> bool {
+        !format.is_raw() || self.settings.raw != RawPolicy::Ignore
+    }
+
+    /// Whether RAW originals are written to the output alongside their conversions
+    pub fn keeps_raw(&self) -> bool {
+        self.settings.keep_raw
+    }
+
+    /// Whether an image in `format` has to be read and passed through
+    /// [`process_bytes`](Self::process_bytes): it is converted, it is a JPEG whose
+    /// orientation is checked before it is copied, or copied images are resized
+    pub fn needs_reading(&self, format: SourceFormat) -> bool {
+        self.format_needs_conversion(format)
+            || (format == SourceFormat::Jpeg && self.settings.rotate_copied_jpegs)
+            || self.resizes_copied()
+            || !self.derivatives.is_empty()
+    }
+
+    /// Whether images that would be copied are resized when over the limits
+    fn resizes_copied(&self) -> bool {
+        self.settings.resize.include_copied && self.settings.resize.is_active()
+    }
+
+    /// Whether a file is re-encoded rather than copied as-is, judged by its extension.
+    /// See [`format_needs_conversion`](Self::format_needs_conversion).
+    pub fn needs_conversion(&self, path: &Path) -> bool {
+        SourceFormat::from_path(path).is_none_or(|format| self.format_needs_conversion(format))
+    }
+
+    /// Whether an image in `format` is re-encoded rather than copied as-is. JPEG, PNG
+    /// and files already in the target format are copied unless `reencode_supported`
+    /// is set. Animated GIFs and WebPs are only recognised once their contents are
+    /// read, and then follow the animation policy. RAW files are always converted
+    /// unless the RAW policy ignores them.
+    pub fn format_needs_conversion(&self, format: SourceFormat) -> bool {
+        if format.is_raw() {
+            return self.accepts(format);
         }
+        let already_supported = matches!(format, SourceFormat::Jpeg | SourceFormat::Png)
+            || format.extension() == self.settings.target_format.extension();
+        !already_supported || self.settings.reencode_supported
+    }
+
+    /// Extension given to converted files
+    pub fn output_extension(&self) -> &'static str {
+        self.settings.target_format.extension()
     }
 
     /// Process an image file: copy if already supported, convert otherwise
@@ -54,164 +386,816 @@ impl ImageConverter {
             fs::create_dir_all(parent)?;
         }
 
-        // Get file extension
-        let ext = input_path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|s| s.to_lowercase())
-            .unwrap_or_default();
+        let data = fs::read(input_path)
+            .with_context(|| format!("Failed to read {}", input_path.display()))?;
+        let output_file = fs::File::create(output_path)
+            .context("Failed to create output file")?;
+        let mut writer = BufWriter::new(output_file);
+
+        let result = self.process_bytes(input_path, &data, &mut writer)?;
+        writer.flush().context("Failed to write output file")?;
+        Ok(result)
+    }
+
+    /// Same as [`process_image`](Self::process_image) for an image already in memory,
+    /// writing the copied or converted bytes to `output`. The format is detected from
+    /// the data; the extension of `name` is only used for files it isn't recognised in.
+    pub fn process_bytes(
+        &self,
+        name: &Path,
+        data: &[u8],
+        output: &mut impl Write,
+    ) -> Result<ConversionResult> {
+        self.process_with(name, data, output, &mut ExtrasMaker::new(self, false, false))
+    }
+
+    /// Same as [`process_bytes`](Self::process_bytes), also making the job's
+    /// derivative sizes of the image and, if `hash` is set, its difference hash for
+    /// finding near duplicates. Both come from the pixels decoded for the conversion;
+    /// copied images are decoded for them only when there's something to make.
+    /// Animations are represented by their first frame.
+    pub fn process_bytes_with_extras(
+        &self,
+        name: &Path,
+        data: &[u8],
+        output: &mut impl Write,
+        hash: bool,
+    ) -> Result<(ConversionResult, Extras)> {
+        let mut extras = ExtrasMaker::new(self, true, hash);
+        let result = self.process_with(name, data, output, &mut extras)?;
+        Ok((result, extras.finish(name, data)?))
+    }
+
+    fn process_with(
+        &self,
+        name: &Path,
+        data: &[u8],
+        output: &mut impl Write,
+        extras: &mut ExtrasMaker,
+    ) -> Result<ConversionResult> {
+        let format = SourceFormat::detect_named(data, name)
+            .or_else(|| SourceFormat::from_path(name))
+            .with_context(|| format!("Unrecognised image format: {}", name.display()))?;
+        let ext = format.extension();
 
         // Check if we should just copy the file
-        if matches!(ext.as_str(), "jpg" | "jpeg" | "png") {
-            fs::copy(input_path, output_path)
+        if !self.format_needs_conversion(format) {
+            if self.resizes_copied() && self.exceeds_limits(data, format) {
+                return self.resize_copied(data, format, output, extras);
+            }
+            if format == SourceFormat::Jpeg && self.settings.rotate_copied_jpegs {
+                let orientation = jpeg_transform::orientation(data);
+                if orientation != Orientation::NoTransforms {
+                    return self.rotate_jpeg(data, orientation, output);
+                }
+            }
+            output.write_all(data)
                 .context("Failed to copy image file")?;
             return Ok(ConversionResult::Copied);
         }
 
-        // Special handling for GIF (check if animated, if so copy as-is)
-        if ext == "gif" {
-            if self.is_animated_gif(input_path)? {
-                fs::copy(input_path, output_path)?;
-                return Ok(ConversionResult::Copied);
-            }
-            // Static GIF, keep as GIF
-            fs::copy(input_path, output_path)?;
-            return Ok(ConversionResult::Copied);
+        if format.is_raw() {
+            return self.process_raw(data, format, output, extras);
         }
 
-        // Special handling for WebP (check if animated)
-        if ext == "webp" {
-            if self.is_animated_webp(input_path)? {
-                fs::copy(input_path, output_path)?;
-                return Ok(ConversionResult::Copied);
-            }
+        // Animated GIFs and WebPs follow the animation policy
+        if let Some(frames) = animation::frame_count(data, ext).filter(|&frames| frames > 1) {
+            return self.process_animated(data, ext, frames, output, extras);
         }
 
-        // Convert other formats to JPEG
-        let format_name = ext.to_uppercase();
-        self.convert_to_jpeg(input_path, output_path, &format_name)?;
-        
-        Ok(ConversionResult::Converted {
-            original_format: format_name,
+        // Convert other formats to the target format
+        let format_name = format.name();
+        let encoded = self.convert(data, ext, output, format_name, extras)?;
+        Ok(encoded.into_result(format_name))
+    }
+
+    /// Encode the job's derivative sizes of a decoded image, each fitted within its
+    /// size and encoded in the target format with the job's other settings. The
+    /// largest is scaled from the image and each smaller one from the size before it.
+    fn make_derivatives(&self, decoded: &Decoded) -> Result<Vec<Derivative>> {
+        // The derivatives are already to size when they're encoded
+        let converter = ImageConverter::with_settings(ConversionSettings {
+            resize: ResizeSettings::default(),
+            ..self.settings
+        });
+        let mut order: Vec<usize> = (0..self.derivatives.len()).collect();
+        order.sort_by_key(|&i| Reverse(self.derivatives[i].max_long_edge));
+
+        let mut made: Vec<Option<Derivative>> = self.derivatives.iter().map(|_| None).collect();
+        let mut previous: Option<DynamicImage> = None;
+        for i in order {
+            let size = &self.derivatives[i];
+            let limits = ResizeSettings {
+                max_long_edge: Some(size.max_long_edge),
+                ..Default::default()
+            };
+            let source = previous.as_ref().unwrap_or(&decoded.img);
+            let img = resize::resized(source, &limits).unwrap_or_else(|| source.clone());
+
+            let mut data = Vec::new();
+            let encoded = converter
+                .encode(img.clone(), decoded.exif.clone(), decoded.icc.clone(), decoded.source, &mut data)
+                .with_context(|| format!("Failed to make the {} derivative", size.name))?;
+            made[i] = Some(Derivative {
+                name: size.name.clone(),
+                data,
+                extension: encoded.extension,
+                dimensions: encoded.dimensions.output,
+            });
+            previous = Some(img);
+        }
+        Ok(made.into_iter().flatten().collect())
+    }
+
+    /// Decode an image of any format the converter reads, upright
+    fn decode_any(&self, name: &Path, data: &[u8]) -> Result<Decoded> {
+        let format = SourceFormat::detect_named(data, name)
+            .or_else(|| SourceFormat::from_path(name))
+            .with_context(|| format!("Unrecognised image format: {}", name.display()))?;
+        if format.is_raw() {
+            Ok(self.decode_raw(data)?.0)
+        } else {
+            self.decode(data, format.extension(), format.name())
+        }
+    }
+
+    /// Whether an image that would be copied is bigger than the resize limits allow,
+    /// judged from its header. Images whose size can't be read are copied.
+    fn exceeds_limits(&self, data: &[u8], format: SourceFormat) -> bool {
+        upright_size(data, format)
+            .is_some_and(|(width, height)| resize::plan(&self.settings.resize, width, height).is_some())
+    }
+
+    /// Re-encode an image that would have been copied, to resize it. It keeps its
+    /// format: JPEGs stay JPEGs and PNGs stay PNGs.
+    fn resize_copied(
+        &self,
+        data: &[u8],
+        format: SourceFormat,
+        output: &mut impl Write,
+        extras: &mut ExtrasMaker,
+    ) -> Result<ConversionResult> {
+        let target_format = match format {
+            SourceFormat::Jpeg => TargetFormat::Jpeg,
+            SourceFormat::Png => TargetFormat::Png,
+            _ => self.settings.target_format,
+        };
+        let converter = ImageConverter::with_settings(ConversionSettings {
+            target_format,
+            ..self.settings
+        });
+        let format_name = format.name();
+        let encoded = converter.convert(data, format.extension(), output, format_name, extras)?;
+        Ok(encoded.into_result(format_name))
+    }
+
+    /// Rotate a JPEG that is otherwise copied. Files the lossless transform can't
+    /// handle (progressive JPEGs, mostly) are copied unchanged rather than re-encoded.
+    fn rotate_jpeg(
+        &self,
+        data: &[u8],
+        orientation: Orientation,
+        output: &mut impl Write,
+    ) -> Result<ConversionResult> {
+        let (rotated, trimmed, error) = match jpeg_transform::transform(data, orientation) {
+            Ok(transformed) => (transformed.data, transformed.trimmed, None),
+            Err(e) => (data.to_vec(), false, Some(format!("{:#}", e))),
+        };
+        output.write_all(&rotated).context("Failed to write rotated JPEG")?;
+
+        Ok(ConversionResult::Reoriented {
+            orientation: orientation.to_exif(),
+            trimmed,
+            error,
+        })
+    }
+
+    fn process_animated(
+        &self,
+        data: &[u8],
+        ext: &str,
+        frames: usize,
+        output: &mut impl Write,
+        extras: &mut ExtrasMaker,
+    ) -> Result<ConversionResult> {
+        let original_format = ext.to_uppercase();
+        let to_webp = matches!(
+            self.settings.target_format,
+            TargetFormat::WebpLossless | TargetFormat::WebpLossy
+        );
+
+        let (handling, extension) = match self.settings.animation {
+            AnimationPolicy::FirstFrame => {
+                // Decoders return the first frame of an animation
+                let encoded = self.convert(data, ext, output, &original_format, extras)?;
+                (AnimationPolicy::FirstFrame, encoded.extension)
+            }
+            // Re-encoding a GIF as a GIF gains nothing, so it is kept
+            AnimationPolicy::ConvertAnimated if to_webp || ext != "gif" => {
+                let frames = decode_frames(data, ext)?;
+                if to_webp {
+                    self.encode_animated_webp(&frames, output)?;
+                    (AnimationPolicy::ConvertAnimated, "webp")
+                } else {
+                    encode_animated_gif(frames, output)?;
+                    (AnimationPolicy::ConvertAnimated, "gif")
+                }
+            }
+            AnimationPolicy::Keep | AnimationPolicy::ConvertAnimated => {
+                output.write_all(data)
+                    .context("Failed to copy animated image")?;
+                (AnimationPolicy::Keep, ext)
+            }
+        };
+
+        Ok(ConversionResult::Animated {
+            original_format,
+            frames,
+            handling,
+            extension: extension.to_string(),
         })
     }
 
-    fn is_animated_gif(&self, path: &Path) -> Result<bool> {
-        // For simplicity, we'll keep all GIFs as-is
-        // A more sophisticated implementation would parse GIF headers
-        Ok(true)
+    /// Convert a RAW file from its embedded preview or by developing it, with the
+    /// EXIF tags of the RAW file itself (previews usually carry none)
+    fn process_raw(
+        &self,
+        data: &[u8],
+        format: SourceFormat,
+        output: &mut impl Write,
+        extras: &mut ExtrasMaker,
+    ) -> Result<ConversionResult> {
+        let (decoded, method) = self.decode_raw(data)?;
+        extras.take(&decoded)?;
+        let encoded = self.encode(decoded.img, decoded.exif, decoded.icc, decoded.source, output)?;
+
+        Ok(ConversionResult::Raw {
+            original_format: format.name().to_string(),
+            method,
+            metadata_preserved: encoded.exif == ExifOutcome::Carried,
+            dimensions: encoded.dimensions,
+        })
     }
 
-    fn is_animated_webp(&self, path: &Path) -> Result<bool> {
-        // Try to detect animated WebP
-        let file = fs::File::open(path)?;
-        let reader = BufReader::new(file);
-        
-        // Use image crate to check
-        match image::load(reader, ImageFormat::WebP) {
-            Ok(_) => {
-                // For now, assume single-frame WebP
-                // A full implementation would check WebP headers for animation flag
-                Ok(false)
+    /// Decode a RAW file's embedded preview or develop its sensor data, following the
+    /// RAW policy. Returns the method actually used.
+    fn decode_raw(&self, data: &[u8]) -> Result<(Decoded, RawPolicy)> {
+        let preview = match self.settings.raw {
+            RawPolicy::EmbeddedPreview => raw::embedded_preview(data),
+            RawPolicy::Develop | RawPolicy::Ignore => None,
+        };
+
+        // Files without a usable preview are developed instead
+        let (mut img, method) = match preview {
+            Some(jpeg) => {
+                let img = image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg)
+                    .context("Failed to decode the embedded preview")?;
+                (img, RawPolicy::EmbeddedPreview)
             }
-            Err(_) => Ok(false),
+            None => (DynamicImage::ImageRgb8(raw::develop(data)?), RawPolicy::Develop),
+        };
+
+        // Neither the preview nor the sensor data is stored rotated; the RAW file's
+        // own Orientation tag says how to show both
+        if let Some(orientation) = Orientation::from_exif_chunk(data) {
+            img.apply_orientation(orientation);
         }
+
+        let decoded = Decoded {
+            exif: MetadataHandler::read_raw_exif(data),
+            icc: None,
+            source: SourceDepth::of(&img),
+            img,
+        };
+        Ok((decoded, method))
     }
 
-    fn convert_to_jpeg(
+    fn encode_animated_webp(&self, frames: &[Frame], writer: &mut impl Write) -> Result<()> {
+        let first = frames.first().context("Animation has no frames")?;
+        let (width, height) = first.buffer().dimensions();
+
+        let mut config = webp::WebPConfig::new()
+            .map_err(|_| anyhow::anyhow!("Failed to set up the WebP encoder"))?;
+        if self.settings.target_format == TargetFormat::WebpLossless {
+            config.lossless = 1;
+        } else {
+            config.quality = f32::from(self.quality());
+        }
+
+        // Frames are placed by timestamp, so each one starts where the last one's delay ends
+        let mut encoder = webp::AnimEncoder::new(width, height, &config);
+        let mut timestamp_ms = 0;
+        for frame in frames {
+            encoder.add_frame(webp::AnimFrame::from_rgba(
+                frame.buffer().as_raw(),
+                width,
+                height,
+                timestamp_ms,
+            ));
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            timestamp_ms += i32::try_from(numerator / denominator.max(1)).unwrap_or(i32::MAX);
+        }
+
+        let encoded = encoder
+            .try_encode()
+            .map_err(|e| anyhow::anyhow!("Failed to encode animated WebP: {:?}", e))?;
+        writer.write_all(&encoded).context("Failed to write WebP")?;
+        Ok(())
+    }
+
+    /// Returns what was carried over to the output besides the pixels
+    fn convert(
         &self,
-        input_path: &Path,
-        output_path: &Path,
+        data: &[u8],
+        ext: &str,
+        writer: &mut impl Write,
         format_name: &str,
-    ) -> Result<()> {
+        extras: &mut ExtrasMaker,
+    ) -> Result<Encoded> {
+        let decoded = self.decode(data, ext, format_name)?;
+        extras.take(&decoded)?;
+        self.encode(decoded.img, decoded.exif, decoded.icc, decoded.source, writer)
+    }
+
+    /// Decode an image, turned upright, with its EXIF block and ICC profile
+    fn decode(&self, data: &[u8], ext: &str, format_name: &str) -> Result<Decoded> {
         // Load the image using appropriate decoder
-        let img = if format_name == "HEIC" || format_name == "HEIF" {
-            self.load_heic(input_path)?
+        // HEIC and AVIF share a container and are both decoded by libheif, which
+        // already applies the container's rotation and mirroring
+        let (img, icc, source) = if is_heif(format_name) {
+            let info = self.inspect_heif(data);
+            let mut img = depth::expand_to_16_bits(self.load_heif(data, format_name)?, info.bits);
+            let mut icc = info.icc;
+            // PQ and HLG images come out as SDR sRGB, which their profile (if any)
+            // no longer describes
+            if let Some(format @ (HdrFormat::Pq | HdrFormat::Hlg)) = info.hdr {
+                img = hdr::tone_map(&img, format, info.primaries);
+                icc = None;
+            }
+            let source = SourceDepth {
+                bits: info.bits,
+                hdr: info.hdr,
+            };
+            (img, icc, source)
         } else {
-            // Use image crate for other formats
-            image::open(input_path)
-                .with_context(|| format!(
-                    "Failed to open {} image at {}",
-                    format_name,
-                    input_path.display()
-                ))?
+            // Use image crate for other formats, turning the pixels the way the
+            // EXIF orientation says
+            let format = ImageFormat::from_extension(ext)
+                .with_context(|| format!("Unsupported image type: {}", format_name))?;
+            let open = || -> image::ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+                let mut decoder = ImageReader::with_format(Cursor::new(data), format).into_decoder()?;
+                let orientation = decoder.orientation()?;
+                // A profile that can't be read is treated like a missing one
+                let icc = decoder.icc_profile().ok().flatten();
+                let mut img = DynamicImage::from_decoder(decoder)?;
+                img.apply_orientation(orientation);
+                Ok((img, icc))
+            };
+            let (img, icc) = open().with_context(|| format!("Failed to open {} image", format_name))?;
+            let source = SourceDepth::of(&img);
+            (img, icc, source)
         };
 
-        // If image has transparency, composite onto white background
-        let img = self.composite_on_white(img);
-
         // Try to preserve EXIF metadata
-        let exif_data = self.extract_exif(input_path);
-
-        // Encode as JPEG
-        let mut output_file = fs::File::create(output_path)
-            .context("Failed to create output JPEG file")?;
-        
-        let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(
-            &mut output_file,
-            self.jpeg_quality,
-        );
-        
-        img.write_with_encoder(encoder)
-            .context("Failed to encode JPEG")?;
+        let exif = self.extract_exif(data, format_name);
+
+        Ok(Decoded { img, exif, icc, source })
+    }
+
+    /// Encode a decoded, upright image in the target format, with its ICC profile
+    /// embedded or applied according to the colour profile policy. Sources with more
+    /// than 8 bits per channel are dithered down to 8 bits, or written as 16-bit PNG
+    /// if the target is PNG or the job asks for it. Transparent images the target
+    /// can't hold follow the transparency policy. Images are first scaled to the
+    /// job's resize limits.
+    fn encode(
+        &self,
+        img: DynamicImage,
+        exif_data: Option<Vec<u8>>,
+        icc: Option<Vec<u8>>,
+        source: SourceDepth,
+        writer: &mut impl Write,
+    ) -> Result<Encoded> {
+        // The pixels have already been turned, so viewers mustn't turn them again
+        let had_exif = exif_data.is_some();
+        let exif_data = exif_data.and_then(|mut exif| {
+            if !MetadataHandler::fits_jpeg(&exif) {
+                eprintln!("Warning: Dropping {} byte EXIF block, too large to carry over", exif.len());
+                return None;
+            }
+            let _ = Orientation::remove_from_exif_chunk(&mut exif);
+            Some(exif)
+        });
+
+        let original = img.dimensions();
+        let img = resize::resize(img, &self.settings.resize);
+        let dimensions = Dimensions {
+            original,
+            output: img.dimensions(),
+        };
+
+        let (img, icc, mut color_profile) = match icc {
+            Some(icc) => self.manage_color(img, icc),
+            None => (img, None, None),
+        };
+
+        let sixteen_bit = source.bits > 8
+            && (self.settings.high_bit_depth_png || self.settings.target_format == TargetFormat::Png);
+        let (target_format, img, dithered) = if sixteen_bit {
+            (TargetFormat::Png, depth::to_16_bits(img), false)
+        } else {
+            let dithered = depth::bits_per_channel(&img) > 8;
+            (self.settings.target_format, depth::dither_to_8_bits(img), dithered)
+        };
+
+        // An alpha channel that is opaque everywhere carries nothing
+        let transparent = has_transparency(&img);
+        let img = if transparent { img } else { drop_alpha(img) };
+
+        let (target_format, img, transparency) = if !transparent {
+            (target_format, img, None)
+        } else if target_format.supports_alpha() {
+            (target_format, img, Some(TransparencyHandling::Kept))
+        } else {
+            match self.settings.transparency {
+                TransparencyPolicy::Composite { background } => {
+                    let img = composite(&img, background);
+                    (target_format, img, Some(TransparencyHandling::Composited))
+                }
+                TransparencyPolicy::KeepAlpha { format } => {
+                    (format.target_format(), img, Some(TransparencyHandling::ChangedFormat))
+                }
+                TransparencyPolicy::Fail => {
+                    anyhow::bail!("Image has transparent pixels, which the target format can't store")
+                }
+            }
+        };
+
+        let written = match target_format {
+            TargetFormat::Jpeg => self.encode_jpeg(&img, writer, exif_data, icc)?,
+            TargetFormat::Png => {
+                encode_with(PngEncoder::new(writer), &img, exif_data, icc, "PNG")?
+            }
+            TargetFormat::WebpLossless => {
+                encode_with(WebPEncoder::new_lossless(writer), &img, exif_data, icc, "WebP")?
+            }
+            TargetFormat::WebpLossy => self.encode_lossy_webp(&img, writer, exif_data, icc)?,
+            TargetFormat::Avif => encode_with(
+                AvifEncoder::new_with_speed_quality(writer, AVIF_SPEED, self.quality()),
+                &img,
+                exif_data,
+                icc,
+                "AVIF",
+            )?,
+        };
+
+        if let Some(profile) = color_profile.as_mut() {
+            if profile.handling == ProfileHandling::Embedded && !written.icc {
+                profile.handling = ProfileHandling::Dropped;
+            }
+        }
+
+        let bit_depth = (source.bits > 8 || source.hdr.is_some()).then_some(DepthConversion {
+            source_bits: source.bits,
+            hdr: source.hdr,
+            output_bits: if sixteen_bit { 16 } else { 8 },
+            dithered,
+        });
+
+        Ok(Encoded {
+            exif: match (had_exif, written.exif) {
+                (_, true) => ExifOutcome::Carried,
+                (false, false) => ExifOutcome::None,
+                (true, false) => ExifOutcome::Dropped,
+            },
+            color_profile,
+            bit_depth,
+            transparency,
+            dimensions,
+            extension: target_format.extension(),
+        })
+    }
+
+    /// Apply the colour profile policy. Returns the image to encode, the profile to
+    /// embed in it and what was done. Images that can't be converted to sRGB keep
+    /// their profile instead, and AVIF output, which can't carry one, is always
+    /// converted.
+    fn manage_color(
+        &self,
+        img: DynamicImage,
+        icc: Vec<u8>,
+    ) -> (DynamicImage, Option<Vec<u8>>, Option<SourceProfile>) {
+        let name = color::profile_name(&icc);
+        let can_embed = self.settings.target_format != TargetFormat::Avif;
+
+        if self.settings.color_profile == ColorProfilePolicy::ConvertToSrgb || !can_embed {
+            match color::to_srgb(&img, &icc) {
+                Ok(converted) => {
+                    let handling = ProfileHandling::ConvertedToSrgb;
+                    return (converted, None, Some(SourceProfile { name, handling }));
+                }
+                Err(e) => eprintln!("Warning: Failed to convert to sRGB: {:#}", e),
+            }
+        }
+
+        if can_embed {
+            let handling = ProfileHandling::Embedded;
+            (img, Some(icc), Some(SourceProfile { name, handling }))
+        } else {
+            let handling = ProfileHandling::Dropped;
+            (img, None, Some(SourceProfile { name, handling }))
+        }
+    }
+
+    fn quality(&self) -> u8 {
+        self.settings.quality.clamp(1, 100)
+    }
 
-        // Write EXIF data if we extracted any
+    fn encode_jpeg(
+        &self,
+        img: &DynamicImage,
+        writer: &mut impl Write,
+        exif_data: Option<Vec<u8>>,
+        icc: Option<Vec<u8>>,
+    ) -> Result<Written> {
+        let (width, height) = img.dimensions();
+        let (Ok(jpeg_width), Ok(jpeg_height)) = (u16::try_from(width), u16::try_from(height)) else {
+            anyhow::bail!("{}x{} image is too large for JPEG (max 65535 pixels per side)", width, height);
+        };
+
+        let mut encoder = jpeg_encoder::Encoder::new(writer, self.quality());
+        encoder.set_sampling_factor(match self.settings.chroma_subsampling {
+            ChromaSubsampling::Yuv444 => SamplingFactor::F_1_1,
+            ChromaSubsampling::Yuv422 => SamplingFactor::F_2_1,
+            ChromaSubsampling::Yuv420 => SamplingFactor::F_2_2,
+        });
+
+        // The encoder writes the EXIF block as an APP1 segment right after the JFIF header
+        let mut metadata_preserved = false;
         if let Some(exif) = exif_data {
-            if let Err(e) = self.write_exif(output_path, exif) {
-                eprintln!("Warning: Failed to write EXIF metadata: {}", e);
+            match encoder.add_exif_metadata(&exif) {
+                Ok(()) => metadata_preserved = true,
+                Err(e) => eprintln!("Warning: Failed to write EXIF metadata: {}", e),
             }
         }
 
-        Ok(())
+        // Profiles too large for one APP2 segment are split across several
+        let mut icc_written = false;
+        if let Some(icc) = icc {
+            match encoder.add_icc_profile(&icc) {
+                Ok(()) => icc_written = true,
+                Err(e) => eprintln!("Warning: Failed to write ICC profile: {}", e),
+            }
+        }
+
+        // Greyscale sources stay single-channel
+        let result = if img.color().has_color() {
+            let rgb = img.to_rgb8();
+            encoder.encode(rgb.as_raw(), jpeg_width, jpeg_height, jpeg_encoder::ColorType::Rgb)
+        } else {
+            let luma = img.to_luma8();
+            encoder.encode(luma.as_raw(), jpeg_width, jpeg_height, jpeg_encoder::ColorType::Luma)
+        };
+        result.context("Failed to encode JPEG")?;
+
+        Ok(Written {
+            exif: metadata_preserved,
+            icc: icc_written,
+        })
     }
 
-    fn load_heic(&self, path: &Path) -> Result<DynamicImage> {
+    fn encode_lossy_webp(
+        &self,
+        img: &DynamicImage,
+        writer: &mut impl Write,
+        exif_data: Option<Vec<u8>>,
+        icc: Option<Vec<u8>>,
+    ) -> Result<Written> {
+        let (width, height) = img.dimensions();
+        let quality = f32::from(self.quality());
+
+        let encoded = if img.color().has_alpha() {
+            let rgba = img.to_rgba8();
+            webp::Encoder::from_rgba(rgba.as_raw(), width, height).encode_simple(false, quality)
+        } else {
+            let rgb = img.to_rgb8();
+            webp::Encoder::from_rgb(rgb.as_raw(), width, height).encode_simple(false, quality)
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to encode WebP: {:?}", e))?;
+
+        // libwebp's simple API can't embed metadata, so the EXIF and ICCP chunks are
+        // added afterwards
+        let with_metadata = if exif_data.is_some() || icc.is_some() {
+            MetadataHandler::add_webp_metadata(&encoded, exif_data.as_deref(), icc.as_deref(), width, height)
+        } else {
+            None
+        };
+        let written = Written {
+            exif: with_metadata.is_some() && exif_data.is_some(),
+            icc: with_metadata.is_some() && icc.is_some(),
+        };
+        writer
+            .write_all(with_metadata.as_deref().unwrap_or(&encoded))
+            .context("Failed to write WebP")?;
+
+        Ok(written)
+    }
+
+    /// Decode a HEIC or AVIF image with libheif. Sources with more than 8 bits per
+    /// channel (10-bit and HDR files) come back as 16-bit images holding their
+    /// original sample values, which [`depth::expand_to_16_bits`] stretches.
+    fn load_heif(&self, data: &[u8], format_name: &str) -> Result<DynamicImage> {
         // Register HEIC decoding hooks
         libheif_rs::LibHeif::new();
-        
-        // Load using image crate with libheif-rs integration
-        image::open(path)
-            .with_context(|| format!(
-                "Failed to decode HEIC image at {}",
-                path.display()
-            ))
-    }
-
-    fn composite_on_white(&self, img: DynamicImage) -> DynamicImage {
-        // Check if image has alpha channel
-        if img.color().has_alpha() {
-            let (width, height) = img.dimensions();
-            let mut white_bg = image::RgbaImage::from_pixel(
-                width,
-                height,
-                image::Rgba([255, 255, 255, 255]),
-            );
 
-            // Composite the image onto white background
-            image::imageops::overlay(&mut white_bg, &img.to_rgba8(), 0, 0);
-            DynamicImage::ImageRgba8(white_bg).to_rgb8().into()
+        // The libheif-rs hooks recognise the container's brand ahead of the `image`
+        // crate's own AVIF support, which can only encode
+        ImageReader::new(Cursor::new(data))
+            .with_guessed_format()?
+            .decode()
+            .with_context(|| format!("Failed to decode {} image", format_name))
+    }
+
+    fn extract_exif(&self, data: &[u8], format_name: &str) -> Option<Vec<u8>> {
+        if !MetadataHandler::format_has_exif(format_name) {
+            return None;
+        }
+
+        if is_heif(format_name) {
+            self.extract_heif_exif(data)
         } else {
-            img
+            MetadataHandler::read_exif_from_bytes(data)
         }
     }
 
-    fn extract_exif(&self, path: &Path) -> Option<Vec<u8>> {
-        // Try to extract EXIF data using little_exif
-        match little_exif::metadata::Metadata::new_from_path(path) {
-            Ok(metadata) => {
-                // Serialize the metadata to bytes
-                // For now, we'll just note that we tried
-                None
+    fn extract_heif_exif(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let context = libheif_rs::HeifContext::read_from_bytes(data).ok()?;
+        let handle = context.primary_image_handle().ok()?;
+
+        let mut exif_ids = [0; 1];
+        if handle.metadata_block_ids(&mut exif_ids, b"Exif") == 0 {
+            return None;
+        }
+
+        let block = handle.metadata(exif_ids[0]).ok()?;
+        MetadataHandler::exif_from_heif_block(&block)
+    }
+
+    /// Bit depth, HDR encoding and ICC profile of a HEIC or AVIF image. Images
+    /// libheif can't read are taken to be plain 8-bit ones; decoding reports the error.
+    fn inspect_heif(&self, data: &[u8]) -> HeifInfo {
+        use libheif_rs::{AuxiliaryImagesFilter, ColorPrimaries, TransferCharacteristics};
+
+        let mut info = HeifInfo {
+            bits: 8,
+            hdr: None,
+            primaries: Primaries::Bt709,
+            icc: None,
+        };
+        let Ok(context) = libheif_rs::HeifContext::read_from_bytes(data) else {
+            return info;
+        };
+        let Ok(handle) = context.primary_image_handle() else {
+            return info;
+        };
+
+        info.bits = handle.luma_bits_per_pixel();
+        // An ICC profile, if there is one rather than just colour primaries (nclx)
+        info.icc = handle.color_profile_raw().map(|profile| profile.data);
+        if let Some(nclx) = handle.color_profile_nclx() {
+            info.hdr = match nclx.transfer_characteristics() {
+                TransferCharacteristics::ITU_R_BT_2100_0_PQ => Some(HdrFormat::Pq),
+                TransferCharacteristics::ITU_R_BT_2100_0_HLG => Some(HdrFormat::Hlg),
+                _ => None,
+            };
+            info.primaries = match nclx.color_primaries() {
+                ColorPrimaries::ITU_R_BT_2020_2_and_2100_0 => Primaries::Bt2020,
+                ColorPrimaries::SMPTE_RP_431_2 | ColorPrimaries::SMPTE_EG_432_1 => Primaries::DisplayP3,
+                _ => Primaries::Bt709,
+            };
+        }
+        if info.hdr.is_none() {
+            let filter = AuxiliaryImagesFilter::new().omit_alpha().omit_depth();
+            let has_gain_map = handle
+                .auxiliary_images(filter)
+                .iter()
+                .any(|aux| aux.auxiliary_type().is_ok_and(|kind| kind == hdr::APPLE_GAIN_MAP));
+            if has_gain_map {
+                info.hdr = Some(HdrFormat::AppleGainMap);
             }
-            Err(_) => None,
         }
+        info
+    }
+}
+
+/// Whether an image has an alpha channel with any pixel less than fully opaque
+fn has_transparency(img: &DynamicImage) -> bool {
+    match img {
+        DynamicImage::ImageLumaA8(buf) => buf.pixels().any(|p| p.0[1] < u8::MAX),
+        DynamicImage::ImageRgba8(buf) => buf.pixels().any(|p| p.0[3] < u8::MAX),
+        DynamicImage::ImageLumaA16(buf) => buf.pixels().any(|p| p.0[1] < u16::MAX),
+        DynamicImage::ImageRgba16(buf) => buf.pixels().any(|p| p.0[3] < u16::MAX),
+        DynamicImage::ImageRgba32F(buf) => buf.pixels().any(|p| p.0[3] < 1.0),
+        _ => false,
     }
+}
 
-    fn write_exif(&self, path: &Path, exif_data: Vec<u8>) -> Result<()> {
-        // Write EXIF data to the JPEG file
-        // This is a placeholder - full implementation would use exiftool or similar
-        Ok(())
+/// The same image without its alpha channel, at the same bit depth
+fn drop_alpha(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLumaA8(_) => DynamicImage::ImageLuma8(img.to_luma8()),
+        DynamicImage::ImageRgba8(_) => DynamicImage::ImageRgb8(img.to_rgb8()),
+        DynamicImage::ImageLumaA16(_) => DynamicImage::ImageLuma16(img.to_luma16()),
+        DynamicImage::ImageRgba16(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgb32F(img.to_rgb32f()),
+        other => other,
+    }
+}
+
+/// Flatten a transparent image onto a solid background colour
+fn composite(img: &DynamicImage, background: [u8; 3]) -> DynamicImage {
+    let [r, g, b] = background;
+    let (width, height) = img.dimensions();
+    let mut canvas = image::RgbaImage::from_pixel(width, height, image::Rgba([r, g, b, 255]));
+    image::imageops::overlay(&mut canvas, &img.to_rgba8(), 0, 0);
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8())
+}
+
+/// Width and height an image will have once turned upright, read from its header
+pub fn upright_size(data: &[u8], format: SourceFormat) -> Option<(u32, u32)> {
+    if is_heif(format.name()) {
+        // libheif reports the size with the container's rotation applied
+        let context = libheif_rs::HeifContext::read_from_bytes(data).ok()?;
+        let handle = context.primary_image_handle().ok()?;
+        return Some((handle.width(), handle.height()));
+    }
+    let image_format = ImageFormat::from_extension(format.extension())?;
+    let mut decoder = ImageReader::with_format(Cursor::new(data), image_format).into_decoder().ok()?;
+    let (width, height) = decoder.dimensions();
+    let turned = matches!(
+        decoder.orientation().ok()?,
+        Orientation::Rotate90 | Orientation::Rotate270 | Orientation::Rotate90FlipH | Orientation::Rotate270FlipH
+    );
+    Some(if turned { (height, width) } else { (width, height) })
+}
+
+/// Whether a format is read through libheif
+fn is_heif(format_name: &str) -> bool {
+    matches!(format_name, "HEIC" | "AVIF")
+}
+
+/// Every frame of an animated GIF or WebP, composited to the full canvas size
+fn decode_frames(data: &[u8], ext: &str) -> Result<Vec<Frame>> {
+    let frames = if ext == "gif" {
+        GifDecoder::new(Cursor::new(data))?.into_frames()
+    } else {
+        WebPDecoder::new(Cursor::new(data))?.into_frames()
+    };
+    frames
+        .collect_frames()
+        .with_context(|| format!("Failed to decode animated {}", ext.to_uppercase()))
+}
+
+fn encode_animated_gif(frames: Vec<Frame>, writer: &mut impl Write) -> Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder
+        .encode_frames(frames)
+        .context("Failed to encode animated GIF")
+}
+
+/// Encode through one of the `image` crate encoders, attaching EXIF and the ICC
+/// profile when the encoder supports them
+fn encode_with(
+    mut encoder: impl ImageEncoder,
+    img: &DynamicImage,
+    exif_data: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+    format_name: &str,
+) -> Result<Written> {
+    let mut metadata_preserved = false;
+    if let Some(exif) = exif_data {
+        match encoder.set_exif_metadata(exif) {
+            Ok(()) => metadata_preserved = true,
+            Err(e) => eprintln!("Warning: Failed to write EXIF metadata: {}", e),
+        }
+    }
+
+    let mut icc_written = false;
+    if let Some(icc) = icc {
+        match encoder.set_icc_profile(icc) {
+            Ok(()) => icc_written = true,
+            Err(e) => eprintln!("Warning: Failed to write ICC profile: {}", e),
+        }
     }
+
+    img.write_with_encoder(encoder)
+        .with_context(|| format!("Failed to encode {}", format_name))?;
+
+    Ok(Written {
+        exif: metadata_preserved,
+        icc: icc_written,
+    })
 }
 
 impl Default for ImageConverter {
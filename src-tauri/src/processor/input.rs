@@ -0,0 +1,114 @@
+use crate::utils::format::SourceFormat;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a job reads its files from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum JobInput {
+    /// `input_path` is a zip file
+    #[default]
+    Zip,
+    /// `input_path` is a folder, walked recursively
+    Directory,
+    /// Individual files; `input_path` is the folder holding the first of them
+    Files { paths: Vec<String> },
+}
+
+impl JobInput {
+    /// Folders become directory inputs; anything else is read as a zip
+    pub fn for_path(path: &Path) -> Self {
+        if path.is_dir() {
+            JobInput::Directory
+        } else {
+            JobInput::Zip
+        }
+    }
+}
+
+/// Dropped or selected paths split into jobs
+#[derive(Debug, Default, PartialEq)]
+pub struct GroupedPaths {
+    /// `(input_path, input)` pairs in the order the paths were given
+    pub jobs: Vec<(String, JobInput)>,
+    /// Loose files that aren't images by their extension, left out of every job
+    pub ignored: Vec<String>,
+}
+
+/// Split dropped or selected paths into jobs: one per zip, one per folder, and
+/// one for all the loose image files together. Other loose files are ignored.
+pub fn group_paths(paths: Vec<String>) -> GroupedPaths {
+    let mut jobs = Vec::new();
+    let mut loose_files = Vec::new();
+    let mut ignored = Vec::new();
+
+    for path in paths {
+        let input = Path::new(&path);
+        if input.is_dir() {
+            jobs.push((path, JobInput::Directory));
+        } else if is_zip(input) {
+            jobs.push((path, JobInput::Zip));
+        } else if SourceFormat::from_path(input).is_some() {
+            loose_files.push(path);
+        } else {
+            ignored.push(path);
+        }
+    }
+
+    if let Some(first) = loose_files.first() {
+        let folder = Path::new(first)
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+        jobs.push((folder, JobInput::Files { paths: loose_files }));
+    }
+
+    GroupedPaths { jobs, ignored }
+}
+
+/// Every file under `root` as `(relative_path, full_path)`, sorted so output
+/// doesn't depend on the order the file system returns entries in. Symlinks to
+/// files are listed; symlinks to folders are skipped, since one pointing back up
+/// the tree would never end.
+pub fn list_directory(root: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative_dir) = pending.pop() {
+        let dir = root.join(&relative_dir);
+        let entries = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read folder {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let relative_path = relative_dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push(relative_path);
+            } else if file_type.is_file() || entry.path().is_file() {
+                files.push((relative_path, entry.path()));
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files
+        .into_iter()
+        .map(|(relative, full)| (zip_style_path(&relative), full))
+        .collect())
+}
+
+/// Relative paths use `/` like zip entry names, whatever the platform
+fn zip_style_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
@@ -0,0 +1,87 @@
+use super::JobInfo;
+use crate::report::ProcessingReport;
+use crate::utils::disk;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// On-disk copy of the queue and finished-job history. Jobs are saved without
+/// their reports, which are kept in files of their own.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StoredJobs {
+    pub queue: Vec<JobInfo>,
+    pub history: Vec<JobInfo>,
+}
+
+/// Saves jobs as a JSON file so the queue survives restarts, and each finished
+/// job's report as `reports/<job id>.json` beside it
+pub struct JobStore {
+    path: PathBuf,
+}
+
+impl JobStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read saved jobs, or nothing if the app has never saved any. An unreadable
+    /// file is set aside as `jobs.json.corrupt` and the app starts with no jobs.
+    pub fn load(&self) -> StoredJobs {
+        disk::load_json(&self.path)
+    }
+
+    pub fn save(&self, jobs: &StoredJobs) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("Failed to create app data directory")?;
+        }
+        write_replacing(&self.path, &serde_json::to_string_pretty(jobs)?)
+    }
+
+    fn report_path(&self, job_id: &str) -> PathBuf {
+        self.path.with_file_name("reports").join(format!("{}.json", job_id))
+    }
+
+    /// Save a finished job's report. Written once, when the job finishes.
+    pub fn save_report(&self, job_id: &str, report: &ProcessingReport) -> Result<()> {
+        let path = self.report_path(job_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create reports directory")?;
+        }
+        write_replacing(&path, &serde_json::to_string(report)?)
+    }
+
+    /// A job's saved report, or `None` if it has none
+    pub fn load_report(&self, job_id: &str) -> Result<Option<ProcessingReport>> {
+        let path = self.report_path(job_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&json)
+            .map(Some)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Delete a job's saved report, if it has one
+    pub fn remove_report(&self, job_id: &str) {
+        let _ = fs::remove_file(self.report_path(job_id));
+    }
+}
+
+/// Write to a temp file first so a crash mid-write can't corrupt what was saved
+fn write_replacing(path: &Path, contents: &str) -> Result<()> {
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, contents)
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace {}", path.display()))?;
+    Ok(())
+}
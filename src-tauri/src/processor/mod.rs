@@ -1,11 +1,23 @@
 pub mod queue;
 pub mod zip_handler;
 pub mod image_converter;
+pub mod input;
+pub mod job_store;
+pub mod options;
+pub mod progress;
+pub mod rules;
 pub mod temp_manager;
+pub mod worker_pool;
 mod state_impl;
 
+use crate::report::{ProcessingReport, ReportStats};
 use anyhow::Result;
+use chrono::Utc;
+use input::JobInput;
+use job_store::{JobStore, StoredJobs};
+use options::JobOptions;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, Ordering};
 use tauri::{AppHandle, Emitter};
@@ -16,10 +28,101 @@ use uuid::Uuid;
 pub struct JobInfo {
     pub id: String,
     pub input_path: String,
+    #[serde(default)]
+    pub input: JobInput,
     pub status: JobStatus,
     pub progress: Option<ProgressInfo>,
     pub output_path: Option<String>,
     pub error: Option<String>,
+    pub options: JobOptions,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    /// Report of a successful run, while the job is in the queue. Finished jobs'
+    /// reports are saved by the job store and loaded with [`ProcessorState::job_report`].
+    #[serde(default)]
+    pub report: Option<ProcessingReport>,
+    /// Counts from the report of a successful run, kept with the job when it's saved
+    #[serde(default)]
+    pub summary: Option<JobSummary>,
+}
+
+impl JobInfo {
+    /// A job for a zip file or a folder, depending on what `input_path` is
+    pub fn new(input_path: String, options: JobOptions) -> Self {
+        let input = JobInput::for_path(Path::new(&input_path));
+        Self::with_input(input_path, input, options)
+    }
+
+    pub fn with_input(input_path: String, input: JobInput, options: JobOptions) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            input_path,
+            input,
+            status: JobStatus::Pending,
+            progress: None,
+            output_path: None,
+            error: None,
+            options,
+            created_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            report: None,
+            summary: None,
+        }
+    }
+
+    /// A copy without the report, for saving
+    fn without_report(&self) -> JobInfo {
+        JobInfo {
+            id: self.id.clone(),
+            input_path: self.input_path.clone(),
+            input: self.input.clone(),
+            status: self.status.clone(),
+            progress: self.progress.clone(),
+            output_path: self.output_path.clone(),
+            error: self.error.clone(),
+            options: self.options.clone(),
+            created_at: self.created_at.clone(),
+            finished_at: self.finished_at.clone(),
+            report: None,
+            summary: self.summary.clone(),
+        }
+    }
+}
+
+/// What a successful job's report adds up to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSummary {
+    pub stats: ReportStats,
+    /// Problems that didn't stop the job, see [`ProcessingReport::warning_count`]
+    pub warning_count: usize,
+}
+
+impl JobSummary {
+    pub fn of(report: &ProcessingReport) -> Self {
+        Self {
+            stats: report.stats.clone(),
+            warning_count: report.warning_count(),
+        }
+    }
+}
+
+/// Jobs made from dropped or selected paths, and the paths left out of them
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Enqueued {
+    pub jobs: Vec<JobInfo>,
+    /// Loose files that aren't images
+    pub ignored: Vec<String>,
+}
+
+/// What a successfully processed job produced
+#[derive(Debug, Clone)]
+pub struct JobOutput {
+    pub output_path: String,
+    pub report: ProcessingReport,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -30,6 +133,11 @@ pub enum JobStatus {
     Success,
     Failed,
     Cancelled,
+    /// The output file already existed and the collision policy said to leave it
+    Skipped,
+    /// Finished, but some images couldn't be converted; they're listed in the report
+    #[serde(rename = "completedWithWarnings")]
+    CompletedWithWarnings,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -58,58 +166,155 @@ pub struct ProcessingStats {
     pub files_skipped: usize,
 }
 
+/// Finished jobs kept in the history, oldest dropped first
+const MAX_HISTORY: usize = 500;
+
 pub struct ProcessorState {
     pub jobs: Arc<Mutex<Vec<JobInfo>>>,
+    pub history: Arc<Mutex<Vec<JobInfo>>>,
     pub cancel_flag: Arc<AtomicBool>,
     pub processing: Arc<AtomicBool>,
+    /// Where jobs are saved; `None` keeps everything in memory
+    pub store: Option<Arc<Mutex<JobStore>>>,
 }
 
 impl ProcessorState {
     pub fn new() -> Self {
         Self {
             jobs: Arc::new(Mutex::new(Vec::new())),
+            history: Arc::new(Mutex::new(Vec::new())),
             cancel_flag: Arc::new(AtomicBool::new(false)),
             processing: Arc::new(AtomicBool::new(false)),
+            store: None,
         }
     }
 
-    pub fn add_jobs(&self, paths: Vec<String>) -> Result<Vec<JobInfo>> {
+    /// Restore the queue and history saved in `store`. Jobs that were still
+    /// processing when the app quit are put back in the queue to run again.
+    pub fn with_store(store: JobStore) -> Self {
+        let StoredJobs { mut queue, mut history } = store.load();
+        for job in queue.iter_mut() {
+            if job.status == JobStatus::Processing {
+                job.status = JobStatus::Pending;
+                job.progress = None;
+            }
+        }
+        // Older saves kept reports inline; move them to their own files
+        for job in queue.iter_mut().chain(history.iter_mut()) {
+            if let Some(report) = job.report.take() {
+                job.summary.get_or_insert_with(|| JobSummary::of(&report));
+                if let Err(e) = store.save_report(&job.id, &report) {
+                    eprintln!("Failed to save report of job {}: {:#}", job.id, e);
+                }
+            }
+        }
+
+        let state = Self {
+            jobs: Arc::new(Mutex::new(queue)),
+            history: Arc::new(Mutex::new(history)),
+            store: Some(Arc::new(Mutex::new(store))),
+            ..Self::new()
+        };
+        state.persist();
+        state
+    }
+
+    /// Save the queue and history if this state has a store. Failures are logged
+    /// rather than returned so a full disk never stops a batch. Reports aren't
+    /// part of this; they're saved once, as each job finishes.
+    fn persist(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+
+        // Snapshot while holding the store lock so saves can't land out of order
+        let store = store.lock().unwrap();
+        let stored = StoredJobs {
+            queue: self.jobs.lock().unwrap().iter().map(JobInfo::without_report).collect(),
+            history: self.history.lock().unwrap().clone(),
+        };
+        if let Err(e) = store.save(&stored) {
+            eprintln!("Failed to save job queue: {:#}", e);
+        }
+    }
+
+    pub fn has_pending(&self) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().any(|job| job.status == JobStatus::Pending)
+    }
+
+    pub fn list_jobs(&self) -> Vec<JobInfo> {
+        self.jobs.lock().unwrap().clone()
+    }
+
+    /// Finished jobs, most recent first. With a store their reports are left out;
+    /// see [`job_report`](Self::job_report).
+    pub fn list_history(&self) -> Vec<JobInfo> {
+        let history = self.history.lock().unwrap();
+        history.iter().rev().cloned().collect()
+    }
+
+    /// Report of a job that ran successfully, from the queue or the saved reports
+    pub fn job_report(&self, job_id: &str) -> Result<Option<ProcessingReport>> {
+        let in_memory = [&self.jobs, &self.history].into_iter().find_map(|jobs| {
+            let jobs = jobs.lock().unwrap();
+            jobs.iter().find(|job| job.id == job_id).and_then(|job| job.report.clone())
+        });
+        match (in_memory, &self.store) {
+            (Some(report), _) => Ok(Some(report)),
+            (None, Some(store)) => store.lock().unwrap().load_report(job_id),
+            (None, None) => Ok(None),
+        }
+    }
+
+    pub fn add_jobs(&self, paths: Vec<String>, options: JobOptions) -> Result<Enqueued> {
+        let grouped = input::group_paths(paths);
         let mut jobs = self.jobs.lock().unwrap();
-        let new_jobs: Vec<JobInfo> = paths
+        let new_jobs: Vec<JobInfo> = grouped
+            .jobs
             .into_iter()
-            .map(|path| JobInfo {
-                id: Uuid::new_v4().to_string(),
-                input_path: path,
-                status: JobStatus::Pending,
-                progress: None,
-                output_path: None,
-                error: None,
-            })
+            .map(|(path, input)| JobInfo::with_input(path, input, options.clone()))
             .collect();
 
         jobs.extend(new_jobs.clone());
-        Ok(new_jobs)
+        drop(jobs);
+
+        self.persist();
+        Ok(Enqueued {
+            jobs: new_jobs,
+            ignored: grouped.ignored,
+        })
     }
 
     pub fn cancel_current(&self) {
         self.cancel_flag.store(true, Ordering::SeqCst);
     }
 
+    /// Remove finished jobs from the queue; they stay in the history
     pub fn clear_finished(&self) {
         let mut jobs = self.jobs.lock().unwrap();
         jobs.retain(|job| {
             job.status == JobStatus::Pending || job.status == JobStatus::Processing
         });
+        drop(jobs);
+
+        self.persist();
     }
 
     pub fn get_next_pending(&self) -> Option<JobInfo> {
         let mut jobs = self.jobs.lock().unwrap();
-        jobs.iter_mut()
+        let next = jobs.iter_mut()
             .find(|job| job.status == JobStatus::Pending)
             .map(|job| {
                 job.status = JobStatus::Processing;
                 job.clone()
-            })
+            });
+        drop(jobs);
+
+        if next.is_some() {
+            self.persist();
+        }
+        next
     }
 
     pub fn update_job(&self, id: &str, update: impl FnOnce(&mut JobInfo)) {
@@ -126,15 +331,64 @@ impl ProcessorState {
         let _ = app.emit("processing-progress", progress);
     }
 
-    pub fn mark_success(&self, app: &AppHandle, job_id: &str, output_path: String) {
+    /// Copy a finished job into the history and save. With a store, its report
+    /// is saved to its own file and the history keeps only the summary.
+    fn record_finished(&self, job_id: &str) {
+        let finished = {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.iter_mut().find(|j| j.id == job_id).map(|job| {
+                job.finished_at = Some(Utc::now().to_rfc3339());
+                job.clone()
+            })
+        };
+
+        if let Some(mut job) = finished {
+            if let Some(store) = &self.store {
+                let store = store.lock().unwrap();
+                if let Some(report) = job.report.take() {
+                    if let Err(e) = store.save_report(&job.id, &report) {
+                        eprintln!("Failed to save report of job {}: {:#}", job.id, e);
+                    }
+                }
+            }
+
+            let dropped: Vec<JobInfo> = {
+                let mut history = self.history.lock().unwrap();
+                history.push(job);
+                let excess = history.len().saturating_sub(MAX_HISTORY);
+                history.drain(..excess).collect()
+            };
+            // The store is locked before the history elsewhere, so never while holding it
+            if let Some(store) = &self.store {
+                let store = store.lock().unwrap();
+                for job in dropped {
+                    store.remove_report(&job.id);
+                }
+            }
+        }
+
+        self.persist();
+    }
+
+    pub fn mark_success(&self, app: &AppHandle, job_id: &str, output: JobOutput) {
+        let output_path = output.output_path;
+        let warning_count = output.report.warning_count();
         self.update_job(job_id, |job| {
-            job.status = JobStatus::Success;
+            job.status = if warning_count > 0 {
+                JobStatus::CompletedWithWarnings
+            } else {
+                JobStatus::Success
+            };
             job.output_path = Some(output_path.clone());
+            job.summary = Some(JobSummary::of(&output.report));
+            job.report = Some(output.report);
             job.progress = None;
         });
+        self.record_finished(job_id);
         let _ = app.emit("job-complete", serde_json::json!({
             "jobId": job_id,
             "outputPath": output_path,
+            "warningCount": warning_count,
         }));
     }
 
@@ -144,17 +398,32 @@ impl ProcessorState {
             job.error = Some(error.clone());
             job.progress = None;
         });
+        self.record_finished(job_id);
         let _ = app.emit("job-failed", serde_json::json!({
             "jobId": job_id,
             "error": error,
         }));
     }
 
+    pub fn mark_skipped(&self, app: &AppHandle, job_id: &str, existing_path: String) {
+        self.update_job(job_id, |job| {
+            job.status = JobStatus::Skipped;
+            job.output_path = Some(existing_path.clone());
+            job.progress = None;
+        });
+        self.record_finished(job_id);
+        let _ = app.emit("job-skipped", serde_json::json!({
+            "jobId": job_id,
+            "outputPath": existing_path,
+        }));
+    }
+
     pub fn mark_cancelled(&self, job_id: &str) {
         self.update_job(job_id, |job| {
             job.status = JobStatus::Cancelled;
             job.progress = None;
         });
+        self.record_finished(job_id);
     }
 }
 
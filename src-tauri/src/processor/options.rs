@@ -0,0 +1,748 @@
+use super::rules::{self, FileFacts, SortRule};
+use crate::utils::metadata::PhotoInfo;
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Per-job settings chosen when a zip is enqueued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct JobOptions {
+    pub non_image_policy: NonImagePolicy,
+    pub nested_zip_mode: NestedZipMode,
+    /// How many levels of zips-inside-zips to descend into (1 = only direct children)
+    pub max_nested_depth: usize,
+    /// Number of images converted at once; `None` uses one worker per CPU core
+    pub worker_threads: Option<usize>,
+    pub conversion: ConversionSettings,
+    /// Smaller copies written for every image besides the full-size one
+    pub derivatives: DerivativeSettings,
+    /// Rearrange the output by capture date or sort rules; `None` mirrors the input's folders
+    pub sorting: Option<SortSettings>,
+    /// Finding images that appear more than once, in this zip or in the zips inside it
+    pub duplicates: DuplicateSettings,
+    /// Go through the job and report where every file would go, without writing anything
+    pub dry_run: bool,
+    pub error_policy: ErrorPolicy,
+    /// Where the converted zip goes; `None` uses the saved preferences
+    pub output: Option<OutputSettings>,
+}
+
+impl Default for JobOptions {
+    fn default() -> Self {
+        Self {
+            non_image_policy: NonImagePolicy::default(),
+            nested_zip_mode: NestedZipMode::default(),
+            max_nested_depth: 3,
+            worker_threads: None,
+            conversion: ConversionSettings::default(),
+            derivatives: DerivativeSettings::default(),
+            sorting: None,
+            duplicates: DuplicateSettings::default(),
+            dry_run: false,
+            error_policy: ErrorPolicy::default(),
+            output: None,
+        }
+    }
+}
+
+/// Placeholders sort templates and rule folders may use
+const SORT_PLACEHOLDERS: &[&str] = &[
+    "year", "month", "day", "hour", "minute", "second", "folder", "filename", "stem", "ext", "make", "model",
+    "lens",
+];
+
+/// Where files go in the output when it is sorted by date or by rules
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortSettings {
+    /// Path of each file relative to the output, e.g. `{year}/{month}/{day}/{filename}`.
+    /// Supports `{year}`, `{month}`, `{day}`, `{hour}`, `{minute}` and `{second}` from the
+    /// capture date, `{make}`, `{model}` and `{lens}` from the EXIF data, and `{folder}`
+    /// (the file's folder in the input), `{filename}`, `{stem}` and `{ext}`. Defaults to
+    /// `{folder}/{filename}`, which keeps the input's structure for files no rule matches.
+    #[serde(default = "default_sort_template")]
+    pub template: String,
+    /// Tried in order before the template; the first rule that matches a file picks
+    /// its folder
+    #[serde(default)]
+    pub rules: Vec<SortRule>,
+}
+
+/// Sort template for jobs that don't give one; files keep their place in the input
+pub fn default_sort_template() -> String {
+    "{folder}/{filename}".to_string()
+}
+
+impl SortSettings {
+    pub fn validate(&self) -> Result<()> {
+        check_template(&self.template, "sort template")?;
+        if !self.template.contains("{filename}") && !self.template.contains("{stem}") {
+            return Err(anyhow!("Sort template must contain {{filename}} or {{stem}}"));
+        }
+
+        let mut names = HashSet::new();
+        for rule in &self.rules {
+            rule.validate()?;
+            check_template(&rule.folder, &format!("the folder of sort rule {}", rule.name))?;
+            if !names.insert(rule.name.as_str()) {
+                return Err(anyhow!("More than one sort rule is named {}", rule.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Where a file goes, and the rule that sent it there if one matched. `path` is
+    /// its place in the output without sorting; filename patterns are matched against
+    /// `source_path`, the file's path in the input. Date and camera placeholders read
+    /// `unknown` for files without them.
+    pub fn destination(
+        &self,
+        path: &Path,
+        source_path: &Path,
+        date: Option<NaiveDateTime>,
+        facts: &FileFacts,
+    ) -> (PathBuf, Option<&SortRule>) {
+        match rules::first_match(&self.rules, source_path, facts) {
+            Some(rule) => {
+                let folder = render_path(&rule.folder, path, date, &facts.photo);
+                (folder.join(path.file_name().unwrap_or_default()), Some(rule))
+            }
+            None => (render_path(&self.template, path, date, &facts.photo), None),
+        }
+    }
+}
+
+/// Check a sort template or rule folder: known placeholders, and a relative path
+/// that stays inside the output
+fn check_template(template: &str, what: &str) -> Result<()> {
+    let mut unknown = None;
+    render_template(template, |name| {
+        if !SORT_PLACEHOLDERS.contains(&name) && unknown.is_none() {
+            unknown = Some(name.to_string());
+        }
+        Some(String::new())
+    })?;
+    if let Some(name) = unknown {
+        return Err(anyhow!("Unknown placeholder {{{}}} in {}", name, what));
+    }
+    if template.starts_with(['/', '\\']) {
+        return Err(anyhow!("The {} must be a relative path", what));
+    }
+    if template.split(['/', '\\']).any(|part| part == "..") {
+        return Err(anyhow!("The {} can't contain ..", what));
+    }
+    Ok(())
+}
+
+/// Render a sort template for a file at `path`
+fn render_path(template: &str, path: &Path, date: Option<NaiveDateTime>, photo: &PhotoInfo) -> PathBuf {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    let folder = path
+        .parent()
+        .map(|parent| parent.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_default();
+    let date_part = |format: &str| match date {
+        Some(date) => date.format(format).to_string(),
+        None => "unknown".to_string(),
+    };
+    // Camera names can hold slashes, which mustn't become folders
+    let text = |value: &Option<String>| match value {
+        Some(value) => value.replace(['/', '\\'], "-"),
+        None => "unknown".to_string(),
+    };
+
+    let rendered = render_template(template, |name| {
+        Some(match name {
+            "year" => date_part("%Y"),
+            "month" => date_part("%m"),
+            "day" => date_part("%d"),
+            "hour" => date_part("%H"),
+            "minute" => date_part("%M"),
+            "second" => date_part("%S"),
+            "make" => text(&photo.make),
+            "model" => text(&photo.model),
+            "lens" => text(&photo.lens),
+            "folder" => folder.clone(),
+            "filename" => file_name.to_string(),
+            "stem" => stem.to_string(),
+            "ext" => ext.to_string(),
+            _ => return None,
+        })
+    })
+    .unwrap_or_else(|_| file_name.to_string());
+
+    // An empty {folder} leaves an empty path segment behind
+    rendered
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .collect()
+}
+
+/// Replace each `{name}` in a template with `value(name)`, keeping placeholders it
+/// returns `None` for. Fails on an unclosed `{`.
+fn render_template(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> Result<String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Unclosed {{ in template {}", template))?;
+        let name = &rest[start + 1..start + end];
+        match value(name) {
+            Some(text) => rendered.push_str(&text),
+            None => rendered.push_str(&rest[start..=start + end]),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Extra sizes written for every image, e.g. `thumb` at 256px and `medium` at
+/// 1024px. None by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DerivativeSettings {
+    pub sizes: Vec<DerivativeSize>,
+    pub layout: DerivativeLayout,
+}
+
+impl DerivativeSettings {
+    pub fn validate(&self) -> Result<()> {
+        for (i, size) in self.sizes.iter().enumerate() {
+            let name = size.name.as_str();
+            if name.trim().is_empty() {
+                return Err(anyhow!("Derivative size names can't be empty"));
+            }
+            if name.contains(['/', '\\']) || name == "." || name == ".." {
+                return Err(anyhow!("Derivative size name {} can't be used as a file or folder name", name));
+            }
+            if size.max_long_edge == 0 {
+                return Err(anyhow!("Derivative size {} must be at least 1 pixel", name));
+            }
+            if self.sizes[..i].iter().any(|other| other.name == name) {
+                return Err(anyhow!("Derivative size {} is listed more than once", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One derivative size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivativeSize {
+    /// Used in the derivative's folder or file name
+    pub name: String,
+    /// Longest side in pixels; smaller images keep their size
+    pub max_long_edge: u32,
+}
+
+/// Where derivatives go in the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DerivativeLayout {
+    /// A parallel folder tree per size, e.g. `thumb/photos/a.jpg`
+    #[default]
+    Folders,
+    /// Next to the full-size image, e.g. `photos/a_thumb.jpg`
+    Suffix,
+}
+
+impl DerivativeLayout {
+    /// Where the `name` derivative of the image written to `path` goes
+    pub fn path(&self, name: &str, path: &Path, extension: &str) -> PathBuf {
+        match self {
+            DerivativeLayout::Folders => Path::new(name).join(path).with_extension(extension),
+            DerivativeLayout::Suffix => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                path.with_file_name(format!("{}_{}.{}", stem, name, extension))
+            }
+        }
+    }
+}
+
+/// How duplicate images are found and what happens to them. Images are only
+/// compared with others in the same job: copies in two zips of a batch, or in two
+/// jobs, are both written. Detection across jobs is out of scope.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DuplicateSettings {
+    pub policy: DuplicatePolicy,
+    /// Also look for images that look the same without being the same file, like a
+    /// resized or re-saved copy. They're only reported, never left out.
+    pub near_duplicates: bool,
+    /// How many of the 64 bits of two images' perceptual hashes may differ for them
+    /// to count as near duplicates
+    pub max_distance: u32,
+}
+
+impl Default for DuplicateSettings {
+    fn default() -> Self {
+        Self {
+            policy: DuplicatePolicy::default(),
+            near_duplicates: false,
+            max_distance: 5,
+        }
+    }
+}
+
+impl DuplicateSettings {
+    /// Whether images need hashing at all
+    pub fn is_active(&self) -> bool {
+        self.policy != DuplicatePolicy::KeepAll || self.near_duplicates
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.max_distance > 32 {
+            return Err(anyhow!("Near-duplicate distance must be at most 32 bits"));
+        }
+        Ok(())
+    }
+}
+
+/// What to do with images whose bytes are identical to an earlier one's
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicatePolicy {
+    /// Write every copy and don't look for duplicates
+    #[default]
+    KeepAll,
+    /// Write every copy and list the duplicates in the report
+    KeepAllAndReport,
+    /// Write only the first copy, in input order, and list the others under `skipped`
+    KeepOne,
+}
+
+/// What to do with zip entries that are not images (videos, sidecars, documents)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NonImagePolicy {
+    /// Copy the entry into the output zip unchanged
+    #[default]
+    Passthrough,
+    /// Leave the entry out and list it under `skipped` in the report
+    DropAndReport,
+    /// Leave the entry out without mentioning it
+    DropSilently,
+}
+
+/// What to do when an image can't be converted (e.g. a corrupt file)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorPolicy {
+    /// Abort the job and write nothing
+    #[default]
+    FailFast,
+    /// Leave the image out and list it under `failed` in the report
+    SkipAndReport,
+    /// Copy the original file into the output unchanged and list it under `failed`
+    CopyOriginalAndReport,
+}
+
+/// How zip files found inside the input zip are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NestedZipMode {
+    /// Treat nested zips like any other non-image file
+    #[default]
+    Ignore,
+    /// Convert the nested zip's contents into a folder named after it
+    Flatten,
+    /// Convert the nested zip's contents and pack them back into a zip of the same name
+    Repack,
+}
+
+/// How images that need converting are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ConversionSettings {
+    pub target_format: TargetFormat,
+    /// 1-100; ignored by PNG and lossless WebP
+    pub quality: u8,
+    /// Only used for JPEG output
+    pub chroma_subsampling: ChromaSubsampling,
+    /// Re-encode JPEG, PNG and static inputs already in the target format instead of copying them
+    pub reencode_supported: bool,
+    pub animation: AnimationPolicy,
+    /// How camera RAW files are turned into images
+    pub raw: RawPolicy,
+    /// Keep each RAW file in the output next to the image made from it
+    pub keep_raw: bool,
+    /// Rotate copied JPEGs upright without re-encoding them, following their EXIF
+    /// orientation. Converted images are always rotated.
+    pub rotate_copied_jpegs: bool,
+    /// What happens to the source's ICC colour profile
+    pub color_profile: ColorProfilePolicy,
+    /// Write sources with more than 8 bits per channel as 16-bit PNG, whatever the
+    /// target format. Otherwise they are dithered down to 8 bits.
+    pub high_bit_depth_png: bool,
+    /// What to do with transparent images when the target format can't store alpha
+    pub transparency: TransparencyPolicy,
+    /// Limits converted images are scaled to; none by default
+    pub resize: ResizeSettings,
+}
+
+impl Default for ConversionSettings {
+    fn default() -> Self {
+        Self {
+            target_format: TargetFormat::default(),
+            quality: 95,
+            chroma_subsampling: ChromaSubsampling::default(),
+            reencode_supported: false,
+            animation: AnimationPolicy::default(),
+            raw: RawPolicy::default(),
+            keep_raw: false,
+            rotate_copied_jpegs: false,
+            color_profile: ColorProfilePolicy::default(),
+            high_bit_depth_png: false,
+            transparency: TransparencyPolicy::default(),
+            resize: ResizeSettings::default(),
+        }
+    }
+}
+
+impl ConversionSettings {
+    /// Human-readable summary for the report, e.g. "JPEG with quality 95 and 4:4:4 chroma subsampling"
+    pub fn describe(&self) -> String {
+        match self.target_format {
+            TargetFormat::Jpeg => format!(
+                "JPEG with quality {} and {} chroma subsampling",
+                self.quality,
+                self.chroma_subsampling.ratio()
+            ),
+            TargetFormat::Png => "PNG".to_string(),
+            TargetFormat::WebpLossless => "lossless WebP".to_string(),
+            TargetFormat::WebpLossy => format!("lossy WebP with quality {}", self.quality),
+            TargetFormat::Avif => format!("AVIF with quality {}", self.quality),
+        }
+    }
+}
+
+/// What to do with animated GIF and WebP inputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnimationPolicy {
+    /// Copy the file unchanged
+    #[default]
+    Keep,
+    /// Re-encode every frame, as animated WebP when that's the target format and
+    /// as animated GIF otherwise
+    ConvertAnimated,
+    /// Convert only the first frame to the target format, like a still image
+    FirstFrame,
+}
+
+/// What to do with camera RAW files (DNG, CR2, NEF, ARW)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RawPolicy {
+    /// Treat them like any other non-image file
+    #[default]
+    Ignore,
+    /// Use the full-size JPEG preview the camera embedded, developing files that
+    /// don't have one
+    EmbeddedPreview,
+    /// Demosaic the sensor data with the camera's white balance and colour matrix
+    Develop,
+}
+
+/// How converted images with an embedded ICC profile (Display P3 HEICs, Adobe RGB
+/// TIFFs) keep their colours
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ColorProfilePolicy {
+    /// Copy the profile into the output. AVIF output can't carry one, so those
+    /// images are converted to sRGB instead.
+    #[default]
+    Embed,
+    /// Convert the pixels to sRGB and leave the profile out
+    ConvertToSrgb,
+}
+
+/// What to do with images that use transparency when the target format (JPEG)
+/// can't store it. Alpha channels that are opaque everywhere are dropped first, so
+/// only images with some see-through pixels count.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum TransparencyPolicy {
+    /// Flatten onto an RGB background colour
+    Composite { background: [u8; 3] },
+    /// Write the image in a format that keeps alpha instead of the target format
+    KeepAlpha { format: AlphaFormat },
+    /// Treat the image as failed, leaving it to the error policy
+    Fail,
+}
+
+impl Default for TransparencyPolicy {
+    fn default() -> Self {
+        TransparencyPolicy::Composite {
+            background: [255, 255, 255],
+        }
+    }
+}
+
+/// Format used for transparent images under [`TransparencyPolicy::KeepAlpha`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AlphaFormat {
+    #[default]
+    Png,
+    WebpLossless,
+}
+
+impl AlphaFormat {
+    pub fn target_format(&self) -> TargetFormat {
+        match self {
+            AlphaFormat::Png => TargetFormat::Png,
+            AlphaFormat::WebpLossless => TargetFormat::WebpLossless,
+        }
+    }
+}
+
+/// Dimensions converted images are scaled to. Unset limits don't apply, so the
+/// default leaves every image at its own size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ResizeSettings {
+    /// Longest side in pixels, whichever way round the image is
+    pub max_long_edge: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    /// Total pixel count in millions
+    pub max_megapixels: Option<f32>,
+    pub mode: ResizeMode,
+    pub filter: ResizeFilter,
+    /// Enlarge images smaller than the limits; otherwise they keep their size
+    pub allow_upscale: bool,
+    /// Also resize JPEG, PNG and files already in the target format that would
+    /// otherwise be copied. They are re-encoded in their own format.
+    pub include_copied: bool,
+}
+
+impl ResizeSettings {
+    /// Whether any limit is set
+    pub fn is_active(&self) -> bool {
+        self.max_long_edge.is_some()
+            || self.max_width.is_some()
+            || self.max_height.is_some()
+            || self.max_megapixels.is_some()
+    }
+}
+
+/// How an image is brought to the resize limits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeMode {
+    /// Scale to fit within every limit, keeping the aspect ratio
+    #[default]
+    Fit,
+    /// Scale to cover `max_width` x `max_height` and crop the overflow from the
+    /// centre, keeping the aspect ratio
+    Fill,
+    /// Stretch to exactly `max_width` x `max_height`
+    Exact,
+}
+
+/// Resampling filter used when resizing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeFilter {
+    Nearest,
+    /// Bilinear
+    Triangle,
+    /// Bicubic
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub fn filter_type(&self) -> image::imageops::FilterType {
+        use image::imageops::FilterType;
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Output format for converted images
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TargetFormat {
+    #[default]
+    Jpeg,
+    Png,
+    WebpLossless,
+    WebpLossy,
+    Avif,
+}
+
+impl TargetFormat {
+    /// File extension given to converted files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TargetFormat::Jpeg => "jpg",
+            TargetFormat::Png => "png",
+            TargetFormat::WebpLossless | TargetFormat::WebpLossy => "webp",
+            TargetFormat::Avif => "avif",
+        }
+    }
+
+    /// Whether the format can store an alpha channel
+    pub fn supports_alpha(&self) -> bool {
+        !matches!(self, TargetFormat::Jpeg)
+    }
+}
+
+/// Chroma subsampling for JPEG output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChromaSubsampling {
+    /// Full colour resolution
+    #[default]
+    Yuv444,
+    /// Half horizontal colour resolution
+    Yuv422,
+    /// Half horizontal and vertical colour resolution (smallest files)
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub fn ratio(&self) -> &'static str {
+        match self {
+            ChromaSubsampling::Yuv444 => "4:4:4",
+            ChromaSubsampling::Yuv422 => "4:2:2",
+            ChromaSubsampling::Yuv420 => "4:2:0",
+        }
+    }
+}
+
+/// Where converted zips are written and what they are called
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputSettings {
+    pub mode: OutputMode,
+    pub destination: OutputDestination,
+    /// Output name without the `.zip` extension; supports `{stem}`, `{date}` and `{job_id}`
+    pub filename_template: String,
+    pub collision_policy: CollisionPolicy,
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        Self {
+            mode: OutputMode::default(),
+            destination: OutputDestination::default(),
+            filename_template: "{stem}-converted".to_string(),
+            collision_policy: CollisionPolicy::default(),
+        }
+    }
+}
+
+impl OutputSettings {
+    /// Reject templates that would produce an empty name or write outside the destination
+    pub fn validate(&self) -> Result<()> {
+        if self.filename_template.trim().is_empty() {
+            return Err(anyhow!("Filename template is empty"));
+        }
+        if self.filename_template.contains(['/', '\\']) {
+            return Err(anyhow!("Filename template can't contain path separators"));
+        }
+        // As a folder name these are the destination itself or its parent
+        if matches!(self.filename_template.trim(), "." | "..") {
+            return Err(anyhow!("Filename template can't be . or .."));
+        }
+        if let OutputDestination::Folder { path } = &self.destination {
+            if path.trim().is_empty() {
+                return Err(anyhow!("Output folder is empty"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the converted copy of `input_path`, e.g. `photos-converted.zip`,
+    /// or `photos-converted` for a folder
+    pub fn file_name(&self, input_path: &Path, job_id: &str) -> String {
+        let stem = input_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        let name = self
+            .filename_template
+            .replace("{stem}", stem)
+            .replace("{date}", &Local::now().format("%Y-%m-%d").to_string())
+            .replace("{job_id}", job_id)
+            .replace(['/', '\\'], "_");
+
+        // An input like `..zip` has the stem `.`, which mustn't name the output
+        let name = if matches!(name.trim(), "" | "." | "..") { "output".to_string() } else { name };
+        match self.mode {
+            OutputMode::Zip => format!("{}.zip", name),
+            OutputMode::Directory => name,
+        }
+    }
+}
+
+/// What the converted files are written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputMode {
+    /// A zip file with `report.json` at its root
+    #[default]
+    Zip,
+    /// A folder with `report.json` at its root, written without an intermediate zip
+    Directory,
+}
+
+/// Folder converted zips are written into
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutputDestination {
+    /// The user's Downloads folder
+    #[default]
+    Downloads,
+    /// The folder the input zip is in
+    NextToInput,
+    /// A fixed folder
+    Folder { path: String },
+}
+
+impl OutputDestination {
+    pub fn resolve(&self, input_path: &Path) -> Result<PathBuf> {
+        match self {
+            OutputDestination::Downloads => {
+                dirs::download_dir().ok_or_else(|| anyhow!("Could not find Downloads folder"))
+            }
+            OutputDestination::NextToInput => input_path
+                .parent()
+                .map(Path::to_path_buf)
+                .ok_or_else(|| anyhow!("Could not find the folder containing {}", input_path.display())),
+            OutputDestination::Folder { path } => Ok(PathBuf::from(path)),
+        }
+    }
+}
+
+/// What to do when the output file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CollisionPolicy {
+    /// Add a number to the name: `photos-converted-1.zip` or `photos-converted-1`
+    #[default]
+    Suffix,
+    /// Replace the existing file or folder
+    Overwrite,
+    /// Leave the existing output alone and don't process the input
+    Skip,
+}
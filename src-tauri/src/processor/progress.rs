@@ -0,0 +1,38 @@
+use crate::processor::{ProcessorState, ProgressInfo};
+use std::sync::atomic::Ordering;
+use tauri::AppHandle;
+
+/// Receives progress updates from the processing pipeline.
+///
+/// The pipeline itself knows nothing about how progress is displayed; the
+/// desktop app forwards updates as Tauri events while the CLI prints them
+/// to the terminal.
+pub trait ProgressReporter: Send + Sync {
+    /// Called whenever the pipeline moves to a new phase or file
+    fn report(&self, job_id: &str, progress: ProgressInfo);
+
+    /// Checked between files; returning true aborts the current job
+    fn is_cancelled(&self) -> bool;
+}
+
+/// Reports progress through the Tauri event system and `ProcessorState`
+pub struct AppProgressReporter<'a> {
+    app: &'a AppHandle,
+    state: &'a ProcessorState,
+}
+
+impl<'a> AppProgressReporter<'a> {
+    pub fn new(app: &'a AppHandle, state: &'a ProcessorState) -> Self {
+        Self { app, state }
+    }
+}
+
+impl ProgressReporter for AppProgressReporter<'_> {
+    fn report(&self, job_id: &str, progress: ProgressInfo) {
+        self.state.emit_progress(self.app, job_id, progress);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.state.cancel_flag.load(Ordering::SeqCst)
+    }
+}
@@ -1,5 +1,7 @@
 use super::*;
-use crate::processor::zip_handler::process_zip_file;
+use crate::processor::progress::AppProgressReporter;
+use crate::processor::zip_handler::{process_job, OutputExists};
+use std::path::Path;
 use std::sync::atomic::Ordering;
 use tauri::AppHandle;
 
@@ -25,16 +27,25 @@ pub async fn start_queue_processor(app: AppHandle, state: Arc<ProcessorState>) {
             };
 
             // Process the job
-            match process_zip_file(&app, &state, &job).await {
-                Ok(output_path) => {
+            let reporter = AppProgressReporter::new(&app, &state);
+            let destination = job.options.output.clone().unwrap_or_default().destination;
+            let result = match destination.resolve(Path::new(&job.input_path)) {
+                Ok(output_dir) => process_job(&reporter, &job, &output_dir).await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(output) => {
                     if state.cancel_flag.load(Ordering::SeqCst) {
                         state.mark_cancelled(&job.id);
                     } else {
-                        state.mark_success(&app, &job.id, output_path);
+                        state.mark_success(&app, &job.id, output);
                     }
                 }
                 Err(e) => {
-                    if state.cancel_flag.load(Ordering::SeqCst) {
+                    if let Some(OutputExists(existing)) = e.downcast_ref::<OutputExists>() {
+                        state.mark_skipped(&app, &job.id, existing.to_string_lossy().to_string());
+                    } else if state.cancel_flag.load(Ordering::SeqCst) {
                         state.mark_cancelled(&job.id);
                     } else {
                         state.mark_failed(&app, &job.id, e.to_string());
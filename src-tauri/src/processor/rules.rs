@@ -0,0 +1,204 @@
+use crate::utils::metadata::PhotoInfo;
+use anyhow::{anyhow, Context, Result};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Sort rules
+// Rules send files to folders by what their metadata says: the camera that took
+// them, any other EXIF field, their format, size or shape, or their name. When sorting, they are tried in
+// order and the first whose conditions all hold picks the file's folder; files no
+// rule matches follow the job's sort template.
+
+/// A named rule sending the files it matches to a folder
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortRule {
+    /// Listed in the report against every file the rule placed
+    pub name: String,
+    /// Conditions a file must meet, all of them. A rule without any takes every file.
+    #[serde(rename = "match", default)]
+    pub conditions: RuleMatch,
+    /// Folder for matching files, relative to the output. A template with the same
+    /// placeholders as the sort template, e.g. `cameras/{model}/{year}`; files keep
+    /// their names inside it.
+    pub folder: String,
+}
+
+/// Conditions of a sort rule. Text is compared case-insensitively as a glob
+/// pattern, so `Canon*` matches any Canon camera.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RuleMatch {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// Any of these formats, as named in the report, e.g. `["HEIC", "DNG"]`
+    pub format: Vec<String>,
+    /// Size limits in pixels, compared with the image once upright
+    pub min_width: Option<u32>,
+    pub max_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_height: Option<u32>,
+    pub orientation: Option<Shape>,
+    pub has_gps: Option<bool>,
+    /// Patterns for other EXIF fields by tag name, e.g. `{ Software = "Lightroom*" }`.
+    /// Tag names are those of the EXIF standard and ignore case.
+    pub exif: BTreeMap<String, String>,
+    /// Pattern for the file's name, e.g. `IMG_*.heic`, or when it contains a `/`, for
+    /// its path in the input, e.g. `exports/**/*.jpg`
+    pub filename: Option<String>,
+}
+
+/// Which way round an image is once upright
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Shape {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+/// What sort rules know about a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileFacts {
+    /// From the image's EXIF data; empty for files without any
+    pub photo: PhotoInfo,
+    /// Format name as shown in the report, for images
+    pub format: Option<String>,
+    /// Width and height once upright, for images whose header could be read
+    pub size: Option<(u32, u32)>,
+}
+
+/// Rules as saved in a rules file
+#[derive(Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<SortRule>,
+}
+
+/// Read rules from a file: JSON if its extension is `.json`, TOML otherwise. Either
+/// holds a `rules` list, e.g. `[[rules]]` tables in TOML, in the order they're tried.
+pub fn load(path: &Path) -> Result<Vec<SortRule>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read rules file {}", path.display()))?;
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+    let file: RulesFile = if is_json {
+        serde_json::from_str(&text).with_context(|| format!("Invalid rules file {}", path.display()))?
+    } else {
+        toml::from_str(&text).with_context(|| format!("Invalid rules file {}", path.display()))?
+    };
+    Ok(file.rules)
+}
+
+/// The first rule matching a file at `path` (its place in the input), if any
+pub fn first_match<'a>(rules: &'a [SortRule], path: &Path, facts: &FileFacts) -> Option<&'a SortRule> {
+    rules.iter().find(|rule| rule.conditions.matches(path, facts))
+}
+
+impl SortRule {
+    /// Check the rule's name and conditions. Its folder is checked with the sort
+    /// template, which shares its placeholders.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(anyhow!("Sort rules need a name"));
+        }
+        self.conditions
+            .validate()
+            .with_context(|| format!("Invalid sort rule {}", self.name))
+    }
+}
+
+impl RuleMatch {
+    pub fn validate(&self) -> Result<()> {
+        let fields = self.exif.values();
+        for pattern in [&self.make, &self.model, &self.lens, &self.filename].into_iter().flatten().chain(fields) {
+            Pattern::new(pattern).with_context(|| format!("Invalid pattern {}", pattern))?;
+        }
+        if self.min_width.zip(self.max_width).is_some_and(|(min, max)| min > max)
+            || self.min_height.zip(self.max_height).is_some_and(|(min, max)| min > max)
+        {
+            return Err(anyhow!("Minimum size is larger than the maximum"));
+        }
+        Ok(())
+    }
+
+    /// Whether a file at `path` meets every condition. Conditions on metadata the
+    /// file doesn't have, like the camera model of a PNG, aren't met.
+    pub fn matches(&self, path: &Path, facts: &FileFacts) -> bool {
+        let photo = &facts.photo;
+        let text_matches = |pattern: &Option<String>, value: &Option<String>| match (pattern, value) {
+            (None, _) => true,
+            (Some(pattern), Some(value)) => glob_matches(pattern, value),
+            (Some(_), None) => false,
+        };
+        if !text_matches(&self.make, &photo.make)
+            || !text_matches(&self.model, &photo.model)
+            || !text_matches(&self.lens, &photo.lens)
+        {
+            return false;
+        }
+
+        if !self.format.is_empty()
+            && !facts
+                .format
+                .as_ref()
+                .is_some_and(|format| self.format.iter().any(|wanted| wanted.eq_ignore_ascii_case(format)))
+        {
+            return false;
+        }
+
+        let limits = [self.min_width, self.max_width, self.min_height, self.max_height];
+        if limits.iter().any(Option::is_some) || self.orientation.is_some() {
+            let Some((width, height)) = facts.size else {
+                return false;
+            };
+            let within = self.min_width.is_none_or(|min| width >= min)
+                && self.max_width.is_none_or(|max| width <= max)
+                && self.min_height.is_none_or(|min| height >= min)
+                && self.max_height.is_none_or(|max| height <= max);
+            let shape = match width.cmp(&height) {
+                std::cmp::Ordering::Greater => Shape::Landscape,
+                std::cmp::Ordering::Less => Shape::Portrait,
+                std::cmp::Ordering::Equal => Shape::Square,
+            };
+            if !within || self.orientation.is_some_and(|wanted| wanted != shape) {
+                return false;
+            }
+        }
+
+        if self.has_gps.is_some_and(|wanted| wanted != photo.has_gps) {
+            return false;
+        }
+
+        let field_matches = |(tag, pattern): (&String, &String)| {
+            photo
+                .fields
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(tag))
+                .is_some_and(|(_, value)| glob_matches(pattern, value))
+        };
+        if !self.exif.iter().all(field_matches) {
+            return false;
+        }
+
+        match &self.filename {
+            Some(pattern) if pattern.contains('/') => glob_matches(pattern, &path.to_string_lossy().replace('\\', "/")),
+            Some(pattern) => glob_matches(pattern, &path.file_name().unwrap_or_default().to_string_lossy()),
+            None => true,
+        }
+    }
+}
+
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let options = MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    Pattern::new(pattern).is_ok_and(|pattern| pattern.matches_with(value, options))
+}
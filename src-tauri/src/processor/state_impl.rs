@@ -5,8 +5,10 @@ impl Clone for ProcessorState {
     fn clone(&self) -> Self {
         Self {
             jobs: Arc::clone(&self.jobs),
+            history: Arc::clone(&self.history),
             cancel_flag: Arc::clone(&self.cancel_flag),
             processing: Arc::clone(&self.processing),
+            store: self.store.clone(),
         }
     }
 }
@@ -25,23 +25,84 @@ impl TempManager {
             .context(format!("Failed to create subdirectory: {}", name))?;
         Ok(subdir)
     }
+}
+
+impl Drop for TempManager {
+    fn drop(&mut self) {
+        // Clean up temp directory when TempManager is dropped
+        let _ = fs::remove_dir_all(&self.temp_dir);
+    }
+}
+
+/// An output file or folder being written next to where it will end up, so it can
+/// be moved into place with a rename instead of a copy. Removed on drop unless
+/// `finish` was called, so a failed job never leaves a partial output behind.
+pub struct PartialOutput {
+    path: PathBuf,
+    finished: bool,
+}
+
+impl PartialOutput {
+    /// A folder, created straight away
+    pub fn dir(parent: &Path, job_id: &str) -> Result<Self> {
+        let output = Self::file(parent, job_id);
+        fs::create_dir_all(&output.path)
+            .context(format!("Failed to create output folder in {}", parent.display()))?;
 
-    pub fn get_extract_dir(&self) -> Result<PathBuf> {
-        self.create_subdir("extract")
+        Ok(output)
     }
 
-    pub fn get_staging_dir(&self) -> Result<PathBuf> {
-        self.create_subdir("staging")
+    /// A single file, which the caller creates at `path()`
+    pub fn file(parent: &Path, job_id: &str) -> Self {
+        let path = parent.join(format!(".file-sorter-{}.partial", job_id));
+        Self { path, finished: false }
     }
 
-    pub fn get_output_zip_path(&self) -> PathBuf {
-        self.temp_dir.join("output.zip")
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move the output to `destination`, replacing any file or folder already there.
+    /// A rename can't replace a folder, so the old output is first moved aside and
+    /// only deleted once the new one is in place; if the move fails it is put back.
+    pub fn finish(mut self, destination: &Path) -> Result<()> {
+        let replaced = if destination.exists() {
+            let aside = self.path.with_extension("old");
+            fs::rename(destination, &aside)
+                .context(format!("Failed to replace {}", destination.display()))?;
+            Some(aside)
+        } else {
+            None
+        };
+
+        if let Err(e) = fs::rename(&self.path, destination) {
+            if let Some(aside) = &replaced {
+                let _ = fs::rename(aside, destination);
+            }
+            return Err(e).context(format!("Failed to move output to {}", destination.display()));
+        }
+        self.finished = true;
+
+        if let Some(aside) = replaced {
+            remove_path(&aside);
+        }
+        Ok(())
     }
 }
 
-impl Drop for TempManager {
+/// Remove a file or a folder with everything in it, ignoring errors
+fn remove_path(path: &Path) {
+    if path.is_dir() {
+        let _ = fs::remove_dir_all(path);
+    } else {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl Drop for PartialOutput {
     fn drop(&mut self) {
-        // Clean up temp directory when TempManager is dropped
-        let _ = fs::remove_dir_all(&self.temp_dir);
+        if !self.finished {
+            remove_path(&self.path);
+        }
     }
 }
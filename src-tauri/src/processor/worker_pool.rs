@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// How often a worker waiting for room checks whether it should stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Number of workers to use when a job doesn't set one
+pub fn default_thread_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Run `work` over `tasks` on a fixed number of OS threads so blocking decode
+/// work stays off the async runtime.
+///
+/// Each worker builds its own state with `init` (e.g. open file handles) and
+/// pulls tasks in order. Results are sent as `(task_index, result)` in completion
+/// order. Workers stop picking up tasks once `stop` is set, and the channel closes
+/// when every worker has exited.
+///
+/// At most two tasks per thread run or wait to be received from the earliest task
+/// not yet received on. Callers that need results in order therefore hold a
+/// bounded number while a slow task finishes, instead of the rest of the job.
+pub fn spawn<T, S, R>(
+    tasks: Vec<T>,
+    threads: usize,
+    stop: Arc<AtomicBool>,
+    init: impl Fn() -> S + Send + Sync + 'static,
+    work: impl Fn(&mut S, &T) -> R + Send + Sync + 'static,
+) -> Results<R>
+where
+    T: Send + Sync + 'static,
+    R: Send + 'static,
+{
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let threads = threads.clamp(1, tasks.len().max(1));
+    let window = threads * 2;
+    let progress = Arc::new(Progress::default());
+    let results = Results {
+        receiver,
+        progress: Arc::clone(&progress),
+        received: vec![false; tasks.len()],
+    };
+    let tasks = Arc::new(tasks);
+    let next = Arc::new(AtomicUsize::new(0));
+    let init = Arc::new(init);
+    let work = Arc::new(work);
+
+    for _ in 0..threads {
+        let tasks = Arc::clone(&tasks);
+        let next = Arc::clone(&next);
+        let stop = Arc::clone(&stop);
+        let init = Arc::clone(&init);
+        let work = Arc::clone(&work);
+        let progress = Arc::clone(&progress);
+        let sender = sender.clone();
+
+        thread::spawn(move || {
+            let mut state = init();
+            while !stop.load(Ordering::SeqCst) {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                let Some(task) = tasks.get(idx) else {
+                    break;
+                };
+                if !progress.wait_for_room(idx, window, &stop) {
+                    break;
+                }
+                if sender.send((idx, work(&mut state, task))).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    results
+}
+
+/// Results of a worker pool, as `(task_index, result)` in completion order
+pub struct Results<R> {
+    receiver: UnboundedReceiver<(usize, R)>,
+    progress: Arc<Progress>,
+    /// Which tasks' results have been received
+    received: Vec<bool>,
+}
+
+impl<R> Results<R> {
+    /// The next result, or `None` once every worker has exited
+    pub async fn recv(&mut self) -> Option<(usize, R)> {
+        let (idx, result) = self.receiver.recv().await?;
+        self.received[idx] = true;
+        let mut earliest = self.progress.earliest.lock().unwrap();
+        while self.received.get(*earliest).copied().unwrap_or(false) {
+            *earliest += 1;
+        }
+        self.progress.advanced.notify_all();
+        Some((idx, result))
+    }
+}
+
+/// Shared between the workers and [`Results`]
+#[derive(Default)]
+struct Progress {
+    /// Index of the earliest task whose result hasn't been received
+    earliest: Mutex<usize>,
+    advanced: Condvar,
+}
+
+impl Progress {
+    /// Wait until task `idx` is within `window` of the earliest task not yet
+    /// received. Returns `false` if the pool was stopped first.
+    fn wait_for_room(&self, idx: usize, window: usize, stop: &AtomicBool) -> bool {
+        let mut earliest = self.earliest.lock().unwrap();
+        while idx >= *earliest + window {
+            if stop.load(Ordering::SeqCst) {
+                return false;
+            }
+            earliest = self.advanced.wait_timeout(earliest, STOP_POLL_INTERVAL).unwrap().0;
+        }
+        true
+    }
+}
@@ -1,255 +1,1402 @@
 use super::*;
-use crate::processor::image_converter::{ConversionResult, ImageConverter};
-use crate::processor::temp_manager::TempManager;
-use crate::report::ReportBuilder;
+use crate::processor::image_converter::{self, ConversionResult, Derivative, ImageConverter};
+use crate::processor::input::{self, is_zip, JobInput};
+use crate::processor::options::{
+    CollisionPolicy, DuplicatePolicy, DuplicateSettings, ErrorPolicy, JobOptions, NestedZipMode,
+    NonImagePolicy, OutputMode,
+};
+use crate::processor::progress::ProgressReporter;
+use crate::processor::rules::FileFacts;
+use crate::processor::temp_manager::{PartialOutput, TempManager};
+use crate::processor::worker_pool;
+use crate::report::{DateSource, DuplicateKind, ReportBuilder};
 use crate::utils::collision::CollisionManager;
+use crate::utils::disk;
+use crate::utils::duplicates::{self, ContentHash};
+use crate::utils::format::{SourceFormat, HEADER_LEN};
+use crate::utils::metadata::MetadataHandler;
 use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use std::collections::{hash_map, HashMap};
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::Ordering;
-use tauri::AppHandle;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use zip::write::FileOptions;
-use zip::ZipArchive;
+use zip::{ZipArchive, ZipWriter};
 
-pub async fn process_zip_file(
-    app: &AppHandle,
-    state: &ProcessorState,
+/// Name of the report written at the root of every output
+const REPORT_FILE_NAME: &str = "report.json";
+
+/// How often the processing loop checks for cancellation while waiting on workers
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Largest image read into memory for converting. Bigger files fail to convert
+/// rather than risk running out of memory.
+const MAX_IMAGE_SIZE: u64 = 1_000_000_000;
+
+/// Most memory set aside up front for reading an image. A zip entry's size comes
+/// from its header, which a damaged or crafted archive can get wrong.
+const MAX_PREALLOC: u64 = 64 << 20;
+
+/// What the processing phase does with a scanned entry
+#[derive(Clone, Copy)]
+enum EntryKind {
+    Image,
+    Passthrough,
+}
+
+/// Where a scanned entry's bytes come from
+#[derive(Clone)]
+enum EntrySource {
+    /// Entry `index` of `Scan::archives[archive]`
+    Archive { archive: usize, index: usize },
+    /// A file on disk, from a folder or loose-file input
+    Disk(PathBuf),
+}
+
+/// Files listed from the job's input before scanning
+enum Listing {
+    Archive(ZipArchive<File>),
+    /// `(relative_path, full_path)` pairs
+    Files(Vec<(String, PathBuf)>),
+}
+
+impl Listing {
+    fn len(&self) -> usize {
+        match self {
+            Listing::Archive(archive) => archive.len(),
+            Listing::Files(files) => files.len(),
+        }
+    }
+}
+
+/// A file found while scanning the input and any nested zips
+struct ScannedEntry {
+    source: EntrySource,
+    /// Name of the entry within its archive or input folder
+    name: String,
+    /// Full path of the entry, including the zips it is nested in (e.g. `day1.zip/IMG_1.heic`)
+    source_path: String,
+    /// Desired path relative to the root of its package
+    output_path: PathBuf,
+    /// Index into `Scan::packages`
+    package: usize,
+    kind: EntryKind,
+    /// Format of an image, from its contents or failing that its extension
+    format: Option<SourceFormat>,
+    /// Uncompressed size in bytes
+    size: u64,
+    /// Last modified time from the zip entry or file system, if it has one
+    modified: Option<NaiveDateTime>,
+}
+
+/// Conversion handed to the worker pool for one image
+struct Task {
+    source: EntrySource,
+    /// Name of the entry; its extension picks the decoder if the contents aren't recognised
+    name: String,
+    source_path: String,
+    /// Also read what sorting needs to know about the image
+    read_facts: bool,
+    /// Hash the image too when duplicates are looked for
+    duplicates: DuplicateSettings,
+}
+
+/// A converted image, held in memory until its turn to be written
+struct Converted {
+    result: ConversionResult,
+    data: Vec<u8>,
+    /// Extension of the written file, if it differs from the input's
+    extension: Option<String>,
+    /// Also copy the original into the output, for RAW files when the job keeps them
+    keep_original: bool,
+    /// The job's derivative sizes of the image
+    derivatives: Vec<Derivative>,
+    /// Date, camera, format and size, if the task asked for them
+    facts: Option<FileFacts>,
+    /// Hashes of the input, when looking for duplicates
+    hashes: Option<Hashes>,
+}
+
+/// What duplicates are found by
+#[derive(Clone, Copy)]
+struct Hashes {
+    /// Of the input's bytes
+    content: ContentHash,
+    /// Of the decoded image, when near duplicates are looked for and it could be read
+    perceptual: Option<u64>,
+}
+
+impl Converted {
+    fn new(
+        converter: &ImageConverter,
+        result: ConversionResult,
+        data: Vec<u8>,
+        derivatives: Vec<Derivative>,
+    ) -> Self {
+        let extension = match &result {
+            ConversionResult::Copied | ConversionResult::Reoriented { .. } => None,
+            ConversionResult::Raw { .. } => Some(converter.output_extension().to_string()),
+            ConversionResult::Converted { extension, .. } | ConversionResult::Animated { extension, .. } => {
+                Some(extension.clone())
+            }
+        };
+        let keep_original = matches!(result, ConversionResult::Raw { .. }) && converter.keeps_raw();
+        Self {
+            result,
+            data,
+            extension,
+            keep_original,
+            derivatives,
+            facts: None,
+            hashes: None,
+        }
+    }
+}
+
+/// Where an entry's output goes. Placements are written in scan order.
+struct Placement {
+    source: EntrySource,
+    kind: EntryKind,
+    package: usize,
+    source_path: String,
+    /// Desired path relative to the root of its package, with the input's extension
+    output_path: PathBuf,
+    modified: Option<NaiveDateTime>,
+    /// Index into the worker pool's tasks, or `None` if the entry is copied unchanged
+    task: Option<usize>,
+}
+
+/// How a file was sorted: the date used, where that came from, and the rule that
+/// placed it if one did
+struct SortOutcome {
+    date: Option<NaiveDateTime>,
+    source: DateSource,
+    rule: Option<String>,
+}
+
+/// What happened to an entry; `output_path` is where it ended up, as shown in the report
+enum Outcome {
+    Image {
+        result: ConversionResult,
+        output_path: String,
+        /// Where the original was copied to as well, if it was kept
+        original_copy: Option<String>,
+        derivatives: Vec<WrittenDerivative>,
+    },
+    Passthrough {
+        output_path: String,
+    },
+    /// Conversion failed and the error policy let the job continue
+    Failed {
+        error: String,
+        copied_to: Option<String>,
+    },
+    /// Left out as a copy of the image at `of`, an earlier entry's source path
+    Duplicate {
+        of: String,
+    },
+}
+
+/// A derivative size of an image, as written to the output
+struct WrittenDerivative {
+    name: String,
+    output_path: String,
+    dimensions: (u32, u32),
+}
+
+/// Where a package's files are written
+enum Sink {
+    Zip(ZipWriter<File>),
+    /// Files are written under this folder
+    Folder(PathBuf),
+    /// Nothing is written, for dry runs
+    Discard,
+}
+
+impl Sink {
+    fn zip(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .context(format!("Failed to create {}", path.display()))?;
+        Ok(Sink::Zip(ZipWriter::new(file)))
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.copy_from(path, &mut &data[..])
+    }
+
+    fn copy_from(&mut self, path: &Path, reader: &mut impl Read) -> Result<()> {
+        match self {
+            Sink::Zip(writer) => {
+                let options = FileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .unix_permissions(0o755);
+                writer.start_file(path.to_string_lossy(), options)?;
+                std::io::copy(reader, writer)?;
+            }
+            Sink::Folder(dir) => {
+                let output_path = dir.join(path);
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = File::create(&output_path)?;
+                std::io::copy(reader, &mut file)?;
+            }
+            Sink::Discard => {}
+        }
+        Ok(())
+    }
+
+    /// Write a zip's central directory; folders need nothing
+    fn finish(&mut self) -> Result<()> {
+        if let Sink::Zip(writer) = self {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// A zip or folder being assembled: the output itself, or a repacked nested zip
+struct Package {
+    sink: Sink,
+    collision_manager: CollisionManager,
+    /// Parent package and the (already reserved) path of this zip inside it
+    parent: Option<(usize, PathBuf)>,
+    /// Prefix for output paths in the report, e.g. `day1.zip/` for a repacked zip
+    report_prefix: String,
+    /// Temp file a repacked zip is built in before being copied into its parent
+    /// zip. `None` when it is built in place inside a folder output.
+    temp_path: Option<PathBuf>,
+}
+
+impl Package {
+    /// Reserve a unique name for a file, returning its path within the package and
+    /// its path as shown in the report
+    fn reserve(&mut self, desired_path: &Path) -> (PathBuf, String) {
+        let path = self.collision_manager.get_unique_path(desired_path);
+        let report_path = format!("{}{}", self.report_prefix, path.to_string_lossy());
+        (path, report_path)
+    }
+}
+
+/// Everything collected during the scan phase
+struct Scan<'a> {
+    options: &'a JobOptions,
+    converter: &'a ImageConverter,
+    temp_manager: &'a TempManager,
+    /// Where the output's own files are written, taken by the first package
+    root_sink: Option<Sink>,
+    report: &'a mut ReportBuilder,
+    archives: Vec<ZipArchive<File>>,
+    /// On-disk location of each archive, so workers can open their own handles
+    archive_paths: Vec<PathBuf>,
+    entries: Vec<ScannedEntry>,
+    packages: Vec<Package>,
+}
+
+/// Returned when the output file already exists and the job's collision policy is
+/// [`CollisionPolicy::Skip`]. Nothing is written.
+#[derive(Debug)]
+pub struct OutputExists(pub PathBuf);
+
+impl std::fmt::Display for OutputExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Output {} already exists", self.0.display())
+    }
+}
+
+impl std::error::Error for OutputExists {}
+
+/// Convert the images in the job's input (a zip, a folder or loose files) and write
+/// the result into `output_dir` as a zip or a folder, returning its path and the
+/// report. The output is named and placed according to the job's output settings.
+pub async fn process_job(
+    reporter: &dyn ProgressReporter,
     job: &JobInfo,
-) -> Result<String> {
+    output_dir: &Path,
+) -> Result<JobOutput> {
     let input_path = Path::new(&job.input_path);
-    
-    // Create temp directory for this job
+    job.options.derivatives.validate()?;
+    job.options.duplicates.validate()?;
+    if let Some(sorting) = &job.options.sorting {
+        sorting.validate()?;
+    }
+    let output_settings = job.options.output.clone().unwrap_or_default();
+    output_settings.validate()?;
+    let output_path = output_dir.join(output_settings.file_name(input_path, &job.id));
+
+    let dry_run = job.options.dry_run;
+
+    // Check before doing any work so a skipped job costs nothing
+    if output_settings.collision_policy == CollisionPolicy::Skip && output_path.exists() && !dry_run {
+        return Err(OutputExists(output_path).into());
+    }
+
+    // Create temp directory for this job; it only holds nested zips
     let temp_manager = TempManager::new(&job.id)?;
-    let extract_dir = temp_manager.get_extract_dir()?;
-    let staging_dir = temp_manager.get_staging_dir()?;
 
-    // Open input zip
-    let input_file = File::open(input_path)
-        .context("Failed to open input zip file")?;
-    let mut archive = ZipArchive::new(input_file)
-        .context("Failed to read zip archive")?;
+    // The output is written straight into a hidden file or folder beside its final
+    // location, then renamed into place once everything has succeeded. A dry run
+    // goes through the same steps, converting images in memory to learn their
+    // final names, and throws the results away.
+    let (partial_output, root_sink) = if dry_run {
+        (None, Sink::Discard)
+    } else {
+        fs::create_dir_all(output_dir)
+            .context(format!("Failed to create output folder {}", output_dir.display()))?;
+        match output_settings.mode {
+            OutputMode::Zip => {
+                let partial = PartialOutput::file(output_dir, &job.id);
+                let sink = Sink::zip(partial.path())?;
+                (Some(partial), sink)
+            }
+            OutputMode::Directory => {
+                let partial = PartialOutput::dir(output_dir, &job.id)?;
+                let sink = Sink::Folder(partial.path().to_path_buf());
+                (Some(partial), sink)
+            }
+        }
+    };
+
+    // Open the input and list what's in it
+    let listing = match &job.input {
+        JobInput::Zip => {
+            let input_file = File::open(input_path)
+                .context("Failed to open input zip file")?;
+            Listing::Archive(ZipArchive::new(input_file).context("Failed to read zip archive")?)
+        }
+        JobInput::Directory => Listing::Files(input::list_directory(input_path)?),
+        JobInput::Files { paths } => Listing::Files(
+            paths
+                .iter()
+                .map(|path| {
+                    let path = PathBuf::from(path);
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default();
+                    (name, path)
+                })
+                .collect(),
+        ),
+    };
 
     // Scan phase
-    state.emit_progress(
-        app,
+    reporter.report(
         &job.id,
         ProgressInfo {
             current_file: 0,
-            total_files: archive.len(),
+            total_files: listing.len(),
             current_filename: "Scanning...".to_string(),
             phase: ProcessingPhase::Scanning,
         },
     );
 
     // Check for cancellation
-    if state.cancel_flag.load(Ordering::SeqCst) {
+    if reporter.is_cancelled() {
         return Err(anyhow::anyhow!("Processing cancelled"));
     }
 
-    // Build list of image files to process
-    let mut image_entries = Vec::new();
-    let converter = ImageConverter::new();
+    // Build list of entries to process, descending into nested zips as configured
+    let converter =
+        ImageConverter::with_settings(job.options.conversion).with_derivatives(job.options.derivatives.sizes.clone());
     let mut report = ReportBuilder::new(input_path);
-
-    for i in 0..archive.len() {
-        let file = archive.by_index(i)?;
-        let file_name = file.name().to_string();
-        
-        report.increment_scanned();
-
-        // Skip directories
-        if file.is_dir() {
-            continue;
+    report.set_conversion_settings(job.options.conversion);
+    report.set_dry_run(dry_run);
+    let mut scan = Scan {
+        options: &job.options,
+        converter: &converter,
+        temp_manager: &temp_manager,
+        root_sink: Some(root_sink),
+        report: &mut report,
+        archives: Vec::new(),
+        archive_paths: Vec::new(),
+        entries: Vec::new(),
+        packages: Vec::new(),
+    };
+    scan.add_package(None)?;
+    match listing {
+        Listing::Archive(archive) => {
+            scan.archives.push(archive);
+            scan.archive_paths.push(input_path.to_path_buf());
+            scan.scan_archive(0, "", Path::new(""), 0, 0)?;
         }
+        Listing::Files(files) => scan.scan_files(files)?,
+    }
 
-        // Skip nested zips
-        if file_name.to_lowercase().ends_with(".zip") {
-            report.add_skipped(
-                file_name.clone(),
-                "Nested zip files are ignored".to_string(),
-            );
-            continue;
-        }
+    let Scan {
+        archives: mut scanned_archives,
+        archive_paths,
+        entries,
+        mut packages,
+        ..
+    } = scan;
 
-        // Check if it's an image we should process
-        let path = Path::new(&file_name);
-        if converter.should_process(path) {
-            image_entries.push((i, file_name));
-        }
+    let total_images = entries
+        .iter()
+        .filter(|entry| matches!(entry.kind, EntryKind::Image))
+        .count();
+    if total_images == 0 {
+        return Err(anyhow::anyhow!("No image files found in {}", input_path.display()));
     }
 
-    let total_images = image_entries.len();
-    if total_images == 0 {
-        return Err(anyhow::anyhow!("No image files found in zip"));
+    // Everything is written to the destination as it is processed, so check up
+    // front that it will fit. Input sizes are a rough estimate of the output size.
+    let needed_space: u64 = entries.iter().map(|entry| entry.size).sum();
+    let available_space = if dry_run { None } else { disk::available_space(output_dir) };
+    if let Some(available) = available_space.filter(|&free| free < needed_space) {
+        return Err(anyhow::anyhow!(
+            "Not enough free space in {}: about {} needed, {} available",
+            output_dir.display(),
+            disk::format_size(needed_space),
+            disk::format_size(available)
+        ));
+    }
+
+    let mut tasks = Vec::new();
+    let mut placements = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let (placement, task) = plan_entry(&converter, entry, tasks.len(), &job.options);
+        tasks.extend(task);
+        placements.push(placement);
     }
 
     // Processing phase
-    let mut collision_manager = CollisionManager::new();
-    let mut processed_files: Vec<(PathBuf, PathBuf)> = Vec::new(); // (staging_path, zip_path)
 
-    for (idx, (zip_index, file_name)) in image_entries.iter().enumerate() {
-        // Check for cancellation
-        if state.cancel_flag.load(Ordering::SeqCst) {
-            return Err(anyhow::anyhow!("Processing cancelled"));
+    // Check for cancellation
+    if reporter.is_cancelled() {
+        return Err(anyhow::anyhow!("Processing cancelled"));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let threads = job
+        .options
+        .worker_threads
+        .unwrap_or_else(worker_pool::default_thread_count);
+    let archive_paths = Arc::new(archive_paths);
+    let mut results = worker_pool::spawn(
+        tasks,
+        threads,
+        Arc::clone(&stop),
+        HashMap::new,
+        move |archives, task| run_task(&converter, &archive_paths, archives, task),
+    );
+
+    let written = write_outputs(
+        reporter,
+        &job.id,
+        &mut scanned_archives,
+        &mut packages,
+        &placements,
+        &mut results,
+        &job.options,
+    )
+    .await;
+
+    // Stop handing out work in case of an error or cancel, then wait for
+    // in-flight tasks to drain
+    stop.store(true, Ordering::SeqCst);
+    while results.recv().await.is_some() {}
+    let outcomes = written?;
+
+    // Record results in scan order
+    let total_files = placements.len();
+    let mut hashed = Vec::new();
+    for ((outcome, sorted, hashes), placement) in outcomes.into_iter().zip(placements) {
+        let output_path = match &outcome {
+            Outcome::Image { output_path, .. } | Outcome::Passthrough { output_path } => Some(output_path),
+            Outcome::Failed { copied_to, .. } => copied_to.as_ref(),
+            Outcome::Duplicate { .. } => None,
+        };
+        if let Some(hashes) = hashes {
+            hashed.push((placement.source_path.clone(), output_path.cloned(), hashes));
+        }
+        if let Some(sorted) = sorted {
+            if let Some(output_path) = output_path {
+                report.add_sorted(
+                    placement.source_path.clone(),
+                    output_path.clone(),
+                    sorted.date,
+                    sorted.source,
+                    sorted.rule,
+                );
+            }
+        }
+        if let Outcome::Image { output_path, derivatives, .. } = &outcome {
+            for derivative in derivatives {
+                report.add_derivative(
+                    placement.source_path.clone(),
+                    output_path.clone(),
+                    derivative.name.clone(),
+                    derivative.output_path.clone(),
+                    derivative.dimensions,
+                );
+            }
+        }
+        match outcome {
+            Outcome::Image { result: ConversionResult::Copied, output_path, .. } => {
+                report.add_copied(placement.source_path, output_path);
+            }
+            Outcome::Image {
+                result:
+                    ConversionResult::Converted {
+                        original_format,
+                        exif,
+                        color_profile,
+                        bit_depth,
+                        transparency,
+                        dimensions,
+                        ..
+                    },
+                output_path,
+                ..
+            } => {
+                if let Some(depth) = bit_depth {
+                    report.add_bit_depth(
+                        placement.source_path.clone(),
+                        output_path.clone(),
+                        depth.source_bits,
+                        depth.hdr,
+                        depth.output_bits,
+                        depth.dithered,
+                    );
+                }
+                report.add_dimensions(
+                    placement.source_path.clone(),
+                    output_path.clone(),
+                    dimensions.original,
+                    dimensions.output,
+                );
+                if let Some(handling) = transparency {
+                    report.add_transparency(placement.source_path.clone(), output_path.clone(), handling);
+                }
+                if let Some(profile) = color_profile {
+                    report.add_color_profile(
+                        placement.source_path.clone(),
+                        output_path.clone(),
+                        profile.name,
+                        profile.handling,
+                    );
+                }
+                report.add_conversion(
+                    placement.source_path,
+                    output_path,
+                    original_format,
+                    exif,
+                );
+            }
+            Outcome::Image {
+                result: ConversionResult::Animated { original_format, frames, handling, .. },
+                output_path,
+                ..
+            } => {
+                report.add_animated(placement.source_path, output_path, original_format, frames, handling);
+            }
+            Outcome::Image {
+                result: ConversionResult::Reoriented { orientation, trimmed, error },
+                output_path,
+                ..
+            } => {
+                report.add_reoriented(placement.source_path, output_path, orientation, trimmed, error);
+            }
+            Outcome::Image {
+                result: ConversionResult::Raw { original_format, method, metadata_preserved, dimensions },
+                output_path,
+                original_copy,
+                ..
+            } => {
+                report.add_dimensions(
+                    placement.source_path.clone(),
+                    output_path.clone(),
+                    dimensions.original,
+                    dimensions.output,
+                );
+                report.add_raw(
+                    placement.source_path,
+                    output_path,
+                    original_copy,
+                    original_format,
+                    method,
+                    metadata_preserved,
+                );
+            }
+            Outcome::Passthrough { output_path } => {
+                report.add_passthrough(placement.source_path, output_path);
+            }
+            Outcome::Failed { error, copied_to } => {
+                report.add_failed(placement.source_path, copied_to, error);
+            }
+            Outcome::Duplicate { of } => {
+                report.add_skipped(placement.source_path, format!("Duplicate of {}", of));
+            }
+        }
+    }
+    if job.options.duplicates.is_active() {
+        report_duplicates(&mut report, &hashed, job.options.duplicates);
+    }
+
+    // Packaging phase
+    reporter.report(
+        &job.id,
+        ProgressInfo {
+            current_file: total_files,
+            total_files,
+            current_filename: match output_settings.mode {
+                OutputMode::Zip => "Finishing output zip...".to_string(),
+                OutputMode::Directory => "Finishing output folder...".to_string(),
+            },
+            phase: ProcessingPhase::Packaging,
+        },
+    );
+
+    // Check for cancellation one more time
+    if reporter.is_cancelled() {
+        return Err(anyhow::anyhow!("Processing cancelled"));
+    }
+
+    // Repacked zips are always created after their parent, so finishing them in
+    // reverse order completes every inner zip before the zip that contains it
+    for idx in (1..packages.len()).rev() {
+        let (parent_idx, zip_path) = packages[idx]
+            .parent
+            .clone()
+            .expect("repacked zips always have a parent");
+        packages[idx].sink.finish()?;
+        if let Some(temp_path) = packages[idx].temp_path.clone() {
+            let mut zip_file = File::open(&temp_path)?;
+            packages[parent_idx].sink.copy_from(&zip_path, &mut zip_file)?;
         }
+    }
+
+    let report = report.build();
+    let report_json = serde_json::to_string_pretty(&report)
+        .context("Failed to serialize report")?;
+
+    let final_output_path = match output_settings.collision_policy {
+        CollisionPolicy::Suffix => next_free_path(&output_path, output_settings.mode),
+        // Skip was checked up front; if the output appeared since, replacing it is the
+        // closest thing to what the user asked for
+        CollisionPolicy::Overwrite | CollisionPolicy::Skip => output_path,
+    };
+
+    // Where the output would have gone
+    let Some(partial_output) = partial_output else {
+        return Ok(JobOutput {
+            output_path: final_output_path.to_string_lossy().to_string(),
+            report,
+        });
+    };
 
-        state.emit_progress(
-            app,
-            &job.id,
+    // Add report.json to the root of the output and close it before moving it
+    packages[0]
+        .sink
+        .write(Path::new(REPORT_FILE_NAME), report_json.as_bytes())
+        .context("Failed to write report.json")?;
+    packages[0].sink.finish()?;
+    drop(packages);
+
+    partial_output.finish(&final_output_path)?;
+
+    Ok(JobOutput {
+        output_path: final_output_path.to_string_lossy().to_string(),
+        report,
+    })
+}
+
+/// Write every placement to its package in scan order. Unchanged entries are copied
+/// directly; converted ones are taken from the workers, holding on to any that
+/// finish ahead of their turn (the pool runs at most two per worker ahead). Output
+/// names are reserved as each file is written, once its final extension (and when
+/// sorting, its capture date) is known; doing so in scan order keeps the output the
+/// same for any thread count, and makes the copy of a duplicate that's kept always
+/// the first one.
+async fn write_outputs(
+    reporter: &dyn ProgressReporter,
+    job_id: &str,
+    archives: &mut [ZipArchive<File>],
+    packages: &mut [Package],
+    placements: &[Placement],
+    results: &mut worker_pool::Results<Result<Converted>>,
+    options: &JobOptions,
+) -> Result<Vec<(Outcome, Option<SortOutcome>, Option<Hashes>)>> {
+    let error_policy = options.error_policy;
+    let total_files = placements.len();
+    let mut finished_early: HashMap<usize, Result<Converted>> = HashMap::new();
+    let mut outcomes = Vec::with_capacity(total_files);
+    // Source path of the first image with each content, when only one is kept
+    let mut first_copies: HashMap<ContentHash, String> = HashMap::new();
+
+    for (idx, placement) in placements.iter().enumerate() {
+        let package = &mut packages[placement.package];
+        let (outcome, sorted, hashes) = match placement.task {
+            Some(task_idx) => {
+                let converted = loop {
+                    if let Some(converted) = finished_early.remove(&task_idx) {
+                        break converted;
+                    }
+
+                    // Wake up regularly so a cancel is noticed even while every worker is busy
+                    let received = tokio::time::timeout(CANCEL_POLL_INTERVAL, results.recv()).await;
+                    if reporter.is_cancelled() {
+                        return Err(anyhow::anyhow!("Processing cancelled"));
+                    }
+                    match received {
+                        // Fail-fast jobs stop at the first error, whichever file it's for
+                        Ok(Some((_, Err(e)))) if error_policy == ErrorPolicy::FailFast => {
+                            return Err(e);
+                        }
+                        Ok(Some((finished_idx, result))) => {
+                            finished_early.insert(finished_idx, result);
+                        }
+                        Ok(None) => {
+                            return Err(anyhow::anyhow!("Worker exited without processing a file"));
+                        }
+                        Err(_) => {}
+                    }
+                };
+                let hashes = converted.as_ref().ok().and_then(|converted| converted.hashes);
+                let duplicate_of = match hashes {
+                    Some(hashes) if options.duplicates.policy == DuplicatePolicy::KeepOne => {
+                        match first_copies.entry(hashes.content) {
+                            hash_map::Entry::Occupied(first) => Some(first.get().clone()),
+                            hash_map::Entry::Vacant(entry) => {
+                                entry.insert(placement.source_path.clone());
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                };
+                let facts = converted.as_ref().ok().and_then(|converted| converted.facts.as_ref());
+                let (output_path, sorted) = sorted_path(options, placement, facts);
+                let outcome = match (converted, duplicate_of) {
+                    (Ok(_), Some(first)) => Outcome::Duplicate { of: first },
+                    (Ok(converted), None) => {
+                        let desired_path = match &converted.extension {
+                            Some(extension) => change_extension(&output_path, extension),
+                            None => output_path.clone(),
+                        };
+                        let (zip_path, report_path) = package.reserve(&desired_path);
+                        package
+                            .sink
+                            .write(&zip_path, &converted.data)
+                            .context(format!("Failed to write {}", report_path))?;
+
+                        let original_copy = if converted.keep_original {
+                            let (zip_path, report_path) = package.reserve(&output_path);
+                            copy_entry(archives, placement, &zip_path, &mut package.sink)?;
+                            Some(report_path)
+                        } else {
+                            None
+                        };
+
+                        // Named after the full-size image as written, so they stay
+                        // paired when it got a numeric suffix
+                        let mut derivatives = Vec::with_capacity(converted.derivatives.len());
+                        for derivative in converted.derivatives {
+                            let desired_path =
+                                options.derivatives.layout.path(&derivative.name, &zip_path, derivative.extension);
+                            let (derivative_path, output_path) = package.reserve(&desired_path);
+                            package
+                                .sink
+                                .write(&derivative_path, &derivative.data)
+                                .context(format!("Failed to write {}", output_path))?;
+                            derivatives.push(WrittenDerivative {
+                                name: derivative.name,
+                                output_path,
+                                dimensions: derivative.dimensions,
+                            });
+                        }
+
+                        Outcome::Image {
+                            result: converted.result,
+                            output_path: report_path,
+                            original_copy,
+                            derivatives,
+                        }
+                    }
+                    (Err(e), _) => {
+                        // The original keeps its own name
+                        let copied_to = if error_policy == ErrorPolicy::CopyOriginalAndReport {
+                            let (zip_path, report_path) = package.reserve(&output_path);
+                            copy_entry(archives, placement, &zip_path, &mut package.sink)?;
+                            Some(report_path)
+                        } else {
+                            None
+                        };
+                        Outcome::Failed {
+                            error: format!("{:#}", e),
+                            copied_to,
+                        }
+                    }
+                };
+                (outcome, sorted, hashes)
+            }
+            None => {
+                let (desired_path, sorted) = sorted_path(options, placement, None);
+                let (zip_path, output_path) = package.reserve(&desired_path);
+                copy_entry(archives, placement, &zip_path, &mut package.sink)?;
+                let outcome = match placement.kind {
+                    EntryKind::Image => Outcome::Image {
+                        result: ConversionResult::Copied,
+                        output_path,
+                        original_copy: None,
+                        derivatives: Vec::new(),
+                    },
+                    EntryKind::Passthrough => Outcome::Passthrough { output_path },
+                };
+                (outcome, sorted, None)
+            }
+        };
+        outcomes.push((outcome, sorted, hashes));
+
+        reporter.report(
+            job_id,
             ProgressInfo {
                 current_file: idx + 1,
-                total_files: total_images,
-                current_filename: file_name.clone(),
+                total_files,
+                current_filename: placement.source_path.clone(),
                 phase: ProcessingPhase::Converting,
             },
         );
+        if reporter.is_cancelled() {
+            return Err(anyhow::anyhow!("Processing cancelled"));
+        }
+    }
 
-        // Extract file to temp
-        let mut zip_file = archive.by_index(*zip_index)?;
-        let zip_uncompressed_size = zip_file.size();
-        let zip_compressed_size = zip_file.compressed_size();
-        let extract_path = extract_dir.join(file_name);
-        
-        if let Some(parent) = extract_path.parent() {
-            fs::create_dir_all(parent)?;
+    Ok(outcomes)
+}
+
+/// Where a placement goes once the job's sort rules and template are applied, and
+/// how it was sorted. Dates are the capture date if known, otherwise the file's
+/// modified time. Without sort settings it keeps its place.
+fn sorted_path(
+    options: &JobOptions,
+    placement: &Placement,
+    facts: Option<&FileFacts>,
+) -> (PathBuf, Option<SortOutcome>) {
+    let Some(sorting) = &options.sorting else {
+        return (placement.output_path.clone(), None);
+    };
+    let no_facts = FileFacts::default();
+    let facts = facts.unwrap_or(&no_facts);
+    let (date, source) = match (facts.photo.taken, placement.modified) {
+        (Some(date), _) => (Some(date), DateSource::Exif),
+        (None, Some(date)) => (Some(date), DateSource::Modified),
+        (None, None) => (None, DateSource::Unknown),
+    };
+    let (path, rule) = sorting.destination(&placement.output_path, Path::new(&placement.source_path), date, facts);
+    let sorted = SortOutcome {
+        date,
+        source,
+        rule: rule.map(|rule| rule.name.clone()),
+    };
+    (path, Some(sorted))
+}
+
+/// Add the job's duplicate groups to the report. `hashed` holds every image that was
+/// hashed, in scan order, as `(source_path, output_path, hashes)`. Near duplicates
+/// are looked for among the first image with each content, so exact copies don't
+/// make up a near group between themselves.
+fn report_duplicates(
+    report: &mut ReportBuilder,
+    hashed: &[(String, Option<String>, Hashes)],
+    settings: DuplicateSettings,
+) {
+    let files = |indexes: &[usize]| {
+        indexes
+            .iter()
+            .map(|&idx| (hashed[idx].0.clone(), hashed[idx].1.clone()))
+            .collect()
+    };
+
+    let contents: Vec<ContentHash> = hashed.iter().map(|(_, _, hashes)| hashes.content).collect();
+    for group in duplicates::exact_groups(&contents) {
+        report.add_duplicate_group(DuplicateKind::Exact, duplicates::to_hex(&contents[group[0]]), files(&group));
+    }
+
+    if !settings.near_duplicates {
+        return;
+    }
+    let mut seen = std::collections::HashSet::new();
+    let (firsts, perceptual): (Vec<usize>, Vec<u64>) = hashed
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, _, hashes))| seen.insert(hashes.content))
+        .filter_map(|(idx, (_, _, hashes))| Some((idx, hashes.perceptual?)))
+        .unzip();
+    for group in duplicates::near_groups(&perceptual, settings.max_distance) {
+        let indexes: Vec<usize> = group.iter().map(|&idx| firsts[idx]).collect();
+        report.add_duplicate_group(DuplicateKind::Near, format!("{:016x}", perceptual[group[0]]), files(&indexes));
+    }
+}
+
+impl Scan<'_> {
+    fn add_package(&mut self, parent: Option<(usize, PathBuf)>) -> Result<usize> {
+        let idx = self.packages.len();
+
+        // Reserve the zip's name in its parent now so report paths are final
+        let (parent, report_prefix, sink, temp_path) = match parent {
+            Some((parent_idx, desired_path)) => {
+                let parent_package = &mut self.packages[parent_idx];
+                let (zip_path, report_path) = parent_package.reserve(&desired_path);
+                let report_prefix = format!("{}/", report_path);
+
+                // Inside a folder the zip is built where it belongs; inside another
+                // zip it's built in a temp file and copied in once complete
+                let (sink_path, temp_path) = match &parent_package.sink {
+                    Sink::Folder(dir) => (Some(dir.join(&zip_path)), None),
+                    Sink::Zip(_) => {
+                        let repack_dir = self.temp_manager.create_subdir("repack")?;
+                        let temp_path = repack_dir.join(format!("{}.zip", idx));
+                        (Some(temp_path.clone()), Some(temp_path))
+                    }
+                    // Nor is anything written inside a dry run's nested zips
+                    Sink::Discard => (None, None),
+                };
+                let sink = match sink_path {
+                    Some(sink_path) => {
+                        if let Some(dir) = sink_path.parent() {
+                            fs::create_dir_all(dir)?;
+                        }
+                        Sink::zip(&sink_path)?
+                    }
+                    None => Sink::Discard,
+                };
+                (Some((parent_idx, zip_path)), report_prefix, sink, temp_path)
+            }
+            None => {
+                let sink = self.root_sink.take().expect("the root package is added first");
+                (None, String::new(), sink, None)
+            }
+        };
+
+        // The output's root also holds report.json, so no input file may take that name
+        let mut collision_manager = CollisionManager::new();
+        if parent.is_none() {
+            collision_manager.get_unique_path(Path::new(REPORT_FILE_NAME));
         }
 
-        let mut extracted_file = File::create(&extract_path)?;
-        std::io::copy(&mut zip_file, &mut extracted_file)?;
-        drop(extracted_file);
+        self.packages.push(Package {
+            sink,
+            collision_manager,
+            parent,
+            report_prefix,
+            temp_path,
+        });
+        Ok(idx)
+    }
+
+    fn scan_archive(
+        &mut self,
+        archive_idx: usize,
+        source_prefix: &str,
+        output_prefix: &Path,
+        package: usize,
+        depth: usize,
+    ) -> Result<()> {
+        for i in 0..self.archives[archive_idx].len() {
+            let file = self.archives[archive_idx].by_index(i)?;
+            let file_name = file.name().to_string();
+            let is_dir = file.is_dir();
+            drop(file);
 
-        // Determine output path and handle collisions
-        let original_path = Path::new(file_name);
-        let mut output_relative_path = original_path.to_path_buf();
+            self.report.increment_scanned();
 
-        // If converting, change extension to .jpg
-        let needs_conversion = !matches!(
-            original_path.extension().and_then(|e| e.to_str()),
-            Some("jpg") | Some("jpeg") | Some("png") | Some("gif")
-        );
+            // Skip directories
+            if is_dir {
+                continue;
+            }
 
-        if needs_conversion {
-            output_relative_path = change_extension(&output_relative_path, "jpg");
+            let source = EntrySource::Archive { archive: archive_idx, index: i };
+            self.scan_entry(source, &file_name, source_prefix, output_prefix, package, depth)?;
         }
 
-        // Get unique path (handles collisions)
-        let unique_relative_path = collision_manager.get_unique_path(&output_relative_path);
-        let staging_path = staging_dir.join(&unique_relative_path);
+        Ok(())
+    }
+
+    /// Files from a folder or loose-file input, as `(relative_path, full_path)` pairs
+    fn scan_files(&mut self, files: Vec<(String, PathBuf)>) -> Result<()> {
+        for (name, path) in files {
+            self.report.increment_scanned();
+            self.scan_entry(EntrySource::Disk(path), &name, "", Path::new(""), 0, 0)?;
+        }
 
-        // Process the image
-        match converter.process_image(&extract_path, &staging_path) {
-            Ok(ConversionResult::Copied) => {
-                report.add_copied(
-                    file_name.clone(),
-                    unique_relative_path.to_string_lossy().to_string(),
+        Ok(())
+    }
+
+    fn scan_entry(
+        &mut self,
+        source: EntrySource,
+        file_name: &str,
+        source_prefix: &str,
+        output_prefix: &Path,
+        package: usize,
+        depth: usize,
+    ) -> Result<()> {
+        let path = Path::new(file_name);
+        let source_path = format!("{}{}", source_prefix, file_name);
+
+        // Images are recognised by their contents, whatever their extension says
+        let declared = SourceFormat::from_path(path);
+        let detected = self.entry_format(&source, path);
+        let format = detected.or(declared);
+        if format.is_some_and(|format| self.converter.accepts(format)) {
+            let mut output_path = output_prefix.join(path);
+            if let Some(format) = detected.filter(|&format| declared != Some(format)) {
+                let declared_extension = path
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                self.report.add_format_mismatch(
+                    source_path.clone(),
+                    declared_extension,
+                    format.name().to_string(),
                 );
+                output_path = change_extension(&output_path, format.extension());
             }
-            Ok(ConversionResult::Converted { original_format }) => {
-                let metadata_preserved = crate::utils::metadata::MetadataHandler::format_has_exif(&original_format);
-                report.add_conversion(
-                    file_name.clone(),
-                    unique_relative_path.to_string_lossy().to_string(),
-                    original_format,
-                    metadata_preserved,
-                );
+
+            let size = self.entry_size(&source)?;
+            let modified = self.entry_modified(&source);
+            self.entries.push(ScannedEntry {
+                source,
+                name: file_name.to_string(),
+                source_path,
+                output_path,
+                package,
+                kind: EntryKind::Image,
+                format,
+                size,
+                modified,
+            });
+            return Ok(());
+        }
+
+        if is_zip(path)
+            && self.options.nested_zip_mode != NestedZipMode::Ignore
+            && depth < self.options.max_nested_depth
+        {
+            return self.scan_nested_zip(source, &source_path, &output_prefix.join(path), package, depth);
+        }
+
+        match self.options.non_image_policy {
+            NonImagePolicy::Passthrough => {
+                let size = self.entry_size(&source)?;
+                let modified = self.entry_modified(&source);
+                self.entries.push(ScannedEntry {
+                    source,
+                    name: file_name.to_string(),
+                    source_path,
+                    output_path: output_prefix.join(path),
+                    package,
+                    kind: EntryKind::Passthrough,
+                    format: None,
+                    size,
+                    modified,
+                });
             }
-            Err(e) => {
-                let extracted_size = fs::metadata(&extract_path).map(|m| m.len()).ok();
-                let extension = original_path
-                    .extension()
-                    .and_then(|ext| ext.to_str())
-                    .unwrap_or("");
-
-                eprintln!("Error processing image from zip");
-                eprintln!("  file_name: {}", file_name);
-                eprintln!("  extension: {}", extension);
-                eprintln!("  needs_conversion: {}", needs_conversion);
-                eprintln!("  extract_path: {}", extract_path.display());
-                eprintln!("  staging_path: {}", staging_path.display());
-                eprintln!("  zip_uncompressed_size: {} bytes", zip_uncompressed_size);
-                eprintln!("  zip_compressed_size: {} bytes", zip_compressed_size);
-                if let Some(size) = extracted_size {
-                    eprintln!("  extracted_size: {} bytes", size);
-                } else {
-                    eprintln!("  extracted_size: <unavailable>");
+            NonImagePolicy::DropAndReport => {
+                let reason = self.skip_reason(path);
+                self.report.add_skipped(source_path, reason);
+            }
+            NonImagePolicy::DropSilently => {
+                // Nested zips were always reported, keep doing so
+                if is_zip(path) {
+                    let reason = self.skip_reason(path);
+                    self.report.add_skipped(source_path, reason);
                 }
-                eprintln!("  error: {:#}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn scan_nested_zip(
+        &mut self,
+        source: EntrySource,
+        source_path: &str,
+        output_path: &Path,
+        package: usize,
+        depth: usize,
+    ) -> Result<()> {
+        let nested_idx = self.archives.len();
+        let nested_path = match source {
+            EntrySource::Archive { archive, index } => {
+                // ZipArchive needs a seekable reader, so the nested zip is extracted first
+                let nested_dir = self.temp_manager.create_subdir("nested")?;
+                let nested_path = nested_dir.join(format!("{}.zip", nested_idx));
+
+                let mut zip_file = self.archives[archive].by_index(index)?;
+                let mut extracted_file = File::create(&nested_path)?;
+                std::io::copy(&mut zip_file, &mut extracted_file)
+                    .with_context(|| format!("Failed to extract nested zip: {}", source_path))?;
+                nested_path
+            }
+            EntrySource::Disk(path) => path,
+        };
+
+        let nested_archive = ZipArchive::new(File::open(&nested_path)?)
+            .with_context(|| format!("Failed to read nested zip: {}", source_path))?;
+        self.archives.push(nested_archive);
+        self.archive_paths.push(nested_path);
 
-                // Fail-fast: abort on any conversion error
-                return Err(e.context(format!("Failed to process image: {}", file_name)));
+        let source_prefix = format!("{}/", source_path);
+        match self.options.nested_zip_mode {
+            NestedZipMode::Flatten => {
+                // `photos/day1.zip` becomes the folder `photos/day1/`
+                let folder = output_path.with_extension("");
+                self.scan_archive(nested_idx, &source_prefix, &folder, package, depth + 1)
             }
+            NestedZipMode::Repack => {
+                let nested_package = self.add_package(Some((package, output_path.to_path_buf())))?;
+                self.scan_archive(nested_idx, &source_prefix, Path::new(""), nested_package, depth + 1)
+            }
+            NestedZipMode::Ignore => unreachable!("ignored nested zips are never scanned"),
         }
+    }
 
-        processed_files.push((staging_path, unique_relative_path));
+    fn entry_size(&mut self, source: &EntrySource) -> Result<u64> {
+        Ok(match source {
+            EntrySource::Archive { archive, index } => self.archives[*archive].by_index_raw(*index)?.size(),
+            EntrySource::Disk(path) => fs::metadata(path)?.len(),
+        })
     }
 
-    // Packaging phase
-    state.emit_progress(
-        app,
-        &job.id,
-        ProgressInfo {
-            current_file: total_images,
-            total_files: total_images,
-            current_filename: "Creating output zip...".to_string(),
-            phase: ProcessingPhase::Packaging,
-        },
-    );
+    /// When an entry was last modified. Zip entries store a local time without a time
+    /// zone; 1980-01-01 00:00, the earliest a zip can hold, is what tools write when
+    /// they have no time at all, so it counts as unknown.
+    fn entry_modified(&mut self, source: &EntrySource) -> Option<NaiveDateTime> {
+        match source {
+            EntrySource::Archive { archive, index } => {
+                let modified = self.archives[*archive].by_index_raw(*index).ok()?.last_modified();
+                let date = NaiveDate::from_ymd_opt(modified.year().into(), modified.month().into(), modified.day().into())?
+                    .and_hms_opt(modified.hour().into(), modified.minute().into(), modified.second().into())?;
+                (date != NaiveDate::from_ymd_opt(1980, 1, 1)?.and_hms_opt(0, 0, 0)?).then_some(date)
+            }
+            EntrySource::Disk(path) => {
+                let modified = fs::metadata(path).ok()?.modified().ok()?;
+                Some(DateTime::<Local>::from(modified).naive_local())
+            }
+        }
+    }
 
-    // Check for cancellation one more time
-    if state.cancel_flag.load(Ordering::SeqCst) {
-        return Err(anyhow::anyhow!("Processing cancelled"));
+    /// Format of an entry judged by its first bytes. Entries that can't be read are
+    /// left to fail (or be copied) later, as if they weren't recognised.
+    fn entry_format(&mut self, source: &EntrySource, path: &Path) -> Option<SourceFormat> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        let read = match source {
+            EntrySource::Archive { archive, index } => self.archives[*archive]
+                .by_index(*index)
+                .ok()?
+                .take(HEADER_LEN as u64)
+                .read_to_end(&mut header),
+            EntrySource::Disk(path) => File::open(path)
+                .ok()?
+                .take(HEADER_LEN as u64)
+                .read_to_end(&mut header),
+        };
+        read.ok()?;
+        SourceFormat::detect_named(&header, path)
     }
 
-    // Create output zip in temp location
-    let temp_output_path = temp_manager.get_output_zip_path();
-    let output_file = File::create(&temp_output_path)?;
-    let mut zip_writer = zip::ZipWriter::new(output_file);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o755);
+    fn skip_reason(&self, path: &Path) -> String {
+        if !is_zip(path) {
+            "Not an image file".to_string()
+        } else if self.options.nested_zip_mode == NestedZipMode::Ignore {
+            "Nested zip files are ignored".to_string()
+        } else {
+            format!(
+                "Nested zip exceeds the maximum depth of {}",
+                self.options.max_nested_depth
+            )
+        }
+    }
+}
+
+/// Images that need converting get a task for the worker pool, numbered `next_task`.
+/// When sorting every image is read, for its capture date and what rules match on,
+/// and likewise for hashing when looking for duplicates.
+fn plan_entry(
+    converter: &ImageConverter,
+    entry: ScannedEntry,
+    next_task: usize,
+    options: &JobOptions,
+) -> (Placement, Option<Task>) {
+    let sorting = options.sorting.is_some();
+    let needs_reading = entry.format.is_some_and(|format| {
+        sorting || options.duplicates.is_active() || converter.needs_reading(format)
+    });
+
+    let task = needs_reading.then(|| Task {
+        source: entry.source.clone(),
+        name: entry.name,
+        source_path: entry.source_path.clone(),
+        read_facts: sorting,
+        duplicates: options.duplicates,
+    });
+    let placement = Placement {
+        source: entry.source,
+        kind: entry.kind,
+        package: entry.package,
+        source_path: entry.source_path,
+        output_path: entry.output_path,
+        modified: entry.modified,
+        task: needs_reading.then_some(next_task),
+    };
+    (placement, task)
+}
+
+/// Runs on a worker thread with that worker's own open archives
+fn run_task(
+    converter: &ImageConverter,
+    archive_paths: &[PathBuf],
+    archives: &mut HashMap<usize, ZipArchive<File>>,
+    task: &Task,
+) -> Result<Converted> {
+    let input = match &task.source {
+        EntrySource::Archive { archive: archive_idx, index } => {
+            let archive = match archives.entry(*archive_idx) {
+                hash_map::Entry::Occupied(entry) => entry.into_mut(),
+                hash_map::Entry::Vacant(entry) => {
+                    let file = File::open(&archive_paths[*archive_idx])?;
+                    entry.insert(ZipArchive::new(file).context("Failed to read zip archive")?)
+                }
+            };
+            read_entry(archive, *index)
+                .with_context(|| format!("Failed to read {} from zip", task.source_path))?
+        }
+        // Files on disk are read in place
+        EntrySource::Disk(path) => {
+            read_file(path).with_context(|| format!("Failed to read {}", path.display()))?
+        }
+    };
+
+    convert_bytes(converter, task, &input)
+}
+
+/// Read an image straight out of the archive. Most decoders need to seek, so the
+/// entry is read into a buffer rather than decoded from the zip stream itself.
+fn read_entry(archive: &mut ZipArchive<File>, index: usize) -> Result<Vec<u8>> {
+    let mut zip_file = archive.by_index(index)?;
+    let size = zip_file.size();
+    Ok(read_image(&mut zip_file, size)?)
+}
+
+/// Read an image from a folder or loose-file input
+fn read_file(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    read_image(&mut file, size)
+}
 
-    // Add all processed files to zip
-    for (staging_path, zip_path) in processed_files {
-        let mut file = File::open(&staging_path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
+/// Convert an image read into memory and encode it into memory, with its
+/// derivatives, and its facts and hashes when the task asks for them
+fn convert_bytes(converter: &ImageConverter, task: &Task, input: &[u8]) -> Result<Converted> {
+    let name = Path::new(&task.name);
+    let mut data = Vec::new();
+    let (result, extras) = converter
+        .process_bytes_with_extras(name, input, &mut data, task.duplicates.near_duplicates)
+        .with_context(|| format!("Failed to process image: {} ({} bytes)", task.source_path, input.len()))?;
 
-        zip_writer.start_file(zip_path.to_string_lossy(), options)?;
-        zip_writer.write_all(&buffer)?;
+    Ok(Converted {
+        facts: task.read_facts.then(|| read_facts(name, input)),
+        hashes: hash_image(task, input, extras.perceptual_hash),
+        ..Converted::new(converter, result, data, extras.derivatives)
+    })
+}
+
+/// Read an image of `size` bytes, as far as anyone can tell, into memory. Reading
+/// stops at [`MAX_IMAGE_SIZE`] whatever the size said.
+fn read_image(reader: &mut impl Read, size: u64) -> std::io::Result<Vec<u8>> {
+    let too_large = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("larger than the {} limit", disk::format_size(MAX_IMAGE_SIZE)),
+        )
+    };
+    if size > MAX_IMAGE_SIZE {
+        return Err(too_large());
     }
 
-    // Add report.json to root of zip
-    let report_json = report.to_json()?;
-    zip_writer.start_file("report.json", options)?;
-    zip_writer.write_all(report_json.as_bytes())?;
+    let mut input = Vec::with_capacity(size.min(MAX_PREALLOC) as usize);
+    reader.take(MAX_IMAGE_SIZE + 1).read_to_end(&mut input)?;
+    if input.len() as u64 > MAX_IMAGE_SIZE {
+        return Err(too_large());
+    }
+    Ok(input)
+}
 
-    zip_writer.finish()?;
+/// What sorting needs to know about an image
+fn read_facts(name: &Path, data: &[u8]) -> FileFacts {
+    let format = SourceFormat::detect_named(data, name).or_else(|| SourceFormat::from_path(name));
+    FileFacts {
+        photo: MetadataHandler::photo_info(data),
+        format: format.map(|format| format.name().to_string()),
+        size: format.and_then(|format| image_converter::upright_size(data, format)),
+    }
+}
 
-    // Move output zip to Downloads folder
-    let downloads_dir = dirs::download_dir()
-        .ok_or_else(|| anyhow::anyhow!("Could not find Downloads folder"))?;
+/// Hashes of an image, if the job looks for duplicates, with the perceptual hash
+/// made alongside its conversion. Images that can't be decoded still get a content
+/// hash.
+fn hash_image(task: &Task, data: &[u8], perceptual: Option<u64>) -> Option<Hashes> {
+    task.duplicates.is_active().then(|| Hashes {
+        content: duplicates::content_hash(data),
+        perceptual,
+    })
+}
 
-    let input_filename = input_path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-    
-    let mut output_filename = format!("{}-converted.zip", input_filename);
-    let mut final_output_path = downloads_dir.join(&output_filename);
+/// Copy an entry across unchanged to `zip_path`. Archive entries going into a zip are
+/// copied as raw compressed data, without being decompressed and compressed again.
+fn copy_entry(
+    archives: &mut [ZipArchive<File>],
+    placement: &Placement,
+    zip_path: &Path,
+    sink: &mut Sink,
+) -> Result<()> {
+    let copied = match (&placement.source, sink) {
+        (EntrySource::Archive { archive, index }, Sink::Zip(writer)) => {
+            let zip_file = archives[*archive].by_index_raw(*index)?;
+            writer
+                .raw_copy_file_rename(zip_file, zip_path.to_string_lossy())
+                .map_err(Into::into)
+        }
+        (EntrySource::Archive { archive, index }, sink) => {
+            let mut zip_file = archives[*archive].by_index(*index)?;
+            sink.copy_from(zip_path, &mut zip_file)
+        }
+        (EntrySource::Disk(path), sink) => {
+            let mut file = File::open(path)?;
+            sink.copy_from(zip_path, &mut file)
+        }
+    };
+
+    copied.with_context(|| format!("Failed to copy file: {}", placement.source_path))
+}
 
-    // Handle collisions in Downloads folder
+/// `path` if it's free, otherwise the first free one of `name-1.zip`, `name-2.zip`, ...
+/// (or `name-1`, `name-2`, ... for a folder)
+fn next_free_path(path: &Path, mode: OutputMode) -> PathBuf {
+    let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("output");
+    let base = match mode {
+        OutputMode::Zip => name.strip_suffix(".zip").unwrap_or(name),
+        OutputMode::Directory => name,
+    };
+
+    let mut candidate = path.to_path_buf();
     let mut counter = 1;
-    while final_output_path.exists() {
-        output_filename = format!("{}-converted-{}.zip", input_filename, counter);
-        final_output_path = downloads_dir.join(&output_filename);
+    while candidate.exists() {
+        let numbered = match mode {
+            OutputMode::Zip => format!("{}-{}.zip", base, counter),
+            OutputMode::Directory => format!("{}-{}", base, counter),
+        };
+        candidate = path.with_file_name(numbered);
         counter += 1;
     }
-
-    fs::copy(&temp_output_path, &final_output_path)
-        .context("Failed to copy output zip to Downloads")?;
-
-    Ok(final_output_path.to_string_lossy().to_string())
+    candidate
 }
 
 fn change_extension(path: &Path, new_ext: &str) -> PathBuf {
@@ -1,4 +1,7 @@
-use chrono::Utc;
+use crate::processor::image_converter::{ExifOutcome, ProfileHandling, TransparencyHandling};
+use crate::processor::options::{AnimationPolicy, ConversionSettings, RawPolicy};
+use crate::utils::hdr::HdrFormat;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
@@ -10,19 +13,72 @@ pub struct ProcessingReport {
     pub app_version: String,
     pub timestamp: String,
     pub input_zip: String,
+    /// Encoder settings used for every converted file in this job
+    pub conversion_settings: ConversionSettings,
+    /// Set when the job was a dry run: paths are where files would have gone
+    #[serde(default)]
+    pub dry_run: bool,
     pub stats: ReportStats,
     pub conversions: Vec<ConversionRecord>,
+    pub passed_through: Vec<PassthroughRecord>,
     pub skipped: Vec<SkippedRecord>,
+    /// Images that couldn't be converted, with a best-effort error policy
+    #[serde(default)]
+    pub failed: Vec<FailedRecord>,
+    /// Animated GIFs and WebPs, and what the animation policy did with each
+    #[serde(default)]
+    pub animated: Vec<AnimatedRecord>,
+    /// Camera RAW files, and how each was turned into an image
+    #[serde(default)]
+    pub raw: Vec<RawRecord>,
+    /// Copied JPEGs rotated upright without re-encoding
+    #[serde(default)]
+    pub reoriented: Vec<ReorientedRecord>,
+    /// Converted images that had an ICC colour profile, and what was done with it
+    #[serde(default)]
+    pub color_profiles: Vec<ColorProfileRecord>,
+    /// Converted images with more than 8 bits per channel or HDR
+    #[serde(default)]
+    pub high_bit_depth: Vec<BitDepthRecord>,
+    /// Converted images with see-through pixels, and what the transparency policy did
+    #[serde(default)]
+    pub transparency: Vec<TransparencyRecord>,
+    /// Width and height of each converted image before and after resizing
+    #[serde(default)]
+    pub dimensions: Vec<DimensionsRecord>,
+    /// Smaller copies written for the job's derivative sizes
+    #[serde(default)]
+    pub derivatives: Vec<DerivativeRecord>,
+    /// Where each file was put when the output was sorted by date or rules
+    #[serde(default)]
+    pub sorted: Vec<SortedRecord>,
+    /// Images found more than once, exactly or, when asked for, nearly
+    #[serde(default)]
+    pub duplicates: Vec<DuplicateGroup>,
+    /// Images whose contents didn't match their extension
+    #[serde(default)]
+    pub format_mismatches: Vec<FormatMismatchRecord>,
     pub metadata_notes: Vec<String>,
 }
 
+impl ProcessingReport {
+    /// Number of problems that didn't stop the job: images that couldn't be
+    /// converted, and JPEGs that couldn't be rotated and were copied as stored
+    pub fn warning_count(&self) -> usize {
+        self.failed.len() + self.reoriented.iter().filter(|r| r.error.is_some()).count()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ReportStats {
     pub files_scanned: usize,
     pub files_included: usize,
     pub files_converted: usize,
+    pub files_passed_through: usize,
     pub files_skipped: usize,
+    #[serde(default)]
+    pub files_failed: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,7 +87,17 @@ pub struct ConversionRecord {
     pub original_path: String,
     pub output_path: String,
     pub original_format: String,
-    pub metadata_preserved: bool,
+    /// `carried`, `none` when the original had no EXIF data, or `dropped` when it
+    /// couldn't be carried over
+    pub exif: ExifOutcome,
+}
+
+/// A non-image entry copied into the output unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PassthroughRecord {
+    pub original_path: String,
+    pub output_path: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,10 +107,216 @@ pub struct SkippedRecord {
     pub reason: String,
 }
 
+/// An image that couldn't be converted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedRecord {
+    pub original_path: String,
+    /// Where the original was copied to, if the error policy copies it
+    pub output_path: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnimatedRecord {
+    pub original_path: String,
+    pub output_path: String,
+    pub original_format: String,
+    pub frames: usize,
+    pub handling: AnimationPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// Where the RAW file itself was copied, if the job keeps RAW originals
+    pub original_copy_path: Option<String>,
+    pub original_format: String,
+    /// `embeddedPreview` or `develop`; files without a preview are developed even
+    /// when the preview was asked for
+    pub method: RawPolicy,
+    pub metadata_preserved: bool,
+}
+
+/// A JPEG that was copied rather than converted, with its EXIF orientation applied
+/// losslessly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReorientedRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// EXIF Orientation value the original had, 2-8
+    pub orientation: u8,
+    /// Whether a partial block row or column (at most 15 pixels) was cut off an edge
+    /// to rotate without re-encoding
+    pub trimmed: bool,
+    /// Why the JPEG couldn't be rotated, in which case it was copied unchanged
+    pub error: Option<String>,
+}
+
+/// A converted image that came with an ICC colour profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ColorProfileRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// The profile's description, e.g. `Display P3`, if it has one
+    pub profile_name: Option<String>,
+    /// `embedded`, `convertedToSrgb`, or `dropped` when neither was possible
+    pub handling: ProfileHandling,
+}
+
+/// A converted image with more than 8 bits per channel, or an HDR image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BitDepthRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// Bits per channel of the source; 32 for floating point
+    pub source_bits: u8,
+    /// `pq` and `hlg` images were tone mapped to SDR; `appleGainMap` images were
+    /// written from their SDR base image
+    pub hdr: Option<HdrFormat>,
+    /// 8, or 16 when written as 16-bit PNG
+    pub output_bits: u8,
+    pub dithered: bool,
+}
+
+/// A converted image with see-through pixels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransparencyRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// `kept`, `changedFormat` when written as PNG or lossless WebP instead of the
+    /// target format, or `composited` onto the background colour
+    pub handling: TransparencyHandling,
+}
+
+/// A converted image's size, upright, before and after the job's resize limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DimensionsRecord {
+    pub original_path: String,
+    pub output_path: String,
+    pub original_width: u32,
+    pub original_height: u32,
+    pub output_width: u32,
+    pub output_height: u32,
+}
+
+impl DimensionsRecord {
+    pub fn resized(&self) -> bool {
+        (self.original_width, self.original_height) != (self.output_width, self.output_height)
+    }
+}
+
+/// A derivative size of an image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivativeRecord {
+    /// The input entry it was made from
+    pub original_path: String,
+    /// The full-size image written for the same entry
+    pub full_size_path: String,
+    /// Name of the size, e.g. `thumb`
+    pub name: String,
+    pub output_path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Where a file was put by the job's sort rules or template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SortedRecord {
+    pub original_path: String,
+    pub output_path: String,
+    /// The date it was sorted by, e.g. `2024-05-01T14:03:00`, or `None` if it had none
+    pub date: Option<String>,
+    pub date_source: DateSource,
+    /// Name of the sort rule that placed it; `None` when it followed the template
+    #[serde(default)]
+    pub rule: Option<String>,
+}
+
+/// Where the date a file was sorted by came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DateSource {
+    /// The image's EXIF DateTimeOriginal (or DateTimeDigitized)
+    Exif,
+    /// The zip entry's or file's last modified time
+    Modified,
+    /// Neither; it went under `unknown`
+    Unknown,
+}
+
+/// Images that are the same, or look alike
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// SHA-256 of the files for an exact group, or the difference hash of the first
+    /// file for a near one, in hex
+    pub hash: String,
+    /// In input order, so the first is the copy kept when only one is
+    pub files: Vec<DuplicateFile>,
+}
+
+/// How the images in a duplicate group match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateKind {
+    /// Byte for byte the same
+    Exact,
+    /// Different files whose perceptual hashes are close. Each distinct file is
+    /// listed once; its exact copies are in their own group.
+    Near,
+}
+
+/// An image in a duplicate group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateFile {
+    pub original_path: String,
+    /// Where it was written, or `None` if it was left out as a duplicate
+    pub output_path: Option<String>,
+}
+
+/// An image whose extension names a different format than its contents, e.g. a
+/// HEIC saved as `.jpg`. It was handled as the detected format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatMismatchRecord {
+    pub original_path: String,
+    /// Extension as found, empty if the file had none
+    pub declared_extension: String,
+    pub detected_format: String,
+}
+
 pub struct ReportBuilder {
     input_zip_name: String,
+    conversion_settings: ConversionSettings,
+    dry_run: bool,
     conversions: Vec<ConversionRecord>,
+    passed_through: Vec<PassthroughRecord>,
     skipped: Vec<SkippedRecord>,
+    failed: Vec<FailedRecord>,
+    animated: Vec<AnimatedRecord>,
+    raw: Vec<RawRecord>,
+    reoriented: Vec<ReorientedRecord>,
+    color_profiles: Vec<ColorProfileRecord>,
+    high_bit_depth: Vec<BitDepthRecord>,
+    transparency: Vec<TransparencyRecord>,
+    dimensions: Vec<DimensionsRecord>,
+    derivatives: Vec<DerivativeRecord>,
+    sorted: Vec<SortedRecord>,
+    duplicates: Vec<DuplicateGroup>,
+    format_mismatches: Vec<FormatMismatchRecord>,
     files_scanned: usize,
     files_included: usize,
     files_converted: usize,
@@ -60,14 +332,37 @@ impl ReportBuilder {
 
         Self {
             input_zip_name,
+            conversion_settings: ConversionSettings::default(),
+            dry_run: false,
             conversions: Vec::new(),
+            passed_through: Vec::new(),
             skipped: Vec::new(),
+            failed: Vec::new(),
+            animated: Vec::new(),
+            raw: Vec::new(),
+            reoriented: Vec::new(),
+            color_profiles: Vec::new(),
+            high_bit_depth: Vec::new(),
+            transparency: Vec::new(),
+            dimensions: Vec::new(),
+            derivatives: Vec::new(),
+            sorted: Vec::new(),
+            duplicates: Vec::new(),
+            format_mismatches: Vec::new(),
             files_scanned: 0,
             files_included: 0,
             files_converted: 0,
         }
     }
 
+    pub fn set_conversion_settings(&mut self, settings: ConversionSettings) {
+        self.conversion_settings = settings;
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
     pub fn increment_scanned(&mut self) {
         self.files_scanned += 1;
     }
@@ -77,13 +372,13 @@ impl ReportBuilder {
         original_path: String,
         output_path: String,
         original_format: String,
-        metadata_preserved: bool,
+        exif: ExifOutcome,
     ) {
         self.conversions.push(ConversionRecord {
             original_path,
             output_path,
             original_format,
-            metadata_preserved,
+            exif,
         });
         self.files_included += 1;
         self.files_converted += 1;
@@ -94,10 +389,218 @@ impl ReportBuilder {
         // We don't add to conversions list since it was just copied
     }
 
+    pub fn add_passthrough(&mut self, original_path: String, output_path: String) {
+        self.passed_through.push(PassthroughRecord {
+            original_path,
+            output_path,
+        });
+        self.files_included += 1;
+    }
+
     pub fn add_skipped(&mut self, path: String, reason: String) {
         self.skipped.push(SkippedRecord { path, reason });
     }
 
+    pub fn add_animated(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        original_format: String,
+        frames: usize,
+        handling: AnimationPolicy,
+    ) {
+        self.animated.push(AnimatedRecord {
+            original_path,
+            output_path,
+            original_format,
+            frames,
+            handling,
+        });
+        self.files_included += 1;
+        if handling != AnimationPolicy::Keep {
+            self.files_converted += 1;
+        }
+    }
+
+    pub fn add_failed(&mut self, original_path: String, output_path: Option<String>, error: String) {
+        if output_path.is_some() {
+            self.files_included += 1;
+        }
+        self.failed.push(FailedRecord {
+            original_path,
+            output_path,
+            error,
+        });
+    }
+
+    pub fn add_raw(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        original_copy_path: Option<String>,
+        original_format: String,
+        method: RawPolicy,
+        metadata_preserved: bool,
+    ) {
+        self.files_included += 1 + usize::from(original_copy_path.is_some());
+        self.files_converted += 1;
+        self.raw.push(RawRecord {
+            original_path,
+            output_path,
+            original_copy_path,
+            original_format,
+            method,
+            metadata_preserved,
+        });
+    }
+
+    pub fn add_reoriented(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        orientation: u8,
+        trimmed: bool,
+        error: Option<String>,
+    ) {
+        self.files_included += 1;
+        self.reoriented.push(ReorientedRecord {
+            original_path,
+            output_path,
+            orientation,
+            trimmed,
+            error,
+        });
+    }
+
+    /// Record a converted image's ICC profile. The image itself is counted by
+    /// [`add_conversion`](Self::add_conversion).
+    pub fn add_color_profile(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        profile_name: Option<String>,
+        handling: ProfileHandling,
+    ) {
+        self.color_profiles.push(ColorProfileRecord {
+            original_path,
+            output_path,
+            profile_name,
+            handling,
+        });
+    }
+
+    /// Record how a converted image's bit depth was reduced or kept. The image itself
+    /// is counted by [`add_conversion`](Self::add_conversion).
+    pub fn add_bit_depth(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        source_bits: u8,
+        hdr: Option<HdrFormat>,
+        output_bits: u8,
+        dithered: bool,
+    ) {
+        self.high_bit_depth.push(BitDepthRecord {
+            original_path,
+            output_path,
+            source_bits,
+            hdr,
+            output_bits,
+            dithered,
+        });
+    }
+
+    /// Record what was done with a transparent image. The image itself is counted by
+    /// [`add_conversion`](Self::add_conversion).
+    pub fn add_transparency(&mut self, original_path: String, output_path: String, handling: TransparencyHandling) {
+        self.transparency.push(TransparencyRecord {
+            original_path,
+            output_path,
+            handling,
+        });
+    }
+
+    /// Record a converted image's size before and after resizing. The image itself
+    /// is counted by [`add_conversion`](Self::add_conversion).
+    pub fn add_dimensions(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        (original_width, original_height): (u32, u32),
+        (output_width, output_height): (u32, u32),
+    ) {
+        self.dimensions.push(DimensionsRecord {
+            original_path,
+            output_path,
+            original_width,
+            original_height,
+            output_width,
+            output_height,
+        });
+    }
+
+    /// Record a derivative written for an image. It isn't counted as a file of its own.
+    pub fn add_derivative(
+        &mut self,
+        original_path: String,
+        full_size_path: String,
+        name: String,
+        output_path: String,
+        (width, height): (u32, u32),
+    ) {
+        self.derivatives.push(DerivativeRecord {
+            original_path,
+            full_size_path,
+            name,
+            output_path,
+            width,
+            height,
+        });
+    }
+
+    /// Record where a file was put when sorting by date
+    pub fn add_sorted(
+        &mut self,
+        original_path: String,
+        output_path: String,
+        date: Option<NaiveDateTime>,
+        date_source: DateSource,
+        rule: Option<String>,
+    ) {
+        self.sorted.push(SortedRecord {
+            original_path,
+            output_path,
+            date: date.map(|date| date.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            date_source,
+            rule,
+        });
+    }
+
+    /// Record a group of duplicate images; `files` are `(original_path, output_path)` pairs
+    pub fn add_duplicate_group(&mut self, kind: DuplicateKind, hash: String, files: Vec<(String, Option<String>)>) {
+        self.duplicates.push(DuplicateGroup {
+            kind,
+            hash,
+            files: files
+                .into_iter()
+                .map(|(original_path, output_path)| DuplicateFile { original_path, output_path })
+                .collect(),
+        });
+    }
+
+    pub fn add_format_mismatch(
+        &mut self,
+        original_path: String,
+        declared_extension: String,
+        detected_format: String,
+    ) {
+        self.format_mismatches.push(FormatMismatchRecord {
+            original_path,
+            declared_extension,
+            detected_format,
+        });
+    }
+
     pub fn build(self) -> ProcessingReport {
         let mut metadata_notes = Vec::new();
         
@@ -106,29 +609,118 @@ impl ReportBuilder {
         for conv in &self.conversions {
             formats_seen.insert(conv.original_format.clone());
         }
+        for raw in &self.raw {
+            formats_seen.insert(raw.original_format.clone());
+        }
 
         for format in formats_seen {
             let note = crate::utils::metadata::MetadataHandler::get_preservation_note(&format);
             metadata_notes.push(note);
         }
 
-        // Add general note about JPEG quality
-        if self.files_converted > 0 {
-            metadata_notes.push("All converted images encoded as JPEG with quality 95".to_string());
+        let count_exif = |outcome| self.conversions.iter().filter(|conv| conv.exif == outcome).count();
+        let without_metadata = count_exif(ExifOutcome::None);
+        let dropped = count_exif(ExifOutcome::Dropped);
+        if without_metadata > 0 {
+            metadata_notes.push(format!(
+                "{} converted file(s) had no EXIF metadata to carry over",
+                without_metadata
+            ));
+        }
+        if dropped > 0 {
+            metadata_notes.push(format!(
+                "EXIF could not be carried over for {} converted file(s) (too large or failed to write)",
+                dropped
+            ));
+        }
+
+        // Add general note about the output encoding
+        let sixteen_bit = self.high_bit_depth.iter().filter(|r| r.output_bits == 16).count();
+        if self.files_converted > sixteen_bit {
+            let scope = if sixteen_bit == 0 { "All" } else { "Other" };
+            metadata_notes.push(format!(
+                "{} converted images encoded as {}",
+                scope,
+                self.conversion_settings.describe()
+            ));
+        }
+        if sixteen_bit > 0 {
+            metadata_notes.push(format!(
+                "{} image(s) with more than 8 bits per channel encoded as 16-bit PNG",
+                sixteen_bit
+            ));
+        }
+
+        let resized = self.dimensions.iter().filter(|record| record.resized()).count();
+        if resized > 0 {
+            metadata_notes.push(format!("{} image(s) resized to the job's size limits", resized));
+        }
+        if !self.derivatives.is_empty() {
+            metadata_notes.push(format!("{} derivative(s) written", self.derivatives.len()));
+        }
+        if !self.sorted.is_empty() {
+            let count = |source| self.sorted.iter().filter(|record| record.date_source == source).count();
+            metadata_notes.push(format!(
+                "Sorted by date: {} file(s) by EXIF capture date, {} by modified time, {} undated",
+                count(DateSource::Exif),
+                count(DateSource::Modified),
+                count(DateSource::Unknown)
+            ));
+            let by_rules = self.sorted.iter().filter(|record| record.rule.is_some()).count();
+            if by_rules > 0 {
+                metadata_notes.push(format!("{} file(s) placed by sort rules", by_rules));
+            }
+        }
+        let exact_groups = self.duplicates.iter().filter(|group| group.kind == DuplicateKind::Exact).count();
+        if exact_groups > 0 {
+            let left_out = self
+                .duplicates
+                .iter()
+                .flat_map(|group| &group.files)
+                .filter(|file| file.output_path.is_none())
+                .count();
+            metadata_notes.push(format!(
+                "{} group(s) of exact duplicates found, {} copy(ies) left out",
+                exact_groups, left_out
+            ));
+        }
+        let near_groups = self.duplicates.len() - exact_groups;
+        if near_groups > 0 {
+            metadata_notes.push(format!("{} group(s) of near duplicates found", near_groups));
+        }
+        if self.dry_run {
+            metadata_notes.push("Dry run: nothing was written".to_string());
         }
 
         ProcessingReport {
             app_version: APP_VERSION.to_string(),
             timestamp: Utc::now().to_rfc3339(),
             input_zip: self.input_zip_name,
+            conversion_settings: self.conversion_settings,
+            dry_run: self.dry_run,
             stats: ReportStats {
                 files_scanned: self.files_scanned,
                 files_included: self.files_included,
                 files_converted: self.files_converted,
+                files_passed_through: self.passed_through.len(),
                 files_skipped: self.skipped.len(),
+                files_failed: self.failed.len(),
             },
             conversions: self.conversions,
+            passed_through: self.passed_through,
             skipped: self.skipped,
+            failed: self.failed,
+            animated: self.animated,
+            raw: self.raw,
+            reoriented: self.reoriented,
+            color_profiles: self.color_profiles,
+            high_bit_depth: self.high_bit_depth,
+            transparency: self.transparency,
+            dimensions: self.dimensions,
+            derivatives: self.derivatives,
+            sorted: self.sorted,
+            duplicates: self.duplicates,
+            format_mismatches: self.format_mismatches,
             metadata_notes,
         }
     }
@@ -143,8 +735,23 @@ impl Clone for ReportBuilder {
     fn clone(&self) -> Self {
         Self {
             input_zip_name: self.input_zip_name.clone(),
+            conversion_settings: self.conversion_settings,
+            dry_run: self.dry_run,
             conversions: self.conversions.clone(),
+            passed_through: self.passed_through.clone(),
             skipped: self.skipped.clone(),
+            failed: self.failed.clone(),
+            animated: self.animated.clone(),
+            raw: self.raw.clone(),
+            reoriented: self.reoriented.clone(),
+            color_profiles: self.color_profiles.clone(),
+            high_bit_depth: self.high_bit_depth.clone(),
+            transparency: self.transparency.clone(),
+            dimensions: self.dimensions.clone(),
+            derivatives: self.derivatives.clone(),
+            sorted: self.sorted.clone(),
+            duplicates: self.duplicates.clone(),
+            format_mismatches: self.format_mismatches.clone(),
             files_scanned: self.files_scanned,
             files_included: self.files_included,
             files_converted: self.files_converted,
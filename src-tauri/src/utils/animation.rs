@@ -0,0 +1,87 @@
+/// Number of frames in a GIF or WebP judged by its extension, or `None` for other
+/// formats and files that aren't what their extension says. Only the container
+/// structure is read; no pixels are decoded.
+pub fn frame_count(data: &[u8], ext: &str) -> Option<usize> {
+    match ext {
+        "gif" => gif_frame_count(data),
+        "webp" => webp_frame_count(data),
+        _ => None,
+    }
+}
+
+/// Counts image descriptors. A truncated file counts the frames seen before it ends.
+pub fn gif_frame_count(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(b"GIF87a") && !data.starts_with(b"GIF89a") {
+        return None;
+    }
+
+    // Header (6 bytes) and logical screen descriptor (7 bytes), then the global colour table
+    let flags = *data.get(10)?;
+    let mut pos = 13 + color_table_size(flags);
+    let mut frames = 0;
+
+    while let Some(&block) = data.get(pos) {
+        match block {
+            // Extension: introducer, label, then data sub-blocks
+            0x21 => pos = skip_sub_blocks(data, pos + 2),
+            // Image descriptor (10 bytes), local colour table, LZW code size, image data
+            0x2C => {
+                frames += 1;
+                let flags = *data.get(pos + 9)?;
+                pos += 10 + color_table_size(flags);
+                pos = skip_sub_blocks(data, pos + 1);
+            }
+            // Trailer
+            0x3B => break,
+            _ => return None,
+        }
+    }
+
+    Some(frames)
+}
+
+/// Still images count as one frame; animations (flagged in the `VP8X` header or with
+/// an `ANIM` chunk) count their `ANMF` frame chunks.
+pub fn webp_frame_count(data: &[u8]) -> Option<usize> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let mut animated = false;
+    let mut frames = 0;
+    let mut pos = 12;
+    while let Some(header) = data.get(pos..pos + 8) {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        match &header[0..4] {
+            // Bit 1 of the extended header's flags marks an animation
+            b"VP8X" => animated |= data.get(pos + 8).is_some_and(|flags| flags & 0x02 != 0),
+            b"ANIM" => animated = true,
+            b"ANMF" => frames += 1,
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        pos = pos.saturating_add(8).saturating_add(size).saturating_add(size & 1);
+    }
+
+    Some(if animated { frames.max(1) } else { 1 })
+}
+
+/// Bytes taken by the colour table described by a GIF packed flags byte
+fn color_table_size(flags: u8) -> usize {
+    if flags & 0x80 != 0 {
+        3 << ((flags & 0x07) + 1)
+    } else {
+        0
+    }
+}
+
+/// Position just past a chain of GIF data sub-blocks starting at `pos`
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> usize {
+    while let Some(&len) = data.get(pos) {
+        pos += 1 + len as usize;
+        if len == 0 {
+            break;
+        }
+    }
+    pos
+}
@@ -0,0 +1,100 @@
+use anyhow::{anyhow, bail, Context, Result};
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel};
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformExecutor, TransformOptions};
+use std::sync::Arc;
+
+// Colour management
+// Wide-gamut sources (Display P3 HEICs from iPhones, Adobe RGB TIFFs) only look right
+// when their ICC profile is honoured. The converter either carries the profile over
+// or converts the pixels to sRGB here, through moxcms.
+
+/// The profile's description, e.g. `Display P3`
+pub fn profile_name(icc: &[u8]) -> Option<String> {
+    let profile = ColorProfile::new_from_slice(icc).ok()?;
+    let name = match profile.description? {
+        ProfileText::PlainString(text) => text,
+        ProfileText::Localizable(strings) => strings.into_iter().next()?.value,
+        ProfileText::Description(description) => description.ascii_string,
+    };
+    let name = name.trim_end_matches('\0').trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Convert an image's pixels from the colour space described by `icc` to sRGB.
+/// Images with more than 8 bits per channel stay at 16 bits. Only RGB and greyscale
+/// profiles are supported; greyscale images come back as RGB.
+pub fn to_srgb(img: &DynamicImage, icc: &[u8]) -> Result<DynamicImage> {
+    let source = ColorProfile::new_from_slice(icc).map_err(|e| anyhow!("Unreadable ICC profile: {}", e))?;
+    let gray = match source.color_space {
+        DataColorSpace::Rgb => false,
+        DataColorSpace::Gray if !img.color().has_color() => true,
+        other => bail!("Can't convert {:?} colour profiles to sRGB", other),
+    };
+    let alpha = img.color().has_alpha();
+    let (source_layout, target_layout) = match (gray, alpha) {
+        (false, false) => (Layout::Rgb, Layout::Rgb),
+        (false, true) => (Layout::Rgba, Layout::Rgba),
+        (true, false) => (Layout::Gray, Layout::Rgb),
+        (true, true) => (Layout::GrayAlpha, Layout::Rgba),
+    };
+
+    let srgb = ColorProfile::new_srgb();
+    let options = TransformOptions::default();
+    let (width, height) = img.dimensions();
+    let high_depth = img.color().bits_per_pixel() / u16::from(img.color().channel_count()) > 8;
+    let pixels = width as usize * height as usize;
+    let channels = if alpha { 4 } else { 3 };
+
+    let converted = if high_depth {
+        let samples = match (gray, alpha) {
+            (false, false) => img.to_rgb16().into_raw(),
+            (false, true) => img.to_rgba16().into_raw(),
+            (true, false) => img.to_luma16().into_raw(),
+            (true, true) => img.to_luma_alpha16().into_raw(),
+        };
+        let transform = source
+            .create_transform_16bit(source_layout, &srgb, target_layout, options)
+            .map_err(|e| anyhow!("Failed to set up colour conversion: {}", e))?;
+        let converted = run(transform, &samples, pixels, channels)?;
+        if alpha {
+            DynamicImage::ImageRgba16(buffer(width, height, converted)?)
+        } else {
+            DynamicImage::ImageRgb16(buffer(width, height, converted)?)
+        }
+    } else {
+        let samples = match (gray, alpha) {
+            (false, false) => img.to_rgb8().into_raw(),
+            (false, true) => img.to_rgba8().into_raw(),
+            (true, false) => img.to_luma8().into_raw(),
+            (true, true) => img.to_luma_alpha8().into_raw(),
+        };
+        let transform = source
+            .create_transform_8bit(source_layout, &srgb, target_layout, options)
+            .map_err(|e| anyhow!("Failed to set up colour conversion: {}", e))?;
+        let converted = run(transform, &samples, pixels, channels)?;
+        if alpha {
+            DynamicImage::ImageRgba8(buffer(width, height, converted)?)
+        } else {
+            DynamicImage::ImageRgb8(buffer(width, height, converted)?)
+        }
+    };
+    Ok(converted)
+}
+
+/// Run a transform over a whole image, producing `channels` samples per pixel
+fn run<T: Copy + Default>(
+    transform: Arc<dyn TransformExecutor<T> + Send + Sync>,
+    samples: &[T],
+    pixels: usize,
+    channels: usize,
+) -> Result<Vec<T>> {
+    let mut converted = vec![T::default(); pixels * channels];
+    transform
+        .transform(samples, &mut converted)
+        .map_err(|e| anyhow!("Colour conversion failed: {}", e))?;
+    Ok(converted)
+}
+
+fn buffer<P: Pixel>(width: u32, height: u32, samples: Vec<P::Subpixel>) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+    ImageBuffer::from_raw(width, height, samples).context("Colour conversion returned the wrong number of pixels")
+}
@@ -0,0 +1,104 @@
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+// Bit depth
+// 16-bit TIFFs and PNGs, and 10- or 12-bit HEICs and AVIFs, have to lose precision
+// for 8-bit formats. Rounding each sample turns smooth gradients (skies, skin) into
+// visible bands, so the step down is dithered with an ordered pattern instead.
+
+/// 8x8 Bayer matrix; each pixel's threshold is its entry over 64
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Bits per channel of an image as decoded: 8, 16 or 32 (floating point)
+pub fn bits_per_channel(img: &DynamicImage) -> u8 {
+    let color = img.color();
+    (color.bits_per_pixel() / u16::from(color.channel_count())) as u8
+}
+
+/// Stretch samples stored at `bits` per channel in a 16-bit image to the full 16-bit
+/// range. libheif returns 10- and 12-bit images with their values as they are, which
+/// would otherwise show almost black.
+pub fn expand_to_16_bits(img: DynamicImage, bits: u8) -> DynamicImage {
+    if !(9..16).contains(&bits) {
+        return img;
+    }
+    let max = (1u32 << bits) - 1;
+    let scale = |sample: u16| ((u32::from(sample).min(max) * 65535 + max / 2) / max) as u16;
+    match img {
+        DynamicImage::ImageLuma16(mut buf) => {
+            buf.iter_mut().for_each(|s| *s = scale(*s));
+            DynamicImage::ImageLuma16(buf)
+        }
+        DynamicImage::ImageLumaA16(mut buf) => {
+            buf.iter_mut().for_each(|s| *s = scale(*s));
+            DynamicImage::ImageLumaA16(buf)
+        }
+        DynamicImage::ImageRgb16(mut buf) => {
+            buf.iter_mut().for_each(|s| *s = scale(*s));
+            DynamicImage::ImageRgb16(buf)
+        }
+        DynamicImage::ImageRgba16(mut buf) => {
+            buf.iter_mut().for_each(|s| *s = scale(*s));
+            DynamicImage::ImageRgba16(buf)
+        }
+        other => other,
+    }
+}
+
+/// Floating-point images as 16-bit, for encoders that only take integers
+pub fn to_16_bits(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb16(img.to_rgb16()),
+        DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        other => other,
+    }
+}
+
+/// Reduce an image with more than 8 bits per channel to 8 bits with ordered
+/// dithering. Colour channels share each pixel's threshold, so the noise doesn't
+/// tint; alpha is rounded. 8-bit images are returned as they are.
+pub fn dither_to_8_bits(img: DynamicImage) -> DynamicImage {
+    if bits_per_channel(&img) <= 8 {
+        return img;
+    }
+    let color = img.color();
+    match (color.has_color(), color.has_alpha()) {
+        (true, true) => DynamicImage::ImageRgba8(dither(&img.to_rgba16(), true)),
+        (true, false) => DynamicImage::ImageRgb8(dither(&img.to_rgb16(), false)),
+        (false, true) => DynamicImage::ImageLumaA8(dither(&img.to_luma_alpha16(), true)),
+        (false, false) => DynamicImage::ImageLuma8(dither(&img.to_luma16(), false)),
+    }
+}
+
+fn dither<P, Q>(img: &ImageBuffer<P, Vec<u16>>, alpha: bool) -> ImageBuffer<Q, Vec<u8>>
+where
+    P: Pixel<Subpixel = u16>,
+    Q: Pixel<Subpixel = u8>,
+{
+    let channels = usize::from(P::CHANNEL_COUNT);
+    let width = img.width() as usize;
+    let mut samples = Vec::with_capacity(img.as_raw().len());
+    for (i, pixel) in img.as_raw().chunks_exact(channels).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let threshold = (f32::from(BAYER[y % 8][x % 8]) + 0.5) / 64.0;
+        for (c, &sample) in pixel.iter().enumerate() {
+            let exact = f32::from(sample) / 257.0;
+            let reduced = if alpha && c == channels - 1 {
+                exact.round()
+            } else {
+                (exact + threshold - 0.5).round()
+            };
+            samples.push(reduced.clamp(0.0, 255.0) as u8);
+        }
+    }
+    ImageBuffer::from_raw(img.width(), img.height(), samples)
+        .expect("one output sample per input sample")
+}
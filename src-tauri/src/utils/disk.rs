@@ -0,0 +1,70 @@
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::Path;
+
+/// Read a JSON file the app saved, or `T::default()` if there is none. A file that
+/// can't be read or parsed, say one cut short by a crash or written by a newer
+/// version, is renamed to `<name>.corrupt` so the app still starts and the old
+/// contents aren't lost.
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> T {
+    if !path.exists() {
+        return T::default();
+    }
+
+    let loaded = fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Ok(serde_json::from_str(&json)?));
+    match loaded {
+        Ok(value) => value,
+        Err(e) => {
+            let mut aside = path.as_os_str().to_owned();
+            aside.push(".corrupt");
+            eprintln!("Failed to load {}, moving it to {:?}: {:#}", path.display(), aside, e);
+            if let Err(e) = fs::rename(path, &aside) {
+                eprintln!("Failed to move {} aside: {}", path.display(), e);
+            }
+            T::default()
+        }
+    }
+}
+
+/// Bytes available to the current user on the file system holding `path`,
+/// or `None` if it can't be determined
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid NUL-terminated string and `stat` is a valid out pointer
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    #[allow(clippy::useless_conversion)] // the field types differ between platforms
+    Some(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+/// Free space isn't checked on other platforms
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Human-readable size, e.g. `1.5 GB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["bytes", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
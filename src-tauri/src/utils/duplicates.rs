@@ -0,0 +1,97 @@
+use image::DynamicImage;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+// Duplicate detection
+// Exact duplicates share a SHA-256 of their source bytes, whatever they're called.
+// Near duplicates, such as resized or re-encoded copies, are found with a difference
+// hash: the image shrunk to 9x8 grey pixels, one bit per pair of neighbours saying
+// whether the left one is brighter. Copies of a photo differ in a few of the 64
+// bits, unrelated photos in about half of them.
+
+/// SHA-256 of a file's bytes
+pub type ContentHash = [u8; 32];
+
+pub fn content_hash(data: &[u8]) -> ContentHash {
+    Sha256::digest(data).into()
+}
+
+/// Difference hash of an image, as it's shown
+pub fn difference_hash(img: &DynamicImage) -> u64 {
+    let small = img.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y).0[0] > small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// Number of bits two difference hashes differ in
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Lowercase hex, for showing hashes in the report
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Indexes of files sharing a content hash, in groups of two or more. Groups are
+/// ordered by their first file, and files within a group keep their order.
+pub fn exact_groups(hashes: &[ContentHash]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<&ContentHash, usize> = HashMap::new();
+    for (idx, hash) in hashes.iter().enumerate() {
+        match group_of.get(hash) {
+            Some(&group) => groups[group].push(idx),
+            None => {
+                group_of.insert(hash, groups.len());
+                groups.push(vec![idx]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Indexes of images whose difference hashes are at most `max_distance` bits apart,
+/// in groups of two or more. Likeness is followed through the group, so A and C
+/// share a group when both are close to B. Ordered as in [`exact_groups`].
+pub fn near_groups(hashes: &[u64], max_distance: u32) -> Vec<Vec<usize>> {
+    // Union-find over every pair; jobs hold thousands of images at most
+    let mut parent: Vec<usize> = (0..hashes.len()).collect();
+    fn root(parent: &mut [usize], mut idx: usize) -> usize {
+        while parent[idx] != idx {
+            parent[idx] = parent[parent[idx]];
+            idx = parent[idx];
+        }
+        idx
+    }
+    for a in 0..hashes.len() {
+        for b in a + 1..hashes.len() {
+            if distance(hashes[a], hashes[b]) <= max_distance {
+                let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+                // The earlier index stays the root, keeping groups in order
+                parent[root_a.max(root_b)] = root_a.min(root_b);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for idx in 0..hashes.len() {
+        let root = root(&mut parent, idx);
+        match group_of.get(&root) {
+            Some(&group) => groups[group].push(idx),
+            None => {
+                group_of.insert(root, groups.len());
+                groups.push(vec![idx]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
@@ -0,0 +1,168 @@
+use std::path::Path;
+
+/// Bytes needed from the start of a file to recognise its format
+pub const HEADER_LEN: usize = 64;
+
+/// Image formats the converter reads, recognised from a file's leading bytes
+/// rather than its extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Tiff,
+    Bmp,
+    Heic,
+    Avif,
+    /// Adobe Digital Negative
+    Dng,
+    /// Canon RAW
+    Cr2,
+    /// Nikon RAW
+    Nef,
+    /// Sony RAW
+    Arw,
+}
+
+impl SourceFormat {
+    /// Format of a file judged by its magic bytes, or `None` if it isn't one we read.
+    /// Only the first [`HEADER_LEN`] bytes are looked at.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else if data.starts_with(b"II*\0") && data.get(8..10) == Some(&b"CR"[..]) {
+            Some(Self::Cr2)
+        } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+            Some(Self::Tiff)
+        } else if is_bmp(data) {
+            Some(Self::Bmp)
+        } else {
+            detect_isobmff(data)
+        }
+    }
+
+    /// Like [`detect`](Self::detect), using the extension to tell DNG, NEF and ARW
+    /// files apart from plain TIFFs, which they are as far as their header goes
+    pub fn detect_named(data: &[u8], path: &Path) -> Option<Self> {
+        let detected = Self::detect(data)?;
+        match Self::from_path(path) {
+            Some(declared) if detected == Self::Tiff && declared.is_raw() => Some(declared),
+            _ => Some(detected),
+        }
+    }
+
+    /// Format implied by a file's extension, ignoring case
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::Webp),
+            "tiff" | "tif" => Some(Self::Tiff),
+            "bmp" => Some(Self::Bmp),
+            "heic" | "heif" => Some(Self::Heic),
+            "avif" => Some(Self::Avif),
+            "dng" => Some(Self::Dng),
+            "cr2" => Some(Self::Cr2),
+            "nef" => Some(Self::Nef),
+            "arw" => Some(Self::Arw),
+            _ => None,
+        }
+    }
+
+    /// Extension given to files in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+            Self::Tiff => "tiff",
+            Self::Bmp => "bmp",
+            Self::Heic => "heic",
+            Self::Avif => "avif",
+            Self::Dng => "dng",
+            Self::Cr2 => "cr2",
+            Self::Nef => "nef",
+            Self::Arw => "arw",
+        }
+    }
+
+    /// Name shown in the report, e.g. `JPEG`
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Jpeg => "JPEG",
+            Self::Png => "PNG",
+            Self::Gif => "GIF",
+            Self::Webp => "WEBP",
+            Self::Tiff => "TIFF",
+            Self::Bmp => "BMP",
+            Self::Heic => "HEIC",
+            Self::Avif => "AVIF",
+            Self::Dng => "DNG",
+            Self::Cr2 => "CR2",
+            Self::Nef => "NEF",
+            Self::Arw => "ARW",
+        }
+    }
+
+    /// Camera RAW formats, which are developed or replaced by their embedded preview
+    pub fn is_raw(self) -> bool {
+        matches!(self, Self::Dng | Self::Cr2 | Self::Nef | Self::Arw)
+    }
+}
+
+/// A BMP starts with `BM` and little else to go on, so its header has to add up:
+/// a file size that fits the headers, zero reserved bytes, and the size of one of
+/// the known DIB headers (OS/2 `BITMAPCOREHEADER` through `BITMAPV5HEADER`).
+fn is_bmp(data: &[u8]) -> bool {
+    let le_u32 = |at: usize| data.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    let (Some(file_size), Some(reserved), Some(dib_size)) = (le_u32(2), le_u32(6), le_u32(14)) else {
+        return false;
+    };
+    data.starts_with(b"BM")
+        && reserved == 0
+        && matches!(dib_size, 12 | 40 | 52 | 56 | 108 | 124)
+        && file_size >= 14 + dib_size
+}
+
+/// HEIC and AVIF are both ISO base media files; the brands in the leading `ftyp`
+/// box tell them apart. AVIF is checked first since AVIF files also list the
+/// generic HEIF brand `mif1`.
+fn detect_isobmff(data: &[u8]) -> Option<SourceFormat> {
+    if data.get(4..8)? != b"ftyp" {
+        return None;
+    }
+
+    let box_size = u32::from_be_bytes(data[0..4].try_into().ok()?) as usize;
+    // Major brand, minor version, then the compatible brands
+    let end = box_size.min(data.len());
+    let brands: Vec<&[u8]> = data
+        .get(8..end)?
+        .chunks_exact(4)
+        .enumerate()
+        .filter(|(i, _)| *i != 1)
+        .map(|(_, brand)| brand)
+        .collect();
+
+    if brands.iter().any(|brand| matches!(*brand, b"avif" | b"avis")) {
+        Some(SourceFormat::Avif)
+    } else if brands.iter().any(|brand| {
+        matches!(
+            *brand,
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"hevm" | b"hevs" | b"mif1" | b"msf1"
+        )
+    }) {
+        Some(SourceFormat::Heic)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,166 @@
+use image::{DynamicImage, ImageBuffer, Rgb, Rgba};
+use serde::{Deserialize, Serialize};
+
+// HDR tone mapping
+// HDR HEICs and AVIFs store brightness on an absolute (PQ) or scene-referred (HLG)
+// scale, usually with BT.2020 primaries. Shown as if they were sRGB they look flat
+// and grey, so they are mapped down to SDR here: decoded to linear light, brought
+// into sRGB primaries, highlights compressed above SDR white, and encoded with the
+// sRGB curve.
+//
+// Apple's HDR photos work the other way round: the main image is already an SDR
+// rendering, made by the camera, and an auxiliary gain map says how much brighter
+// each area gets on an HDR display. For SDR output the main image is used as is.
+
+/// Auxiliary image type of an Apple HDR gain map
+pub const APPLE_GAIN_MAP: &str = "urn:com:apple:photo:2020:aux:hdrgainmap";
+
+/// SDR reference white in nits (ITU-R BT.2408)
+const SDR_WHITE_NITS: f32 = 203.0;
+/// Peak brightness assumed for PQ images, which don't say how bright they were graded
+const PQ_PEAK_NITS: f32 = 1000.0;
+/// Nominal peak of an HLG display
+const HLG_PEAK_NITS: f32 = 1000.0;
+/// Fraction of SDR white below which brightness is left alone
+const KNEE: f32 = 0.7;
+
+/// How an HDR source encodes its highlights
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HdrFormat {
+    /// SMPTE ST 2084 perceptual quantiser
+    Pq,
+    /// Hybrid log-gamma (ARIB STD-B67)
+    Hlg,
+    /// An SDR image with an Apple gain map
+    AppleGainMap,
+}
+
+/// Colour primaries of an HDR source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primaries {
+    Bt709,
+    DisplayP3,
+    Bt2020,
+}
+
+impl Primaries {
+    /// Linear-light matrix into BT.709 / sRGB primaries
+    fn to_bt709(self) -> [[f32; 3]; 3] {
+        match self {
+            Primaries::Bt709 => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Primaries::DisplayP3 => [
+                [1.2249, -0.2247, 0.0],
+                [-0.0420, 1.0419, 0.0],
+                [-0.0197, -0.0786, 1.0979],
+            ],
+            Primaries::Bt2020 => [
+                [1.6605, -0.5876, -0.0728],
+                [-0.1246, 1.1329, -0.0083],
+                [-0.0182, -0.1006, 1.1187],
+            ],
+        }
+    }
+}
+
+/// Map a PQ or HLG image to SDR sRGB. The result is 16-bit so the bit depth step
+/// that follows can dither it.
+pub fn tone_map(img: &DynamicImage, format: HdrFormat, primaries: Primaries) -> DynamicImage {
+    let peak = match format {
+        HdrFormat::Pq => PQ_PEAK_NITS,
+        HdrFormat::Hlg => HLG_PEAK_NITS,
+        // Gain-mapped images are SDR already
+        HdrFormat::AppleGainMap => return img.clone(),
+    };
+    let matrix = primaries.to_bt709();
+    let white = peak / SDR_WHITE_NITS;
+
+    let map = |rgb: [u16; 3]| -> [u16; 3] {
+        let [r, g, b] = rgb.map(|s| f32::from(s) / 65535.0);
+        // Display light in nits
+        let nits = match format {
+            HdrFormat::Hlg => hlg_to_nits([r, g, b]),
+            _ => [r, g, b].map(pq_to_nits),
+        };
+        let linear = multiply(matrix, nits.map(|n| n / SDR_WHITE_NITS));
+
+        // Scaled on luminance, so hues stay put while highlights roll off
+        let luminance = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+        let scale = if luminance > 0.0 {
+            compress(luminance, white) / luminance
+        } else {
+            0.0
+        };
+        linear.map(|c| (srgb_encode((c * scale).clamp(0.0, 1.0)) * 65535.0).round() as u16)
+    };
+
+    let (width, height) = (img.width(), img.height());
+    if img.color().has_alpha() {
+        let rgba = img.to_rgba16();
+        DynamicImage::ImageRgba16(ImageBuffer::from_fn(width, height, |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let [r, g, b] = map([r, g, b]);
+            Rgba([r, g, b, a])
+        }))
+    } else {
+        let rgb = img.to_rgb16();
+        DynamicImage::ImageRgb16(ImageBuffer::from_fn(width, height, |x, y| Rgb(map(rgb.get_pixel(x, y).0))))
+    }
+}
+
+/// Leave luminance below the knee alone and squeeze everything from there up to
+/// `white` (the peak, relative to SDR white) into what's left below 1, with an
+/// extended Reinhard curve that meets the straight part smoothly
+fn compress(luminance: f32, white: f32) -> f32 {
+    if luminance <= KNEE {
+        return luminance;
+    }
+    let range = 1.0 - KNEE;
+    let t = (luminance - KNEE) / range;
+    let t_white = (white - KNEE) / range;
+    KNEE + range * t * (1.0 + t / (t_white * t_white)) / (1.0 + t)
+}
+
+/// SMPTE ST 2084 EOTF
+fn pq_to_nits(signal: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let p = signal.powf(1.0 / M2);
+    let linear = ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1);
+    linear * 10000.0
+}
+
+/// HLG inverse OETF and OOTF (BT.2100) for a display with the nominal peak
+fn hlg_to_nits(signal: [f32; 3]) -> [f32; 3] {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+    const SYSTEM_GAMMA: f32 = 1.2;
+
+    let scene = signal.map(|e| {
+        if e <= 0.5 {
+            e * e / 3.0
+        } else {
+            (((e - C) / A).exp() + B) / 12.0
+        }
+    });
+    let luminance = 0.2627 * scene[0] + 0.6780 * scene[1] + 0.0593 * scene[2];
+    let gain = HLG_PEAK_NITS * luminance.max(0.0).powf(SYSTEM_GAMMA - 1.0);
+    scene.map(|s| s * gain)
+}
+
+fn srgb_encode(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn multiply(matrix: [[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
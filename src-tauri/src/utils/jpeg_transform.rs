@@ -0,0 +1,938 @@
+use anyhow::{bail, Context, Result};
+use image::metadata::Orientation;
+
+// Lossless JPEG rotation
+// Decoding a JPEG, rotating the pixels and encoding it again loses quality. Instead
+// the quantised DCT coefficients are moved between blocks, and transposed or
+// sign-flipped within them, the same transforms jpegtran makes; the coefficients are
+// then written back out with freshly optimised Huffman tables. Only sequential
+// Huffman-coded 8-bit JPEGs (what cameras and phones write) are handled.
+
+/// Natural (row-major) index of each coefficient in zigzag order
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+const DHT: u8 = 0xC4;
+const DRI: u8 = 0xDD;
+const APP1: u8 = 0xE1;
+
+/// A JPEG rotated or flipped into its upright orientation
+pub struct Transformed {
+    pub data: Vec<u8>,
+    /// Whether a partial block row or column was cut off an edge. Blocks can only be
+    /// moved whole, so an edge that ends mid-block can't be mirrored losslessly; like
+    /// `jpegtran -trim`, those few pixels are dropped.
+    pub trimmed: bool,
+}
+
+/// EXIF orientation of a JPEG, from its APP1 segment. Files without one are upright.
+pub fn orientation(data: &[u8]) -> Orientation {
+    segments(data)
+        .take_while(|(marker, _)| *marker != SOS)
+        .find_map(|(marker, payload)| match marker {
+            APP1 => Orientation::from_exif_chunk(payload.strip_prefix(b"Exif\0\0")?),
+            _ => None,
+        })
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+/// Apply `orientation` to a JPEG's coefficients so it displays upright with the EXIF
+/// Orientation tag reset to 1. Other metadata segments are kept as they are.
+pub fn transform(data: &[u8], orientation: Orientation) -> Result<Transformed> {
+    let jpeg = Jpeg::decode(data)?;
+    let (flip_x, flip_y, transpose) = match orientation {
+        Orientation::NoTransforms => (false, false, false),
+        Orientation::FlipHorizontal => (true, false, false),
+        Orientation::Rotate180 => (true, true, false),
+        Orientation::FlipVertical => (false, true, false),
+        Orientation::Rotate90FlipH => (false, false, true),
+        Orientation::Rotate90 => (false, true, true),
+        Orientation::Rotate270FlipH => (true, true, true),
+        Orientation::Rotate270 => (true, false, true),
+    };
+    let (transformed, trimmed) = jpeg.transform(flip_x, flip_y, transpose)?;
+    Ok(Transformed {
+        data: transformed.encode()?,
+        trimmed,
+    })
+}
+
+/// Marker segments up to the first scan's entropy-coded data, as (marker, payload)
+fn segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut pos = if data.starts_with(&[0xFF, SOI]) { 2 } else { data.len() };
+    std::iter::from_fn(move || {
+        // Fill bytes may precede a marker
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        if data.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        if marker == EOI {
+            return None;
+        }
+        let len = usize::from(u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]));
+        let payload = data.get(pos + 4..pos + 2 + len.max(2))?;
+        // The entropy-coded data after a scan header isn't a segment
+        pos = if marker == SOS { data.len() } else { pos + 2 + len };
+        Some((marker, payload))
+    })
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    /// Blocks across and down the component, padded out to whole MCUs
+    blocks_w: usize,
+    blocks_h: usize,
+    /// Quantised coefficients of each block in natural order, row by row
+    blocks: Vec<[i16; 64]>,
+}
+
+impl Component {
+    fn block_mut(&mut self, x: usize, y: usize) -> &mut [i16; 64] {
+        &mut self.blocks[y * self.blocks_w + x]
+    }
+}
+
+struct Jpeg {
+    /// SOF0 or SOF1
+    frame_marker: u8,
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    /// Quantisation tables in natural order, with the precision byte they were stored with
+    quant_tables: [Option<(u8, [u16; 64])>; 4],
+    /// APPn and COM segments, re-emitted as they were apart from the EXIF orientation
+    metadata: Vec<(u8, Vec<u8>)>,
+}
+
+impl Jpeg {
+    fn decode(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(&[0xFF, SOI]) {
+            bail!("Not a JPEG file");
+        }
+
+        let mut jpeg = Jpeg {
+            frame_marker: 0,
+            width: 0,
+            height: 0,
+            components: Vec::new(),
+            quant_tables: [None; 4],
+            metadata: Vec::new(),
+        };
+        let mut dc_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffmanTable>; 4] = Default::default();
+        let mut restart_interval = 0;
+        let mut scanned = false;
+
+        let mut pos = 2;
+        loop {
+            while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+                pos += 1;
+            }
+            if data.get(pos) != Some(&0xFF) {
+                bail!("Corrupt JPEG marker at byte {}", pos);
+            }
+            let marker = *data.get(pos + 1).context("JPEG ends without an EOI marker")?;
+            if marker == EOI {
+                break;
+            }
+            let len = data
+                .get(pos + 2..pos + 4)
+                .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+                .filter(|&len| len >= 2)
+                .context("Truncated JPEG segment")?;
+            let payload = data.get(pos + 4..pos + 2 + len).context("Truncated JPEG segment")?;
+            pos += 2 + len;
+
+            match marker {
+                0xC0 | 0xC1 => jpeg.read_frame(marker, payload, data.len() - pos)?,
+                0xC2 => bail!("Progressive JPEGs can't be rotated losslessly"),
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    bail!("Only baseline and extended sequential JPEGs can be rotated losslessly")
+                }
+                DHT => read_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+                DQT => jpeg.read_quant_tables(payload)?,
+                DRI => {
+                    let value = payload.get(0..2).context("Truncated restart interval")?;
+                    restart_interval = usize::from(u16::from_be_bytes([value[0], value[1]]));
+                }
+                SOS => {
+                    if jpeg.components.is_empty() {
+                        bail!("JPEG scan before its frame header");
+                    }
+                    let mut reader = BitReader::new(data, pos);
+                    jpeg.read_scan(payload, &dc_tables, &ac_tables, restart_interval, &mut reader)?;
+                    pos = next_marker(data, reader.pos);
+                    scanned = true;
+                }
+                0xE0..=0xEF | 0xFE if !scanned => jpeg.metadata.push((marker, payload.to_vec())),
+                _ => {}
+            }
+        }
+
+        if !scanned {
+            bail!("JPEG has no image data");
+        }
+        if jpeg.components.iter().any(|c| jpeg.quant_tables[c.quant_table].is_none()) {
+            bail!("JPEG component has no quantisation table");
+        }
+        Ok(jpeg)
+    }
+
+    /// Read a frame header and allocate its blocks. `data_left` is how many bytes of
+    /// the file follow it.
+    fn read_frame(&mut self, marker: u8, payload: &[u8], data_left: usize) -> Result<()> {
+        if !self.components.is_empty() {
+            bail!("JPEG has more than one frame");
+        }
+        let header = payload.get(0..6).context("Truncated JPEG frame header")?;
+        if header[0] != 8 {
+            bail!("{}-bit JPEGs can't be rotated losslessly", header[0]);
+        }
+        self.frame_marker = marker;
+        self.height = usize::from(u16::from_be_bytes([header[1], header[2]]));
+        self.width = usize::from(u16::from_be_bytes([header[3], header[4]]));
+        if self.width == 0 || self.height == 0 {
+            bail!("JPEG has no dimensions in its frame header");
+        }
+
+        let count = usize::from(header[5]);
+        let specs = payload.get(6..6 + 3 * count).context("Truncated JPEG frame header")?;
+        for spec in specs.chunks_exact(3) {
+            let (h, v) = (usize::from(spec[1] >> 4), usize::from(spec[1] & 15));
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+                bail!("Invalid JPEG component");
+            }
+            self.components.push(Component {
+                id: spec[0],
+                h,
+                v,
+                quant_table: usize::from(spec[2]),
+                blocks_w: 0,
+                blocks_h: 0,
+                blocks: Vec::new(),
+            });
+        }
+
+        // Every block takes at least two bits of entropy-coded data, so a header
+        // claiming more blocks than the rest of the file can hold is corrupt
+        let coded_blocks: usize = self
+            .components
+            .iter()
+            .map(|c| {
+                let (blocks_x, blocks_y) = self.component_blocks(c);
+                blocks_x * blocks_y
+            })
+            .sum();
+        if coded_blocks > data_left.saturating_mul(4) {
+            bail!("{}x{} JPEG frame is larger than its data", self.width, self.height);
+        }
+
+        let (mcus_x, mcus_y) = self.mcus();
+        for component in &mut self.components {
+            component.blocks_w = mcus_x * component.h;
+            component.blocks_h = mcus_y * component.v;
+            component.blocks = vec![[0; 64]; component.blocks_w * component.blocks_h];
+        }
+        Ok(())
+    }
+
+    fn read_quant_tables(&mut self, mut payload: &[u8]) -> Result<()> {
+        while let Some((&spec, rest)) = payload.split_first() {
+            let (precision, id) = (spec >> 4, usize::from(spec & 15));
+            let size = if precision == 0 { 64 } else { 128 };
+            let values = rest.get(..size).filter(|_| id < 4).context("Invalid quantisation table")?;
+            let mut table = [0u16; 64];
+            for (k, &natural) in ZIGZAG.iter().enumerate() {
+                table[natural] = if precision == 0 {
+                    u16::from(values[k])
+                } else {
+                    u16::from_be_bytes([values[2 * k], values[2 * k + 1]])
+                };
+            }
+            self.quant_tables[id] = Some((precision, table));
+            payload = &rest[size..];
+        }
+        Ok(())
+    }
+
+    fn read_scan(
+        &mut self,
+        header: &[u8],
+        dc_tables: &[Option<HuffmanTable>; 4],
+        ac_tables: &[Option<HuffmanTable>; 4],
+        restart_interval: usize,
+        reader: &mut BitReader,
+    ) -> Result<()> {
+        let count = usize::from(*header.first().context("Truncated JPEG scan header")?);
+        let specs = header.get(1..1 + 2 * count).context("Truncated JPEG scan header")?;
+        let sequential = header.get(1 + 2 * count..4 + 2 * count) == Some(&[0, 63, 0][..]);
+        if count == 0 || !sequential {
+            bail!("Invalid JPEG scan header");
+        }
+
+        let mut scan = Vec::with_capacity(count);
+        for spec in specs.chunks_exact(2) {
+            let index = self
+                .components
+                .iter()
+                .position(|c| c.id == spec[0])
+                .context("JPEG scan refers to an unknown component")?;
+            let dc = dc_tables[usize::from(spec[1] >> 4) & 3].as_ref().context("Missing DC Huffman table")?;
+            let ac = ac_tables[usize::from(spec[1] & 15) & 3].as_ref().context("Missing AC Huffman table")?;
+            scan.push((index, dc, ac));
+        }
+        let mut predictions = vec![0; scan.len()];
+
+        // A single-component scan covers only the component's own blocks, not whole MCUs
+        let (units_x, units_y) = if let [(index, _, _)] = scan[..] {
+            self.component_blocks(&self.components[index])
+        } else {
+            self.mcus()
+        };
+
+        for unit in 0..units_x * units_y {
+            if restart_interval > 0 && unit > 0 && unit % restart_interval == 0 {
+                reader.restart()?;
+                predictions.fill(0);
+            }
+            let (unit_x, unit_y) = (unit % units_x, unit / units_x);
+            for (slot, &(index, dc, ac)) in scan.iter().enumerate() {
+                let component = &mut self.components[index];
+                let (h, v) = if scan.len() == 1 { (1, 1) } else { (component.h, component.v) };
+                for y in 0..v {
+                    for x in 0..h {
+                        let block = component.block_mut(unit_x * h + x, unit_y * v + y);
+                        decode_block(reader, dc, ac, &mut predictions[slot], block)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks across and down that hold a component's pixels, without MCU padding
+    fn component_blocks(&self, component: &Component) -> (usize, usize) {
+        let (max_h, max_v) = self.max_sampling();
+        (
+            (self.width * component.h).div_ceil(max_h).div_ceil(8),
+            (self.height * component.v).div_ceil(max_v).div_ceil(8),
+        )
+    }
+
+    fn max_sampling(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h, v)
+    }
+
+    /// MCUs across and down the image
+    fn mcus(&self) -> (usize, usize) {
+        let (max_h, max_v) = self.max_sampling();
+        (self.width.div_ceil(8 * max_h), self.height.div_ceil(8 * max_v))
+    }
+    /// Mirror the source horizontally and/or vertically, then optionally swap its
+    /// axes. Returns the new image and whether a partial edge had to be trimmed.
+    fn transform(self, flip_x: bool, flip_y: bool, transpose: bool) -> Result<(Self, bool)> {
+        // A mirrored axis is cut down to whole MCUs, so no padding ends up on the far side
+        let (max_h, max_v) = self.max_sampling();
+        let (mcus_x, mcus_y) = (self.width / (8 * max_h), self.height / (8 * max_v));
+        let width = if flip_x { mcus_x * 8 * max_h } else { self.width };
+        let height = if flip_y { mcus_y * 8 * max_v } else { self.height };
+        if width == 0 || height == 0 {
+            bail!("{}x{} JPEG is too small to rotate losslessly", self.width, self.height);
+        }
+        let trimmed = width != self.width || height != self.height;
+
+        let components = self
+            .components
+            .into_iter()
+            .map(|source| {
+                let used_w = if flip_x { mcus_x * source.h } else { source.blocks_w };
+                let used_h = if flip_y { mcus_y * source.v } else { source.blocks_h };
+                let (blocks_w, blocks_h) = if transpose { (used_h, used_w) } else { (used_w, used_h) };
+
+                let mut blocks = Vec::with_capacity(blocks_w * blocks_h);
+                for y in 0..blocks_h {
+                    for x in 0..blocks_w {
+                        let (x, y) = if transpose { (y, x) } else { (x, y) };
+                        let x = if flip_x { used_w - 1 - x } else { x };
+                        let y = if flip_y { used_h - 1 - y } else { y };
+                        blocks.push(transform_block(&source.blocks[y * source.blocks_w + x], flip_x, flip_y, transpose));
+                    }
+                }
+
+                let (h, v) = if transpose { (source.v, source.h) } else { (source.h, source.v) };
+                Component {
+                    id: source.id,
+                    h,
+                    v,
+                    quant_table: source.quant_table,
+                    blocks_w,
+                    blocks_h,
+                    blocks,
+                }
+            })
+            .collect();
+
+        // Coefficient (u, v) moves to (v, u), so its quantiser has to move with it
+        let quant_tables = self.quant_tables.map(|table| {
+            table.map(|(precision, values)| {
+                if transpose {
+                    (precision, std::array::from_fn(|i| values[(i % 8) * 8 + i / 8]))
+                } else {
+                    (precision, values)
+                }
+            })
+        });
+
+        let (width, height) = if transpose { (height, width) } else { (width, height) };
+        let jpeg = Jpeg {
+            frame_marker: self.frame_marker,
+            width,
+            height,
+            components,
+            quant_tables,
+            metadata: self.metadata,
+        };
+        Ok((jpeg, trimmed))
+    }
+
+    /// Write the image back out as a single interleaved scan with optimised Huffman tables
+    fn encode(&self) -> Result<Vec<u8>> {
+        let blocks_per_mcu: usize = self.components.iter().map(|c| c.h * c.v).sum();
+        if self.components.len() > 1 && blocks_per_mcu > 10 {
+            bail!("JPEG sampling factors are too large for a single scan");
+        }
+        let (Ok(width), Ok(height)) = (u16::try_from(self.width), u16::try_from(self.height)) else {
+            bail!("JPEG is too large");
+        };
+
+        // The first component (luma) gets table 0, the rest share table 1
+        let table_of = |index: usize| usize::from(index > 0);
+        let mut dc_counts = [[0u32; 257]; 2];
+        let mut ac_counts = [[0u32; 257]; 2];
+        self.for_each_symbol(|index, symbol| {
+            match symbol {
+                Symbol::Dc(size) => dc_counts[table_of(index)][usize::from(size)] += 1,
+                Symbol::Ac(code) => ac_counts[table_of(index)][usize::from(code)] += 1,
+                Symbol::Bits(..) => {}
+            }
+        });
+        let dc_tables = dc_counts.map(|counts| counts.iter().any(|&c| c > 0).then(|| optimal_table(&counts)));
+        let ac_tables = ac_counts.map(|counts| counts.iter().any(|&c| c > 0).then(|| optimal_table(&counts)));
+
+        let mut out = vec![0xFF, SOI];
+        for (marker, payload) in &self.metadata {
+            let mut payload = payload.clone();
+            if *marker == APP1 && payload.starts_with(b"Exif\0\0") {
+                let _ = Orientation::remove_from_exif_chunk(&mut payload[6..]);
+            }
+            write_segment(&mut out, *marker, &payload)?;
+        }
+
+        let mut dqt = Vec::new();
+        for (id, table) in self.quant_tables.iter().enumerate() {
+            let Some((precision, values)) = table else {
+                continue;
+            };
+            dqt.push((precision << 4) | id as u8);
+            for natural in ZIGZAG {
+                if *precision == 0 {
+                    dqt.push(values[natural] as u8);
+                } else {
+                    dqt.extend_from_slice(&values[natural].to_be_bytes());
+                }
+            }
+        }
+        write_segment(&mut out, DQT, &dqt)?;
+
+        let mut sof = vec![8];
+        sof.extend_from_slice(&height.to_be_bytes());
+        sof.extend_from_slice(&width.to_be_bytes());
+        sof.push(self.components.len() as u8);
+        for component in &self.components {
+            sof.extend_from_slice(&[component.id, (component.h << 4 | component.v) as u8, component.quant_table as u8]);
+        }
+        write_segment(&mut out, self.frame_marker, &sof)?;
+
+        let mut dht = Vec::new();
+        for (class, tables) in [(0u8, &dc_tables), (1, &ac_tables)] {
+            for (id, table) in tables.iter().enumerate() {
+                if let Some(table) = table {
+                    dht.push(class << 4 | id as u8);
+                    dht.extend_from_slice(&table.counts);
+                    dht.extend_from_slice(&table.values);
+                }
+            }
+        }
+        write_segment(&mut out, DHT, &dht)?;
+
+        let mut sos = vec![self.components.len() as u8];
+        for (index, component) in self.components.iter().enumerate() {
+            let table = table_of(index) as u8;
+            sos.extend_from_slice(&[component.id, table << 4 | table]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        write_segment(&mut out, SOS, &sos)?;
+
+        let dc_codes = dc_tables.map(|table| table.map(|table| table.codes()));
+        let ac_codes = ac_tables.map(|table| table.map(|table| table.codes()));
+        let mut writer = BitWriter { out, bits: 0, count: 0 };
+        self.for_each_symbol(|index, symbol| {
+            let table = table_of(index);
+            let (code, length) = match symbol {
+                Symbol::Dc(size) => dc_codes[table].as_ref().map_or((0, 0), |codes| codes[usize::from(size)]),
+                Symbol::Ac(code) => ac_codes[table].as_ref().map_or((0, 0), |codes| codes[usize::from(code)]),
+                Symbol::Bits(value, length) => (value, length),
+            };
+            writer.write(code, length);
+        });
+        let mut out = writer.finish();
+        out.extend_from_slice(&[0xFF, EOI]);
+        Ok(out)
+    }
+
+    /// Walk the entropy-coded symbols of a single interleaved scan (or a non-interleaved
+    /// one for greyscale), in the order they are written
+    fn for_each_symbol(&self, mut emit: impl FnMut(usize, Symbol)) {
+        let mut predictions = vec![0i32; self.components.len()];
+        let mut emit_block = |index: usize, block: &[i16; 64]| {
+            let diff = i32::from(block[0]) - predictions[index];
+            predictions[index] = i32::from(block[0]);
+            let size = bit_size(diff);
+            emit(index, Symbol::Dc(size));
+            emit(index, Symbol::Bits(magnitude_bits(diff, size), size));
+
+            let mut run = 0;
+            for &natural in &ZIGZAG[1..] {
+                let value = i32::from(block[natural]);
+                if value == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    emit(index, Symbol::Ac(0xF0));
+                    run -= 16;
+                }
+                let size = bit_size(value);
+                emit(index, Symbol::Ac(run << 4 | size));
+                emit(index, Symbol::Bits(magnitude_bits(value, size), size));
+                run = 0;
+            }
+            if run > 0 {
+                emit(index, Symbol::Ac(0x00));
+            }
+        };
+
+        if let [component] = &self.components[..] {
+            let (width, height) = (self.width.div_ceil(8), self.height.div_ceil(8));
+            for y in 0..height {
+                for x in 0..width {
+                    emit_block(0, &component.blocks[y * component.blocks_w + x]);
+                }
+            }
+            return;
+        }
+
+        let (mcus_x, mcus_y) = self.mcus();
+        for mcu_y in 0..mcus_y {
+            for mcu_x in 0..mcus_x {
+                for (index, component) in self.components.iter().enumerate() {
+                    for v in 0..component.v {
+                        for h in 0..component.h {
+                            let (x, y) = (mcu_x * component.h + h, mcu_y * component.v + v);
+                            emit_block(index, &component.blocks[y * component.blocks_w + x]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One step of the entropy-coded data: a DC size category, an AC run/size byte, or
+/// the raw magnitude bits that follow either
+#[derive(Clone, Copy)]
+enum Symbol {
+    Dc(u8),
+    Ac(u8),
+    Bits(u16, u8),
+}
+
+fn transform_block(block: &[i16; 64], flip_x: bool, flip_y: bool, transpose: bool) -> [i16; 64] {
+    let mut out = [0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            // Mirroring an axis negates that axis's odd frequencies
+            let mut value = block[v * 8 + u];
+            if (flip_x && u % 2 == 1) != (flip_y && v % 2 == 1) {
+                value = -value;
+            }
+            let target = if transpose { u * 8 + v } else { v * 8 + u };
+            out[target] = value;
+        }
+    }
+    out
+}
+
+fn decode_block(
+    reader: &mut BitReader,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+    prediction: &mut i32,
+    block: &mut [i16; 64],
+) -> Result<()> {
+    let size = reader.decode(dc)?;
+    *prediction += reader.receive_extend(size)?;
+    block[0] = coefficient(*prediction).context("Corrupt DC coefficient")?;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = reader.decode(ac)?;
+        let (run, size) = (usize::from(symbol >> 4), symbol & 15);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        let natural = *ZIGZAG.get(k).context("Corrupt AC coefficients")?;
+        block[natural] = coefficient(reader.receive_extend(size)?).context("Corrupt AC coefficient")?;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// A decoded coefficient, if it can be negated by a flip without overflowing
+fn coefficient(value: i32) -> Option<i16> {
+    i16::try_from(value).ok().filter(|&value| value != i16::MIN)
+}
+
+/// Number of bits needed for a coefficient's magnitude, its JPEG size category
+fn bit_size(value: i32) -> u8 {
+    (32 - value.unsigned_abs().leading_zeros()) as u8
+}
+
+/// The low `size` bits written after a size category; negative values are stored
+/// as one less than their two's complement
+fn magnitude_bits(value: i32, size: u8) -> u16 {
+    let value = if value < 0 { value - 1 } else { value };
+    (value & ((1 << size) - 1)) as u16
+}
+
+fn write_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) -> Result<()> {
+    let len = u16::try_from(payload.len() + 2).context("JPEG segment is too large")?;
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+/// Position of the next marker other than a restart marker, where a scan's data ends
+fn next_marker(data: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < data.len() {
+        if data[pos] == 0xFF && !matches!(data[pos + 1], 0x00 | 0xD0..=0xD7 | 0xFF) {
+            return pos;
+        }
+        pos += 1;
+    }
+    data.len()
+}
+
+/// A Huffman table as stored in a DHT segment: how many codes there are of each
+/// length from 1 to 16 bits, then the symbols in code order
+#[derive(Default)]
+struct HuffmanTable {
+    counts: [u8; 16],
+    values: Vec<u8>,
+    /// Largest code of each length, or -1 if there are none; indexed by length
+    max_code: [i32; 17],
+    /// Smallest code of each length, and where its symbol is in `values`
+    min_code: [i32; 17],
+    first_value: [usize; 17],
+}
+
+impl HuffmanTable {
+    fn new(counts: [u8; 16], values: Vec<u8>) -> Self {
+        let mut table = Self {
+            counts,
+            values,
+            max_code: [-1; 17],
+            min_code: [0; 17],
+            first_value: [0; 17],
+        };
+        let (mut code, mut index) = (0i32, 0usize);
+        for length in 1..=16 {
+            let count = usize::from(counts[length - 1]);
+            table.first_value[length] = index;
+            table.min_code[length] = code;
+            if count > 0 {
+                table.max_code[length] = code + count as i32 - 1;
+            }
+            code = (code + count as i32) << 1;
+            index += count;
+        }
+        table
+    }
+
+    /// (code, length) of every symbol; unused symbols have length 0
+    fn codes(&self) -> [(u16, u8); 256] {
+        let mut codes = [(0, 0); 256];
+        let mut index = 0;
+        for length in 1..=16 {
+            for code in 0..usize::from(self.counts[length - 1]) {
+                let value = self.values[index];
+                codes[usize::from(value)] = ((self.min_code[length] as usize + code) as u16, length as u8);
+                index += 1;
+            }
+        }
+        codes
+    }
+}
+
+fn read_huffman_tables(
+    mut payload: &[u8],
+    dc_tables: &mut [Option<HuffmanTable>; 4],
+    ac_tables: &mut [Option<HuffmanTable>; 4],
+) -> Result<()> {
+    while let Some((&spec, rest)) = payload.split_first() {
+        let (class, id) = (spec >> 4, usize::from(spec & 15));
+        let counts: [u8; 16] = rest
+            .get(..16)
+            .and_then(|counts| counts.try_into().ok())
+            .context("Truncated Huffman table")?;
+        let total: usize = counts.iter().map(|&c| usize::from(c)).sum();
+        let values = rest.get(16..16 + total).context("Truncated Huffman table")?;
+        if id > 3 || class > 1 || total > 256 {
+            bail!("Invalid Huffman table");
+        }
+
+        let table = HuffmanTable::new(counts, values.to_vec());
+        if class == 0 {
+            dc_tables[id] = Some(table);
+        } else {
+            ac_tables[id] = Some(table);
+        }
+        payload = &rest[16 + total..];
+    }
+    Ok(())
+}
+
+/// Huffman code lengths for the given symbol counts, limited to 16 bits, following
+/// the procedure in Annex K.2 of the JPEG standard (as libjpeg does it)
+fn optimal_table(symbol_counts: &[u32; 257]) -> HuffmanTable {
+    let mut freq: Vec<u64> = symbol_counts.iter().map(|&c| u64::from(c)).collect();
+    // A reserved symbol keeps any real code from being all ones
+    freq[256] = 1;
+    let mut code_size = [0usize; 257];
+    let mut others = [None::<usize>; 257];
+
+    loop {
+        // The least frequent symbol, preferring the highest index on ties, then the next one
+        let mut c1 = None;
+        let mut c2 = None;
+        for i in 0..257 {
+            if freq[i] == 0 {
+                continue;
+            }
+            if c1.is_none_or(|c: usize| freq[i] <= freq[c]) {
+                c1 = Some(i);
+            }
+        }
+        for i in 0..257 {
+            if freq[i] == 0 || Some(i) == c1 {
+                continue;
+            }
+            if c2.is_none_or(|c: usize| freq[i] <= freq[c]) {
+                c2 = Some(i);
+            }
+        }
+        let (Some(mut c1), Some(mut c2)) = (c1, c2) else {
+            break;
+        };
+
+        freq[c1] += freq[c2];
+        freq[c2] = 0;
+        code_size[c1] += 1;
+        while let Some(next) = others[c1] {
+            c1 = next;
+            code_size[c1] += 1;
+        }
+        others[c1] = Some(c2);
+        code_size[c2] += 1;
+        while let Some(next) = others[c2] {
+            c2 = next;
+            code_size[c2] += 1;
+        }
+    }
+
+    let mut bits = [0u32; 33];
+    for &size in &code_size {
+        if size > 0 {
+            bits[size.min(32)] += 1;
+        }
+    }
+    // Shorten codes longer than 16 bits
+    for i in (17..=32).rev() {
+        while bits[i] > 0 {
+            let mut j = i - 2;
+            while bits[j] == 0 {
+                j -= 1;
+            }
+            bits[i] -= 2;
+            bits[i - 1] += 1;
+            bits[j + 1] += 2;
+            bits[j] -= 1;
+        }
+    }
+    // Drop the reserved symbol, which has the longest code
+    let mut longest = 16;
+    while bits[longest] == 0 {
+        longest -= 1;
+    }
+    bits[longest] -= 1;
+
+    let mut values = Vec::new();
+    for size in 1..=32 {
+        for (symbol, &symbol_size) in code_size[..256].iter().enumerate() {
+            if symbol_size == size {
+                values.push(symbol as u8);
+            }
+        }
+    }
+    let counts = std::array::from_fn(|i| bits[i + 1] as u8);
+    HuffmanTable::new(counts, values)
+}
+
+/// Reads entropy-coded bits, undoing byte stuffing. At a marker it stops and feeds
+/// zeros, leaving `pos` on the marker.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos, bits: 0, count: 0 }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+            (Some(0xFF), Some(0x00)) => {
+                self.pos += 2;
+                0xFF
+            }
+            (Some(0xFF), _) | (None, _) => 0,
+            (Some(&byte), _) => {
+                self.pos += 1;
+                byte
+            }
+        }
+    }
+
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            self.bits = u32::from(self.next_byte());
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> Result<u8> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | self.bit() as i32;
+            if code <= table.max_code[length] {
+                let index = table.first_value[length] + (code - table.min_code[length]) as usize;
+                return table.values.get(index).copied().context("Corrupt Huffman code");
+            }
+        }
+        bail!("Corrupt Huffman code")
+    }
+
+    /// Read a `size`-bit magnitude and turn it back into a signed value
+    fn receive_extend(&mut self, size: u8) -> Result<i32> {
+        if size > 16 {
+            bail!("Corrupt coefficient size");
+        }
+        let mut value = 0i32;
+        for _ in 0..size {
+            value = (value << 1) | self.bit() as i32;
+        }
+        if size > 0 && value < 1 << (size - 1) {
+            value -= (1 << size) - 1;
+        }
+        Ok(value)
+    }
+
+    /// Skip to the byte boundary and past the restart marker that should be there
+    fn restart(&mut self) -> Result<()> {
+        self.count = 0;
+        while self.data.get(self.pos) == Some(&0xFF) && self.data.get(self.pos + 1) == Some(&0xFF) {
+            self.pos += 1;
+        }
+        match self.data.get(self.pos..self.pos + 2) {
+            Some([0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => bail!("Missing JPEG restart marker"),
+        }
+    }
+}
+
+/// Writes entropy-coded bits with byte stuffing, padding the last byte with ones
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, length: u8) {
+        for i in (0..length).rev() {
+            self.bits = (self.bits << 1) | u32::from(code >> i & 1);
+            self.count += 1;
+            if self.count == 8 {
+                self.push_byte();
+            }
+        }
+    }
+
+    fn push_byte(&mut self) {
+        let byte = self.bits as u8;
+        self.out.push(byte);
+        if byte == 0xFF {
+            self.out.push(0x00);
+        }
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            let padding = 8 - self.count;
+            self.bits = (self.bits << padding) | ((1 << padding) - 1);
+            self.push_byte();
+        }
+        self.out
+    }
+}
@@ -1,11 +1,51 @@
-use std::path::Path;
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::experimental::Writer;
+use exif::{DateTime, Exif, In, Reader, Tag, Value};
+use std::collections::BTreeMap;
+use std::io::Cursor;
 
-/// Metadata preservation utilities
-/// Note: Full EXIF preservation is complex and may require external tools like exiftool
-/// This module provides placeholder functionality for metadata operations
+// Metadata preservation utilities
+// EXIF is carried across as a raw TIFF-structured block, the same payload a JPEG stores in its
+// APP1 segment, so the converter can hand it straight to the JPEG encoder.
+
+/// Largest EXIF payload that fits in a single JPEG APP1 segment
+/// (65535 minus the 2-byte length and the 6-byte "Exif\0\0" header)
+pub const MAX_JPEG_EXIF_SIZE: usize = 65527;
+
+/// TIFF tags that describe the pixel layout of the source file rather than the photo.
+/// They are meaningless (or wrong) once the pixels are re-encoded as JPEG.
+const IMAGE_STRUCTURE_TAGS: &[Tag] = &[
+    Tag::ImageWidth,
+    Tag::ImageLength,
+    Tag::BitsPerSample,
+    Tag::Compression,
+    Tag::PhotometricInterpretation,
+    Tag::SamplesPerPixel,
+    Tag::RowsPerStrip,
+    Tag::PlanarConfiguration,
+    Tag::YCbCrSubSampling,
+    Tag::YCbCrPositioning,
+    Tag::TransferFunction,
+];
 
 pub struct MetadataHandler;
 
+/// What a photo's EXIF data says about when, where and with what it was taken
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhotoInfo {
+    /// DateTimeOriginal, or DateTimeDigitized if that's missing
+    pub taken: Option<NaiveDateTime>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// LensModel
+    pub lens: Option<String>,
+    /// Whether it has GPS coordinates
+    pub has_gps: bool,
+    /// Every tag of the main image as text, by its EXIF name, e.g. `Software`.
+    /// Binary values such as the maker note are left out.
+    pub fields: BTreeMap<String, String>,
+}
+
 impl MetadataHandler {
     pub fn new() -> Self {
         Self
@@ -15,7 +55,7 @@ impl MetadataHandler {
     pub fn format_has_exif(format: &str) -> bool {
         matches!(
             format.to_uppercase().as_str(),
-            "HEIC" | "HEIF" | "TIFF" | "TIF" | "JPEG" | "JPG" | "WEBP"
+            "HEIC" | "HEIF" | "TIFF" | "TIF" | "JPEG" | "JPG" | "WEBP" | "AVIF" | "DNG" | "CR2" | "NEF" | "ARW"
         )
     }
 
@@ -23,15 +63,230 @@ impl MetadataHandler {
     pub fn get_preservation_note(format: &str) -> String {
         match format.to_uppercase().as_str() {
             "HEIC" | "HEIF" => {
-                "EXIF data preserved where possible via libheif".to_string()
+                "HEIC/HEIF: EXIF block copied from the libheif metadata when present".to_string()
             }
-            "TIFF" | "TIF" => "EXIF data preserved".to_string(),
-            "WEBP" => "XMP/EXIF preserved if present in source".to_string(),
+            "TIFF" | "TIF" => {
+                "TIFF: EXIF and GPS tags copied from the TIFF directory when present".to_string()
+            }
+            "WEBP" => "WEBP: EXIF chunk copied when present (XMP is not carried over)".to_string(),
             "BMP" => "BMP files do not contain EXIF metadata".to_string(),
-            "AVIF" => "Metadata preservation depends on encoder support".to_string(),
+            "AVIF" => "AVIF: EXIF block copied from the libheif metadata when present".to_string(),
+            "DNG" | "CR2" | "NEF" | "ARW" => {
+                format!("{}: EXIF tags copied from the RAW file; maker notes are not carried over", format.to_uppercase())
+            }
             _ => "Metadata preservation attempted".to_string(),
         }
     }
+
+    /// Read the EXIF block from a TIFF, WebP or AVIF file already in memory, ready to
+    /// embed in a JPEG. Returns `None` if the file has no EXIF data; a block too large
+    /// for an APP1 segment is returned anyway, see [`fits_jpeg`](Self::fits_jpeg).
+    pub fn read_exif_from_bytes(data: &[u8]) -> Option<Vec<u8>> {
+        let exif = Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+
+        // For a TIFF container the parsed buffer is the whole file rather than an EXIF block
+        let block = if Self::is_tiff_container(&exif) {
+            Self::rebuild_tiff_exif(&exif)?
+        } else {
+            exif.buf().to_vec()
+        };
+
+        Self::non_empty(block)
+    }
+
+    /// Read the EXIF tags from a camera RAW file (DNG, CR2, NEF, ARW). These are TIFF
+    /// files too, so the block is always rebuilt from the primary IFD's tags.
+    pub fn read_raw_exif(data: &[u8]) -> Option<Vec<u8>> {
+        let exif = Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+        Self::non_empty(Self::rebuild_tiff_exif(&exif)?)
+    }
+
+    /// Date, camera, lens and GPS presence from the EXIF data of a JPEG, TIFF, camera
+    /// RAW, PNG, WebP or HEIF file. The date is DateTimeOriginal, or DateTimeDigitized
+    /// if that's missing, and `None` for a blank one like `0000:00:00 00:00:00`. Files
+    /// without EXIF data know nothing.
+    pub fn photo_info(data: &[u8]) -> PhotoInfo {
+        let Ok(exif) = Reader::new().read_from_container(&mut Cursor::new(data)) else {
+            return PhotoInfo::default();
+        };
+        PhotoInfo {
+            taken: Self::taken(&exif),
+            make: Self::text(&exif, Tag::Make),
+            model: Self::text(&exif, Tag::Model),
+            lens: Self::text(&exif, Tag::LensModel),
+            has_gps: exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some(),
+            fields: exif
+                .fields()
+                .filter(|field| field.ifd_num == In::PRIMARY)
+                .filter_map(|field| {
+                    let text = match field.value {
+                        Value::Ascii(_) => Self::ascii(&field.value)?,
+                        Value::Undefined(..) | Value::Unknown(..) => return None,
+                        _ => field.display_value().to_string(),
+                    };
+                    Some((field.tag.to_string(), text))
+                })
+                .collect(),
+        }
+    }
+
+    fn taken(exif: &Exif) -> Option<NaiveDateTime> {
+        [Tag::DateTimeOriginal, Tag::DateTimeDigitized].into_iter().find_map(|tag| {
+            let Value::Ascii(ref values) = exif.get_field(tag, In::PRIMARY)?.value else {
+                return None;
+            };
+            let date = DateTime::from_ascii(values.first()?).ok()?;
+            NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?.and_hms_opt(
+                date.hour.into(),
+                date.minute.into(),
+                date.second.into(),
+            )
+        })
+    }
+
+    /// An ASCII tag's value, without the padding cameras leave around it
+    fn text(exif: &Exif, tag: Tag) -> Option<String> {
+        Self::ascii(&exif.get_field(tag, In::PRIMARY)?.value)
+    }
+
+    fn ascii(value: &Value) -> Option<String> {
+        let Value::Ascii(values) = value else {
+            return None;
+        };
+        let text = String::from_utf8_lossy(values.first()?);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    /// Strip the HEIF EXIF item header (a 4-byte offset to the TIFF header)
+    pub fn exif_from_heif_block(block: &[u8]) -> Option<Vec<u8>> {
+        if block.len() < 4 {
+            return None;
+        }
+        let offset = u32::from_be_bytes([block[0], block[1], block[2], block[3]]) as usize;
+        let start = 4usize.checked_add(offset)?;
+        let tiff = block.get(start..)?;
+        if tiff.is_empty() {
+            return None;
+        }
+        Self::non_empty(tiff.to_vec())
+    }
+
+    /// Add EXIF and ICC profile chunks to an encoded WebP file, converting a simple
+    /// (VP8/VP8L-only) file to the extended format so the chunks are recognised. The ICCP
+    /// chunk has to come before the image data, so it goes right after the VP8X header.
+    /// Returns `None` if the file isn't a WebP this function understands.
+    pub fn add_webp_metadata(
+        webp: &[u8],
+        exif: Option<&[u8]>,
+        icc: Option<&[u8]>,
+        width: u32,
+        height: u32,
+    ) -> Option<Vec<u8>> {
+        const ICC_FLAG: u8 = 0x20;
+        const EXIF_FLAG: u8 = 0x08;
+        const ALPHA_FLAG: u8 = 0x10;
+        const VP8X_CHUNK_SIZE: usize = 18;
+
+        if webp.len() < 20 || &webp[0..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+            return None;
+        }
+        let chunks = &webp[12..];
+
+        let mut flags = 0;
+        if exif.is_some() {
+            flags |= EXIF_FLAG;
+        }
+        if icc.is_some() {
+            flags |= ICC_FLAG;
+        }
+
+        let extra = exif.map_or(0, <[u8]>::len) + icc.map_or(0, <[u8]>::len);
+        let mut output = Vec::with_capacity(webp.len() + extra + 48);
+        output.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+        let image_chunks = match &chunks[0..4] {
+            b"VP8X" => {
+                output.extend_from_slice(chunks.get(..VP8X_CHUNK_SIZE)?);
+                // Flags are the first byte of the VP8X payload
+                output[20] |= flags;
+                &chunks[VP8X_CHUNK_SIZE..]
+            }
+            fourcc @ (b"VP8 " | b"VP8L") => {
+                // VP8L stores an alpha hint in bit 28 of the header after the 0x2f signature
+                let has_alpha = fourcc == b"VP8L"
+                    && chunks.get(9..13).is_some_and(|header| {
+                        u32::from_le_bytes([header[0], header[1], header[2], header[3]]) >> 28 & 1 == 1
+                    });
+                if has_alpha {
+                    flags |= ALPHA_FLAG;
+                }
+
+                output.extend_from_slice(b"VP8X");
+                output.extend_from_slice(&10u32.to_le_bytes());
+                output.extend_from_slice(&[flags, 0, 0, 0]);
+                output.extend_from_slice(&(width.checked_sub(1)?).to_le_bytes()[..3]);
+                output.extend_from_slice(&(height.checked_sub(1)?).to_le_bytes()[..3]);
+                chunks
+            }
+            _ => return None,
+        };
+
+        if let Some(icc) = icc {
+            push_riff_chunk(&mut output, b"ICCP", icc)?;
+        }
+        output.extend_from_slice(image_chunks);
+        if let Some(exif) = exif {
+            push_riff_chunk(&mut output, b"EXIF", exif)?;
+        }
+
+        let riff_size = u32::try_from(output.len() - 8).ok()?;
+        output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        Some(output)
+    }
+
+    /// Whether an EXIF block fits in a single JPEG APP1 segment. Larger ones are
+    /// dropped for every target format, so outputs carry the same metadata whatever
+    /// they are encoded as.
+    pub fn fits_jpeg(block: &[u8]) -> bool {
+        block.len() <= MAX_JPEG_EXIF_SIZE
+    }
+
+    fn non_empty(block: Vec<u8>) -> Option<Vec<u8>> {
+        (!block.is_empty()).then_some(block)
+    }
+
+    fn is_tiff_container(exif: &exif::Exif) -> bool {
+        // An embedded EXIF block never carries the primary image's strips or tiles
+        exif.get_field(Tag::StripOffsets, In::PRIMARY).is_some()
+            || exif.get_field(Tag::TileOffsets, In::PRIMARY).is_some()
+    }
+
+    /// A TIFF file's EXIF "block" is the whole file, strips included, so rebuild a standalone
+    /// block from the primary image's descriptive tags only. Private tags (XMP, ICC, Photoshop)
+    /// and maker notes are dropped too; they can easily push the block past the APP1 size
+    /// limit, and maker notes often point back into the original file.
+    fn rebuild_tiff_exif(exif: &exif::Exif) -> Option<Vec<u8>> {
+        let mut writer = Writer::new();
+        let mut field_count = 0;
+        for field in exif.fields() {
+            if field.ifd_num != In::PRIMARY
+                || IMAGE_STRUCTURE_TAGS.contains(&field.tag)
+                || field.tag == Tag::MakerNote
+                || field.tag.description().is_none()
+            {
+                continue;
+            }
+            writer.push_field(field);
+            field_count += 1;
+        }
+        if field_count == 0 {
+            return None;
+        }
+
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, exif.little_endian()).ok()?;
+        Some(buf.into_inner())
+    }
 }
 
 impl Default for MetadataHandler {
@@ -39,3 +294,14 @@ impl Default for MetadataHandler {
         Self::new()
     }
 }
+
+/// Append a RIFF chunk, padded to an even length
+fn push_riff_chunk(output: &mut Vec<u8>, fourcc: &[u8; 4], payload: &[u8]) -> Option<()> {
+    output.extend_from_slice(fourcc);
+    output.extend_from_slice(&u32::try_from(payload.len()).ok()?.to_le_bytes());
+    output.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        output.push(0);
+    }
+    Some(())
+}
@@ -1,2 +1,12 @@
+pub mod animation;
 pub mod collision;
+pub mod color;
+pub mod depth;
+pub mod disk;
+pub mod duplicates;
+pub mod format;
+pub mod hdr;
+pub mod jpeg_transform;
 pub mod metadata;
+pub mod raw;
+pub mod resize;
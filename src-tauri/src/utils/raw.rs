@@ -0,0 +1,363 @@
+use anyhow::{anyhow, Context, Result};
+use image::RgbImage;
+use std::collections::HashSet;
+use std::io::Cursor;
+
+// Camera RAW support
+// DNG, CR2, NEF and ARW are all TIFF files underneath: a chain of IFDs (plus SubIFDs)
+// holding the sensor data and one or more JPEG previews. The previews are found by
+// walking those IFDs directly; the sensor data is unpacked by rawloader and developed
+// here with a plain bilinear demosaic.
+
+/// Linear sRGB to XYZ (D65), used to turn the camera's XYZ matrix into a camera-to-sRGB one
+const SRGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.412_453, 0.357_580, 0.180_423],
+    [0.212_671, 0.715_160, 0.072_169],
+    [0.019_334, 0.119_193, 0.950_227],
+];
+
+/// IFDs visited before giving up on a malformed or looping file
+const MAX_IFDS: usize = 64;
+
+/// Values read from one IFD entry; previews only ever use a single strip
+const MAX_VALUES: usize = 1024;
+
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_PHOTOMETRIC: u16 = 0x0106;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+/// The largest baseline JPEG embedded in a RAW file, or `None` if it has none.
+/// Lossless JPEG streams (the sensor data in DNG and CR2 files) are skipped.
+pub fn embedded_preview(data: &[u8]) -> Option<&[u8]> {
+    let tiff = Tiff::new(data)?;
+    let mut pending = vec![tiff.u32(4)?];
+    let mut visited = HashSet::new();
+    let mut best: Option<&[u8]> = None;
+
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = tiff.read_ifd(offset as usize) else {
+            continue;
+        };
+        pending.extend(ifd.sub_ifds.iter().copied());
+        pending.extend(ifd.next);
+
+        for candidate in ifd.jpeg_candidates() {
+            let Some(jpeg) = data.get(candidate) else {
+                continue;
+            };
+            if is_baseline_jpeg(jpeg) && best.is_none_or(|best| jpeg.len() > best.len()) {
+                best = Some(jpeg);
+            }
+        }
+    }
+
+    best
+}
+
+/// Demosaic a RAW file's sensor data into an sRGB image, applying the camera's
+/// white balance and colour matrix
+pub fn develop(data: &[u8]) -> Result<RgbImage> {
+    let raw = rawloader::decode(&mut Cursor::new(data))
+        .map_err(|e| anyhow!("Failed to decode RAW file: {}", e))?;
+
+    let [top, right, bottom, left] = raw.crops;
+    let width = raw.width.saturating_sub(left + right);
+    let height = raw.height.saturating_sub(top + bottom);
+    if width == 0 || height == 0 {
+        anyhow::bail!("RAW file has no image area");
+    }
+
+    let samples: Vec<f32> = match &raw.data {
+        rawloader::RawImageData::Integer(values) => values.iter().map(|&v| f32::from(v)).collect(),
+        rawloader::RawImageData::Float(values) => values.clone(),
+    };
+    if samples.len() < raw.width * raw.height * raw.cpp {
+        anyhow::bail!("RAW file is truncated");
+    }
+
+    let wb = white_balance(raw.wb_coeffs);
+    let level = |value: f32, channel: usize| {
+        let black = f32::from(raw.blacklevels[channel]);
+        let white = f32::from(raw.whitelevels[channel]).max(black + 1.0);
+        ((value - black) / (white - black)).clamp(0.0, 1.0) * wb[channel.min(2)]
+    };
+
+    let color_at = |row: usize, col: usize| cfa_color(raw.cfa.color_at(row, col));
+    // Linear DNGs are already demosaiced; mosaiced data is levelled up front so
+    // neighbouring samples can be mixed
+    let levelled: Vec<f32> = if raw.cpp >= 3 {
+        Vec::new()
+    } else {
+        (0..raw.height * raw.width)
+            .map(|i| level(samples[i], color_at(i / raw.width, i % raw.width)))
+            .collect()
+    };
+
+    let to_srgb = camera_to_srgb(raw.xyz_to_cam);
+    let mut img = RgbImage::new(
+        u32::try_from(width).context("RAW image is too wide")?,
+        u32::try_from(height).context("RAW image is too tall")?,
+    );
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (row, col) = (y as usize + top, x as usize + left);
+        let camera = if raw.cpp >= 3 {
+            let base = (row * raw.width + col) * raw.cpp;
+            [0, 1, 2].map(|c| level(samples[base + c], c))
+        } else {
+            demosaic_pixel(&levelled, raw.width, raw.height, row, col, color_at)
+        };
+        let rgb = to_srgb.map(|m| m[0] * camera[0] + m[1] * camera[1] + m[2] * camera[2]);
+        pixel.0 = rgb.map(encode_srgb);
+    }
+
+    Ok(img)
+}
+
+/// Bilinear demosaic: each colour is its own sample where the pixel has it, and
+/// the mean of the neighbouring samples of that colour otherwise
+fn demosaic_pixel(
+    samples: &[f32],
+    width: usize,
+    height: usize,
+    row: usize,
+    col: usize,
+    color_at: impl Fn(usize, usize) -> usize,
+) -> [f32; 3] {
+    let mut sums = [0.0f32; 3];
+    let mut counts = [0u32; 3];
+    for r in row.saturating_sub(1)..=(row + 1).min(height - 1) {
+        for c in col.saturating_sub(1)..=(col + 1).min(width - 1) {
+            let color = color_at(r, c);
+            sums[color] += samples[r * width + c];
+            counts[color] += 1;
+        }
+    }
+
+    let own = color_at(row, col);
+    let mut rgb = [0.0; 3];
+    for color in 0..3 {
+        rgb[color] = if color == own {
+            samples[row * width + col]
+        } else if counts[color] > 0 {
+            sums[color] / counts[color] as f32
+        } else {
+            0.0
+        };
+    }
+    rgb
+}
+
+/// rawloader numbers CFA colours red, green, blue and a fourth (usually a second
+/// green, or emerald); the fourth is treated as green
+fn cfa_color(color: usize) -> usize {
+    match color {
+        0 => 0,
+        2 => 2,
+        _ => 1,
+    }
+}
+
+/// As-shot white balance relative to green; cameras that don't record one get none
+fn white_balance(coeffs: [f32; 4]) -> [f32; 3] {
+    let green = coeffs[1];
+    if !(green.is_finite() && green > 0.0) || coeffs[..3].iter().any(|c| !c.is_finite() || *c <= 0.0) {
+        return [1.0; 3];
+    }
+    [coeffs[0] / green, 1.0, coeffs[2] / green]
+}
+
+/// Camera RGB to linear sRGB, following dcraw: combine the camera's XYZ matrix
+/// with sRGB's, scale each row to sum to one so white stays white, and invert.
+/// Cameras without a matrix fall back to passing the colours through.
+fn camera_to_srgb(xyz_to_cam: [[f32; 3]; 4]) -> [[f32; 3]; 3] {
+    const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    let mut cam_to_rgb = [[0.0f32; 3]; 3];
+    for (i, row) in cam_to_rgb.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| xyz_to_cam[i][k] * SRGB_TO_XYZ[k][j]).sum();
+        }
+        let sum: f32 = row.iter().sum();
+        if !sum.is_finite() || sum.abs() < f32::EPSILON {
+            return IDENTITY;
+        }
+        row.iter_mut().for_each(|value| *value /= sum);
+    }
+
+    invert(cam_to_rgb).unwrap_or(IDENTITY)
+}
+
+fn invert(m: [[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if !det.is_finite() || det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let cofactor = |r1: usize, c1: usize, r2: usize, c2: usize| m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1];
+    Some([
+        [cofactor(1, 1, 2, 2) / det, -cofactor(0, 1, 2, 2) / det, cofactor(0, 1, 1, 2) / det],
+        [-cofactor(1, 0, 2, 2) / det, cofactor(0, 0, 2, 2) / det, -cofactor(0, 0, 1, 2) / det],
+        [cofactor(1, 0, 2, 1) / det, -cofactor(0, 0, 2, 1) / det, cofactor(0, 0, 1, 1) / det],
+    ])
+}
+
+/// Linear light to an 8-bit sRGB value
+fn encode_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// The first baseline (lossy) frame header decides; lossless JPEG is the raw sensor data
+fn is_baseline_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+
+    let mut pos = 2;
+    while let (Some(&0xFF), Some(&marker)) = (data.get(pos), data.get(pos + 1)) {
+        match marker {
+            // Fill byte
+            0xFF => pos += 1,
+            0xC0..=0xC2 => return true,
+            // Any other start of frame, or image data before a frame header
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return false,
+            // Markers without a length
+            0x01 | 0xD0..=0xD8 => pos += 2,
+            _ => {
+                let Some(len) = data.get(pos + 2..pos + 4) else {
+                    return false;
+                };
+                pos += 2 + usize::from(u16::from_be_bytes([len[0], len[1]]));
+            }
+        }
+    }
+    false
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// The parts of an IFD needed to find previews
+#[derive(Default)]
+struct Ifd {
+    compression: Option<u32>,
+    photometric: Option<u32>,
+    strip_offsets: Vec<u32>,
+    strip_byte_counts: Vec<u32>,
+    jpeg_offset: Option<u32>,
+    jpeg_length: Option<u32>,
+    sub_ifds: Vec<u32>,
+    next: Option<u32>,
+}
+
+impl Ifd {
+    /// Byte ranges that may hold a JPEG preview
+    fn jpeg_candidates(&self) -> Vec<std::ops::Range<usize>> {
+        let mut candidates = Vec::new();
+        if let (Some(offset), Some(length)) = (self.jpeg_offset, self.jpeg_length) {
+            candidates.push(offset as usize..offset as usize + length as usize);
+        }
+
+        // A JPEG-compressed single strip that isn't colour filter array or linear raw data
+        let is_jpeg = matches!(self.compression, Some(6 | 7));
+        let is_sensor_data = matches!(self.photometric, Some(32803 | 34892));
+        if is_jpeg && !is_sensor_data {
+            if let ([offset], [length]) = (&self.strip_offsets[..], &self.strip_byte_counts[..]) {
+                candidates.push(*offset as usize..*offset as usize + *length as usize);
+            }
+        }
+        candidates
+    }
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(0..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self { data, little_endian })
+    }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let bytes = [*self.data.get(pos)?, *self.data.get(pos + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_ifd(&self, offset: usize) -> Option<Ifd> {
+        let count = usize::from(self.u16(offset)?);
+        let mut ifd = Ifd::default();
+
+        for i in 0..count {
+            let entry = offset + 2 + i * 12;
+            let tag = self.u16(entry)?;
+            let values = || self.values(entry).unwrap_or_default();
+            match tag {
+                TAG_COMPRESSION => ifd.compression = values().first().copied(),
+                TAG_PHOTOMETRIC => ifd.photometric = values().first().copied(),
+                TAG_STRIP_OFFSETS => ifd.strip_offsets = values(),
+                TAG_STRIP_BYTE_COUNTS => ifd.strip_byte_counts = values(),
+                TAG_SUB_IFDS => ifd.sub_ifds = values(),
+                TAG_JPEG_OFFSET => ifd.jpeg_offset = values().first().copied(),
+                TAG_JPEG_LENGTH => ifd.jpeg_length = values().first().copied(),
+                _ => {}
+            }
+        }
+
+        ifd.next = self.u32(offset + 2 + count * 12);
+        Some(ifd)
+    }
+
+    /// SHORT, LONG or IFD values of an entry, stored inline when they fit in four bytes
+    fn values(&self, entry: usize) -> Option<Vec<u32>> {
+        let field_type = self.u16(entry + 2)?;
+        let count = self.u32(entry + 4)? as usize;
+        let size = match field_type {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return None,
+        };
+        let start = if count * size <= 4 {
+            entry + 8
+        } else {
+            self.u32(entry + 8)? as usize
+        };
+
+        (0..count.min(MAX_VALUES))
+            .map(|i| match size {
+                2 => self.u16(start + i * 2).map(u32::from),
+                _ => self.u32(start + i * 4),
+            })
+            .collect()
+    }
+}
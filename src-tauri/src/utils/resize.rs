@@ -0,0 +1,124 @@
+use crate::processor::options::{ResizeMode, ResizeSettings};
+use image::DynamicImage;
+
+// Resizing
+// Phones and cameras produce far more pixels than web pages and CMSs accept (a
+// 48MP HEIC is 8064x6048). Converted images are scaled to the job's limits here,
+// after they are turned upright, so widths and heights are the ones people see.
+
+/// How an image is brought to the resize limits
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Plan {
+    /// Scale to this width and height
+    Scale(u32, u32),
+    /// Scale to `scaled`, then crop the centre to `cropped`
+    Fill { scaled: (u32, u32), cropped: (u32, u32) },
+}
+
+impl Plan {
+    /// Width and height of the result
+    pub fn output_size(&self) -> (u32, u32) {
+        match *self {
+            Plan::Scale(width, height) => (width, height),
+            Plan::Fill { cropped, .. } => cropped,
+        }
+    }
+}
+
+/// What to do with a `width` x `height` image, or `None` if it keeps its size.
+/// `Fill` and `Exact` need both `max_width` and `max_height`; with only one of
+/// them, or just the long edge or megapixel limits, images are fitted instead.
+pub fn plan(settings: &ResizeSettings, width: u32, height: u32) -> Option<Plan> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let bounds = settings.max_width.zip(settings.max_height);
+    let plan = match (settings.mode, bounds) {
+        (ResizeMode::Fill, Some((max_width, max_height))) => {
+            fill(width, height, max_width.max(1), max_height.max(1), settings.allow_upscale)
+        }
+        (ResizeMode::Exact, Some((max_width, max_height))) => {
+            let (max_width, max_height) = (max_width.max(1), max_height.max(1));
+            if settings.allow_upscale {
+                Plan::Scale(max_width, max_height)
+            } else {
+                Plan::Scale(max_width.min(width), max_height.min(height))
+            }
+        }
+        _ => fit(settings, width, height)?,
+    };
+
+    let unchanged = match plan {
+        Plan::Scale(w, h) => (w, h) == (width, height),
+        Plan::Fill { scaled, cropped } => scaled == (width, height) && cropped == scaled,
+    };
+    (!unchanged).then_some(plan)
+}
+
+/// Scale an image to the job's limits. Images within them are returned as they are.
+pub fn resize(img: DynamicImage, settings: &ResizeSettings) -> DynamicImage {
+    resized(&img, settings).unwrap_or(img)
+}
+
+/// A copy of an image scaled to the job's limits, or `None` if it's within them
+pub fn resized(img: &DynamicImage, settings: &ResizeSettings) -> Option<DynamicImage> {
+    let plan = plan(settings, img.width(), img.height())?;
+    let filter = settings.filter.filter_type();
+    let img = match plan {
+        Plan::Scale(width, height) => img.resize_exact(width, height, filter),
+        Plan::Fill {
+            scaled: (width, height),
+            cropped: (crop_width, crop_height),
+        } => img.resize_exact(width, height, filter).crop_imm(
+            (width - crop_width) / 2,
+            (height - crop_height) / 2,
+            crop_width,
+            crop_height,
+        ),
+    };
+    Some(img)
+}
+
+/// The largest size within every limit, keeping the aspect ratio
+fn fit(settings: &ResizeSettings, width: u32, height: u32) -> Option<Plan> {
+    let (w, h) = (f64::from(width), f64::from(height));
+    let mut scale = f64::INFINITY;
+    if let Some(edge) = settings.max_long_edge {
+        scale = scale.min(f64::from(edge.max(1)) / w.max(h));
+    }
+    if let Some(max_width) = settings.max_width {
+        scale = scale.min(f64::from(max_width.max(1)) / w);
+    }
+    if let Some(max_height) = settings.max_height {
+        scale = scale.min(f64::from(max_height.max(1)) / h);
+    }
+    if let Some(megapixels) = settings.max_megapixels {
+        scale = scale.min((f64::from(megapixels.max(0.0)) * 1e6 / (w * h)).sqrt());
+    }
+    if !scale.is_finite() {
+        return None;
+    }
+    if !settings.allow_upscale {
+        scale = scale.min(1.0);
+    }
+    // Rounded down so the result never goes over a limit
+    Some(Plan::Scale(scale_down(width, scale), scale_down(height, scale)))
+}
+
+/// Cover `max_width` x `max_height`, keeping the aspect ratio, and crop what sticks
+/// out. Without upscaling, small images are only cropped, to at most the box.
+fn fill(width: u32, height: u32, max_width: u32, max_height: u32, allow_upscale: bool) -> Plan {
+    let scale = (f64::from(max_width) / f64::from(width)).max(f64::from(max_height) / f64::from(height));
+    let scale = if allow_upscale { scale } else { scale.min(1.0) };
+    let scaled = (
+        ((f64::from(width) * scale).round() as u32).max(1),
+        ((f64::from(height) * scale).round() as u32).max(1),
+    );
+    let cropped = (max_width.min(scaled.0), max_height.min(scaled.1));
+    Plan::Fill { scaled, cropped }
+}
+
+fn scale_down(length: u32, scale: f64) -> u32 {
+    // The epsilon keeps exact results like 4000 * 0.5 from landing just below
+    ((f64::from(length) * scale + 1e-6).floor() as u32).max(1)
+}
@@ -0,0 +1,135 @@
+// Tests for animated GIF/WebP detection and the animation policy
+
+mod common;
+
+#[cfg(test)]
+mod animation_tests {
+    use crate::common::{entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter};
+    use file_sorter_lib::processor::options::{
+        AnimationPolicy, ConversionSettings, JobOptions, TargetFormat,
+    };
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::animation::{frame_count, gif_frame_count, webp_frame_count};
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, ImageFormat, RgbaImage};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    fn gif_with_frames(count: u8) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            let frames = (0..count).map(|i| {
+                let buffer = RgbaImage::from_pixel(8, 8, image::Rgba([i * 60, 100, 200, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        data
+    }
+
+    fn convert(settings: ConversionSettings, name: &str, data: &[u8]) -> (ConversionResult, Vec<u8>) {
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings)
+            .process_bytes(Path::new(name), data, &mut output)
+            .unwrap();
+        (result, output)
+    }
+
+    #[test]
+    fn test_gif_frame_count() {
+        assert_eq!(gif_frame_count(&gif_with_frames(3)), Some(3));
+        assert_eq!(gif_frame_count(&gif_with_frames(1)), Some(1));
+        assert_eq!(gif_frame_count(b"not a gif"), None);
+    }
+
+    #[test]
+    fn test_still_webp_is_one_frame() {
+        let mut webp = Cursor::new(Vec::new());
+        RgbaImage::from_pixel(8, 8, image::Rgba([1, 2, 3, 255]))
+            .write_to(&mut webp, ImageFormat::WebP)
+            .unwrap();
+
+        assert_eq!(webp_frame_count(webp.get_ref()), Some(1));
+        assert_eq!(frame_count(webp.get_ref(), "png"), None);
+    }
+
+    #[test]
+    fn test_still_gif_is_converted() {
+        let (result, output) = convert(ConversionSettings::default(), "still.gif", &gif_with_frames(1));
+
+        assert!(matches!(result, ConversionResult::Converted { .. }));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_animated_gif_kept_by_default() {
+        let gif = gif_with_frames(3);
+        let (result, output) = convert(ConversionSettings::default(), "anim.gif", &gif);
+
+        match result {
+            ConversionResult::Animated { frames, handling, extension, .. } => {
+                assert_eq!(frames, 3);
+                assert_eq!(handling, AnimationPolicy::Keep);
+                assert_eq!(extension, "gif");
+            }
+            _ => panic!("expected an animated result"),
+        }
+        assert_eq!(output, gif);
+    }
+
+    #[test]
+    fn test_first_frame_converted_to_target() {
+        let settings = ConversionSettings {
+            animation: AnimationPolicy::FirstFrame,
+            ..Default::default()
+        };
+        let (result, output) = convert(settings, "anim.gif", &gif_with_frames(3));
+
+        assert!(matches!(
+            result,
+            ConversionResult::Animated { handling: AnimationPolicy::FirstFrame, ref extension, .. }
+                if extension == "jpg"
+        ));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_convert_animated_to_webp_keeps_frames() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::WebpLossless,
+            animation: AnimationPolicy::ConvertAnimated,
+            ..Default::default()
+        };
+        let (result, output) = convert(settings, "anim.gif", &gif_with_frames(3));
+
+        assert!(matches!(
+            result,
+            ConversionResult::Animated { handling: AnimationPolicy::ConvertAnimated, ref extension, .. }
+                if extension == "webp"
+        ));
+        assert_eq!(webp_frame_count(&output), Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_job_records_animated_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_zip(&input, &[("anim.gif", gif_with_frames(2)), ("still.gif", gif_with_frames(1))]);
+
+        let job = JobInfo::new(input.to_string_lossy().to_string(), JobOptions::default());
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["anim.gif", "report.json", "still.jpg"]);
+
+        let animated = &output.report.animated;
+        assert_eq!(animated.len(), 1);
+        assert_eq!(animated[0].original_path, "anim.gif");
+        assert_eq!(animated[0].frames, 2);
+        assert_eq!(animated[0].handling, AnimationPolicy::Keep);
+        assert_eq!(output.report.stats.files_converted, 1);
+    }
+}
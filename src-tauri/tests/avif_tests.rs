@@ -0,0 +1,46 @@
+// Tests for decoding AVIF inputs (8-bit and 10-bit) through libheif
+
+#[cfg(test)]
+mod avif_tests {
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter};
+    use image::{GenericImageView, ImageFormat};
+    use std::path::{Path, PathBuf};
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    /// Converts a fixture to JPEG and checks it still shows red on the left and blue on the right
+    fn assert_round_trips_to_jpeg(name: &str) {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("photo.jpg");
+
+        let result = ImageConverter::new()
+            .process_image(&fixture(name), &output)
+            .unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { ref original_format, .. } if original_format == "AVIF"
+        ));
+        let data = std::fs::read(&output).unwrap();
+        assert_eq!(image::guess_format(&data).unwrap(), ImageFormat::Jpeg);
+
+        let img = image::load_from_memory(&data).unwrap();
+        assert_eq!(img.dimensions(), (32, 16));
+        let left = img.get_pixel(4, 8);
+        let right = img.get_pixel(28, 8);
+        assert!(left[0] > 150 && left[2] < 100, "left half should be red: {:?}", left);
+        assert!(right[2] > 150 && right[0] < 100, "right half should be blue: {:?}", right);
+    }
+
+    #[test]
+    fn test_8_bit_avif_converts_to_jpeg() {
+        assert_round_trips_to_jpeg("photo.avif");
+    }
+
+    #[test]
+    fn test_10_bit_avif_converts_to_jpeg() {
+        assert_round_trips_to_jpeg("photo-10bit.avif");
+    }
+}
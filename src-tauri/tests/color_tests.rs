@@ -0,0 +1,151 @@
+// Tests for ICC colour profiles: embedding them in the output or converting to sRGB
+
+mod common;
+
+#[cfg(test)]
+mod color_tests {
+    use crate::common::{write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{
+        ConversionResult, ImageConverter, ProfileHandling, SourceProfile,
+    };
+    use file_sorter_lib::processor::options::{ColorProfilePolicy, ConversionSettings, JobOptions, TargetFormat};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::color;
+    use image::codecs::tiff::TiffEncoder;
+    use image::{ImageDecoder, ImageEncoder, ImageFormat, ImageReader, RgbImage};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    // A saturated orange, which sRGB shows less vividly than Display P3
+    const ORANGE: [u8; 3] = [200, 100, 50];
+
+    fn display_p3() -> Vec<u8> {
+        moxcms::ColorProfile::new_display_p3().encode().unwrap()
+    }
+
+    /// A TIFF large enough for the decoder to read its profile; its memory limits scale
+    /// with the image size
+    fn tiff(icc: Option<Vec<u8>>) -> Vec<u8> {
+        let img = RgbImage::from_pixel(128, 128, image::Rgb(ORANGE));
+        let mut data = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut data);
+        if let Some(icc) = icc {
+            encoder.set_icc_profile(icc).unwrap();
+        }
+        encoder
+            .write_image(img.as_raw(), 128, 128, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        data.into_inner()
+    }
+
+    fn convert(
+        target_format: TargetFormat,
+        color_profile: ColorProfilePolicy,
+        source: &[u8],
+    ) -> (ConversionResult, Vec<u8>) {
+        let settings = ConversionSettings {
+            target_format,
+            color_profile,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings)
+            .process_bytes(Path::new("photo.tif"), source, &mut output)
+            .unwrap();
+        (result, output)
+    }
+
+    fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+        let mut decoder = ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        decoder.icc_profile().unwrap()
+    }
+
+    fn profile(handling: ProfileHandling) -> Option<SourceProfile> {
+        Some(SourceProfile {
+            name: Some("Display P3".to_string()),
+            handling,
+        })
+    }
+
+    #[test]
+    fn test_profile_name_read() {
+        assert_eq!(color::profile_name(&display_p3()).as_deref(), Some("Display P3"));
+        assert_eq!(color::profile_name(b"not a profile"), None);
+    }
+
+    #[test]
+    fn test_profile_embedded_by_default() {
+        let icc = display_p3();
+        for target_format in [TargetFormat::Jpeg, TargetFormat::Png, TargetFormat::WebpLossy] {
+            let (result, output) = convert(target_format, ColorProfilePolicy::Embed, &tiff(Some(icc.clone())));
+
+            assert!(
+                matches!(result, ConversionResult::Converted { ref color_profile, .. }
+                    if *color_profile == profile(ProfileHandling::Embedded)),
+                "{:?}",
+                target_format
+            );
+            assert_eq!(icc_profile(&output).as_ref(), Some(&icc), "{:?}", target_format);
+        }
+    }
+
+    #[test]
+    fn test_pixels_converted_to_srgb() {
+        let (result, output) = convert(TargetFormat::Png, ColorProfilePolicy::ConvertToSrgb, &tiff(Some(display_p3())));
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { ref color_profile, .. }
+                if *color_profile == profile(ProfileHandling::ConvertedToSrgb)
+        ));
+        assert_eq!(icc_profile(&output), None);
+        let [r, g, b] = image::load_from_memory_with_format(&output, ImageFormat::Png)
+            .unwrap()
+            .to_rgb8()
+            .get_pixel(4, 4)
+            .0;
+        // The same colour needs more saturated sRGB values
+        assert!(r > ORANGE[0] + 10 && b < ORANGE[2] - 10, "converted to {:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn test_images_without_profile_untouched() {
+        let (result, output) = convert(TargetFormat::Png, ColorProfilePolicy::ConvertToSrgb, &tiff(None));
+
+        assert!(matches!(result, ConversionResult::Converted { color_profile: None, .. }));
+        assert_eq!(icc_profile(&output), None);
+        let img = image::load_from_memory_with_format(&output, ImageFormat::Png).unwrap();
+        assert_eq!(img.to_rgb8().get_pixel(4, 4).0, ORANGE);
+    }
+
+    #[tokio::test]
+    async fn test_report_lists_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_zip(&input, &[("wide.tif", tiff(Some(display_p3()))), ("plain.tif", tiff(None))]);
+
+        let options = JobOptions {
+            conversion: ConversionSettings {
+                color_profile: ColorProfilePolicy::ConvertToSrgb,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let profiles = &output.report.color_profiles;
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].original_path, "wide.tif");
+        assert_eq!(profiles[0].output_path, "wide.jpg");
+        assert_eq!(profiles[0].profile_name.as_deref(), Some("Display P3"));
+        assert_eq!(profiles[0].handling, ProfileHandling::ConvertedToSrgb);
+        assert_eq!(output.report.conversion_settings.color_profile, ColorProfilePolicy::ConvertToSrgb);
+        assert_eq!(output.report.stats.files_converted, 2);
+    }
+}
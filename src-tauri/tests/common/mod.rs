@@ -0,0 +1,56 @@
+// Helpers shared by the integration tests. Each test file is its own crate and
+// uses only some of them.
+#![allow(dead_code)]
+
+use file_sorter_lib::processor::progress::ProgressReporter;
+use file_sorter_lib::processor::ProgressInfo;
+use image::{ImageFormat, RgbImage};
+use std::fs::{self, File};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+
+/// Ignores progress and never cancels
+pub struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {
+    fn report(&self, _job_id: &str, _progress: ProgressInfo) {}
+
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// A zip archive of `(name, contents)` entries, in memory
+pub fn zip_of(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, data) in entries {
+        writer.start_file(*name, FileOptions::default()).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// Write a zip archive of `(name, contents)` entries to `path`
+pub fn write_zip(path: &Path, entries: &[(&str, Vec<u8>)]) {
+    fs::write(path, zip_of(entries)).unwrap();
+}
+
+/// Names of the entries in the zip at `path`, sorted
+pub fn entry_names(path: impl AsRef<Path>) -> Vec<String> {
+    let archive = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+    names.sort();
+    names
+}
+
+pub fn encode(img: &RgbImage, format: ImageFormat) -> Vec<u8> {
+    let mut data = Cursor::new(Vec::new());
+    img.write_to(&mut data, format).unwrap();
+    data.into_inner()
+}
+
+/// A small BMP, which default jobs convert
+pub fn bmp() -> Vec<u8> {
+    encode(&RgbImage::from_pixel(4, 4, image::Rgb([10, 20, 30])), ImageFormat::Bmp)
+}
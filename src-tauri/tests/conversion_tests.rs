@@ -0,0 +1,184 @@
+// Tests for per-job conversion settings (target format, quality, chroma subsampling)
+
+#[cfg(test)]
+mod conversion_tests {
+    use exif::experimental::Writer;
+    use exif::{Field, In, Reader, Tag, Value};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ExifOutcome, ImageConverter};
+    use file_sorter_lib::processor::options::{ChromaSubsampling, ConversionSettings, TargetFormat};
+    use file_sorter_lib::report::ReportBuilder;
+    use image::{ImageEncoder, ImageFormat, RgbImage};
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+    use std::path::{Path, PathBuf};
+
+    fn write_webp_with_exif(path: &Path) {
+        let field = Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![b"2023:06:15 14:30:00".to_vec()]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let img = RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 128]));
+        let mut encoder = image::codecs::webp::WebPEncoder::new_lossless(File::create(path).unwrap());
+        encoder.set_exif_metadata(exif.into_inner()).unwrap();
+        encoder
+            .write_image(img.as_raw(), 16, 16, image::ExtendedColorType::Rgb8)
+            .unwrap();
+    }
+
+    fn convert(settings: ConversionSettings, output_name: &str) -> (tempfile::TempDir, PathBuf, ConversionResult) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.webp");
+        let output = dir.path().join(output_name);
+        write_webp_with_exif(&input);
+
+        let result = ImageConverter::with_settings(settings)
+            .process_image(&input, &output)
+            .unwrap();
+        (dir, output, result)
+    }
+
+    fn has_capture_date(path: &Path) -> bool {
+        let file = File::open(path).unwrap();
+        Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .map(|exif| exif.get_field(Tag::DateTimeOriginal, In::PRIMARY).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Horizontal and vertical sampling factors of the first (luma) component in the SOF header
+    fn jpeg_luma_sampling(path: &Path) -> (u8, u8) {
+        let bytes = std::fs::read(path).unwrap();
+        let sof = bytes
+            .windows(2)
+            .position(|w| w == [0xFF, 0xC0])
+            .expect("baseline SOF0 marker");
+        // marker(2) length(2) precision(1) height(2) width(2) components(1) id(1) sampling(1)
+        let sampling = bytes[sof + 11];
+        (sampling >> 4, sampling & 0x0F)
+    }
+
+    #[test]
+    fn test_default_settings_write_jpeg() {
+        let (_dir, output, result) = convert(ConversionSettings::default(), "photo.jpg");
+
+        assert!(matches!(result, ConversionResult::Converted { exif: ExifOutcome::Carried, .. }));
+        assert_eq!(image::guess_format(&std::fs::read(&output).unwrap()).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(jpeg_luma_sampling(&output), (1, 1));
+    }
+
+    #[test]
+    fn test_jpeg_chroma_subsampling() {
+        let settings = ConversionSettings {
+            chroma_subsampling: ChromaSubsampling::Yuv420,
+            quality: 80,
+            ..Default::default()
+        };
+        let (_dir, output, _) = convert(settings, "photo.jpg");
+
+        assert_eq!(jpeg_luma_sampling(&output), (2, 2));
+        assert!(has_capture_date(&output));
+    }
+
+    #[test]
+    fn test_png_target() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::Png,
+            ..Default::default()
+        };
+        let (_dir, output, result) = convert(settings, "photo.png");
+
+        assert!(matches!(result, ConversionResult::Converted { exif: ExifOutcome::Carried, .. }));
+        assert_eq!(image::guess_format(&std::fs::read(&output).unwrap()).unwrap(), ImageFormat::Png);
+        assert!(has_capture_date(&output));
+    }
+
+    #[test]
+    fn test_lossy_webp_target_keeps_exif() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::WebpLossy,
+            quality: 70,
+            reencode_supported: true,
+            ..Default::default()
+        };
+        let (_dir, output, result) = convert(settings, "photo-lossy.webp");
+
+        assert!(matches!(result, ConversionResult::Converted { exif: ExifOutcome::Carried, .. }));
+        let img = image::open(&output).unwrap();
+        assert_eq!((img.width(), img.height()), (16, 16));
+        assert!(has_capture_date(&output));
+    }
+
+    #[test]
+    fn test_avif_target() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::Avif,
+            quality: 60,
+            ..Default::default()
+        };
+        let (_dir, output, result) = convert(settings, "photo.avif");
+
+        assert!(matches!(result, ConversionResult::Converted { .. }));
+        assert_eq!(image::guess_format(&std::fs::read(&output).unwrap()).unwrap(), ImageFormat::Avif);
+    }
+
+    #[test]
+    fn test_input_in_target_format_copied_unless_reencoding() {
+        let path = Path::new("photo.webp");
+        let webp = ConversionSettings {
+            target_format: TargetFormat::WebpLossless,
+            ..Default::default()
+        };
+
+        assert!(!ImageConverter::with_settings(webp).needs_conversion(path));
+        assert!(ImageConverter::with_settings(ConversionSettings::default()).needs_conversion(path));
+        assert!(ImageConverter::with_settings(ConversionSettings {
+            reencode_supported: true,
+            ..webp
+        })
+        .needs_conversion(path));
+    }
+
+    #[test]
+    fn test_reencode_supported_converts_png() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("logo.png");
+        let output = dir.path().join("logo.jpg");
+        RgbImage::from_pixel(4, 4, image::Rgb([10, 20, 30])).save(&input).unwrap();
+
+        let settings = ConversionSettings {
+            reencode_supported: true,
+            ..Default::default()
+        };
+        let result = ImageConverter::with_settings(settings)
+            .process_image(&input, &output)
+            .unwrap();
+
+        assert!(matches!(result, ConversionResult::Converted { .. }));
+        assert_eq!(image::guess_format(&std::fs::read(&output).unwrap()).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_report_records_settings() {
+        let mut builder = ReportBuilder::new(Path::new("photos.zip"));
+        builder.set_conversion_settings(ConversionSettings {
+            target_format: TargetFormat::WebpLossy,
+            quality: 70,
+            ..Default::default()
+        });
+        builder.add_conversion("a.heic".into(), "a.webp".into(), "HEIC".into(), ExifOutcome::Carried);
+
+        let report = builder.build();
+
+        assert_eq!(report.conversion_settings.target_format, TargetFormat::WebpLossy);
+        assert!(report
+            .metadata_notes
+            .iter()
+            .any(|note| note == "All converted images encoded as lossy WebP with quality 70"));
+    }
+}
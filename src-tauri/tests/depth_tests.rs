@@ -0,0 +1,155 @@
+// Tests for sources with more than 8 bits per channel: dithering, 16-bit PNG output and HDR tone mapping
+
+mod common;
+
+#[cfg(test)]
+mod depth_tests {
+    use crate::common::{entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, DepthConversion, ImageConverter};
+    use file_sorter_lib::processor::options::{ConversionSettings, JobOptions, TargetFormat};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::depth;
+    use file_sorter_lib::utils::hdr::{self, HdrFormat, Primaries};
+    use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    /// A shallow grey ramp in steps of 8 columns, each a fraction of an 8-bit step
+    /// apart, so rounding would leave flat bands
+    fn ramp() -> ImageBuffer<Rgb<u16>, Vec<u16>> {
+        ImageBuffer::from_fn(64, 8, |x, _| {
+            let value = 100 * 257 + (x / 8) as u16 * 29;
+            Rgb([value, value, value])
+        })
+    }
+
+    fn tiff_16_bit() -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb16(ramp()).write_to(&mut data, ImageFormat::Tiff).unwrap();
+        data.into_inner()
+    }
+
+    fn convert(settings: ConversionSettings) -> (ConversionResult, Vec<u8>) {
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings)
+            .process_bytes(Path::new("scan.tif"), &tiff_16_bit(), &mut output)
+            .unwrap();
+        (result, output)
+    }
+
+    #[test]
+    fn test_reduction_to_8_bits_dithered() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::WebpLossless,
+            ..Default::default()
+        };
+        let (result, output) = convert(settings);
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted {
+                bit_depth: Some(DepthConversion { source_bits: 16, hdr: None, output_bits: 8, dithered: true }),
+                ..
+            }
+        ));
+        // Each 8x8 step averages out to its exact value
+        let img = image::load_from_memory(&output).unwrap().to_rgb8();
+        for step in 0..8 {
+            let block: Vec<u8> = img
+                .enumerate_pixels()
+                .filter(|(x, _, _)| x / 8 == step)
+                .map(|(_, _, p)| p.0[0])
+                .collect();
+            let mean = block.iter().map(|&v| f64::from(v)).sum::<f64>() / 64.0;
+            let exact = f64::from(ramp().get_pixel(step * 8, 0).0[0]) / 257.0;
+            assert!((mean - exact).abs() < 0.03, "step {}: mean {} for {}", step, mean, exact);
+            // Only the first step falls exactly on an 8-bit value
+            assert_eq!(block.iter().any(|&v| v != block[0]), step > 0, "step {}", step);
+        }
+    }
+
+    #[test]
+    fn test_high_bit_depth_written_as_16_bit_png() {
+        let settings = ConversionSettings {
+            high_bit_depth_png: true,
+            ..Default::default()
+        };
+        let (result, output) = convert(settings);
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted {
+                bit_depth: Some(DepthConversion { source_bits: 16, output_bits: 16, dithered: false, .. }),
+                ref extension,
+                ..
+            } if extension == "png"
+        ));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Png);
+        let img = image::load_from_memory(&output).unwrap();
+        assert_eq!(img.as_rgb16().unwrap(), &ramp());
+    }
+
+    #[test]
+    fn test_10_bit_samples_expanded() {
+        let ten_bit = ImageBuffer::from_fn(3, 1, |x, _| {
+            let value = [0, 512, 1023][x as usize];
+            Rgb([value, value, value])
+        });
+        let expanded = depth::expand_to_16_bits(DynamicImage::ImageRgb16(ten_bit), 10);
+        let samples: Vec<u16> = expanded.as_rgb16().unwrap().pixels().map(|p| p.0[0]).collect();
+        assert_eq!(samples, [0, 32800, 65535]);
+    }
+
+    #[test]
+    fn test_pq_tone_mapped_to_sdr() {
+        // PQ signal levels for 0, 50, 203 (SDR white), 1000 and 10000 nits
+        let levels = [0.0, 0.4403, 0.5807, 0.7518, 1.0];
+        let pq = ImageBuffer::from_fn(levels.len() as u32, 1, |x, _| {
+            let value = (levels[x as usize] * 65535.0_f64).round() as u16;
+            Rgb([value, value, value])
+        });
+        let mapped = hdr::tone_map(&DynamicImage::ImageRgb16(pq), HdrFormat::Pq, Primaries::Bt2020).to_rgb8();
+        let grey: Vec<u8> = mapped.pixels().map(|p| p.0[1]).collect();
+
+        assert_eq!(grey[0], 0);
+        // 50 nits is a quarter of SDR white, below the knee and left alone: sRGB 137
+        assert!(grey[1].abs_diff(137) <= 2, "{:?}", grey);
+        // SDR white is pulled down a little to make room for highlights
+        assert!((200..250).contains(&grey[2]), "{:?}", grey);
+        assert_eq!(grey[3], 255);
+        assert_eq!(grey[4], 255);
+        // Neutral greys stay neutral through the BT.2020 to sRGB conversion
+        assert!(mapped.pixels().all(|p| p.0[0].abs_diff(p.0[2]) <= 1));
+    }
+
+    #[tokio::test]
+    async fn test_job_reports_bit_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_zip(&input, &[("scans/page.tif", tiff_16_bit())]);
+
+        let options = JobOptions {
+            conversion: ConversionSettings {
+                high_bit_depth_png: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["report.json", "scans/page.png"]);
+
+        let records = &output.report.high_bit_depth;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].output_path, "scans/page.png");
+        assert_eq!((records[0].source_bits, records[0].output_bits), (16, 16));
+        assert!(output
+            .report
+            .metadata_notes
+            .iter()
+            .any(|note| note == "1 image(s) with more than 8 bits per channel encoded as 16-bit PNG"));
+    }
+}
@@ -0,0 +1,183 @@
+// Tests for derivative sizes (thumbnails and the like) written alongside each image
+
+mod common;
+
+#[cfg(test)]
+mod derivative_tests {
+    use crate::common::{self, entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter};
+    use file_sorter_lib::processor::options::{
+        ConversionSettings, DerivativeLayout, DerivativeSettings, DerivativeSize, JobOptions, ResizeSettings,
+    };
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::{JobInfo, JobOutput};
+    use image::{GenericImageView, ImageFormat, RgbImage};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        common::encode(&RgbImage::from_pixel(width, height, image::Rgb([40, 120, 200])), format)
+    }
+
+    fn size(name: &str, max_long_edge: u32) -> DerivativeSize {
+        DerivativeSize {
+            name: name.to_string(),
+            max_long_edge,
+        }
+    }
+
+    async fn run(dir: &Path, entries: &[(&str, Vec<u8>)], derivatives: DerivativeSettings) -> JobOutput {
+        let input = dir.join("input.zip");
+        write_zip(&input, entries);
+
+        let options = JobOptions {
+            derivatives,
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        process_job(&SilentReporter, &job, dir).await.unwrap()
+    }
+
+    fn dimensions(archive: &mut zip::ZipArchive<File>, name: &str) -> (u32, u32) {
+        let mut data = Vec::new();
+        archive.by_name(name).unwrap().read_to_end(&mut data).unwrap();
+        image::load_from_memory(&data).unwrap().dimensions()
+    }
+
+    #[test]
+    fn test_layout_paths() {
+        let path = Path::new("photos/a.jpg");
+        assert_eq!(
+            DerivativeLayout::Folders.path("thumb", path, "webp"),
+            PathBuf::from("thumb/photos/a.webp")
+        );
+        assert_eq!(
+            DerivativeLayout::Suffix.path("thumb", path, "jpg"),
+            PathBuf::from("photos/a_thumb.jpg")
+        );
+    }
+
+    #[test]
+    fn test_invalid_sizes_rejected() {
+        let settings = |sizes| DerivativeSettings {
+            sizes,
+            layout: DerivativeLayout::Folders,
+        };
+        assert!(settings(vec![size("thumb", 256), size("medium", 1024)]).validate().is_ok());
+        assert!(settings(vec![size(" ", 256)]).validate().is_err());
+        assert!(settings(vec![size("a/b", 256)]).validate().is_err());
+        assert!(settings(vec![size("..", 256)]).validate().is_err());
+        assert!(settings(vec![size("thumb", 0)]).validate().is_err());
+        assert!(settings(vec![size("thumb", 256), size("thumb", 128)]).validate().is_err());
+    }
+
+    #[test]
+    fn test_extras_made_alongside_conversion() {
+        // The full-size image is limited to 200 pixels; derivatives are scaled from
+        // the decoded image, not from that
+        let settings = ConversionSettings {
+            resize: ResizeSettings {
+                max_long_edge: Some(200),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let converter = ImageConverter::with_settings(settings)
+            .with_derivatives(vec![size("thumb", 64), size("large", 320), size("medium", 128)]);
+
+        let mut output = Vec::new();
+        let (result, extras) = converter
+            .process_bytes_with_extras(Path::new("a.tif"), &encode(400, 300, ImageFormat::Tiff), &mut output, true)
+            .unwrap();
+        assert!(matches!(result, ConversionResult::Converted { .. }));
+        assert_eq!(image::load_from_memory(&output).unwrap().dimensions(), (200, 150));
+        let made: Vec<(&str, (u32, u32))> =
+            extras.derivatives.iter().map(|d| (d.name.as_str(), d.dimensions)).collect();
+        assert_eq!(made, [("thumb", (64, 48)), ("large", (320, 240)), ("medium", (128, 96))]);
+        assert!(extras.perceptual_hash.is_some());
+
+        // Copied images are decoded for their extras, and only hashed when asked
+        let mut output = Vec::new();
+        let (result, extras) = converter
+            .process_bytes_with_extras(Path::new("b.png"), &encode(100, 50, ImageFormat::Png), &mut output, false)
+            .unwrap();
+        assert!(matches!(result, ConversionResult::Copied));
+        assert_eq!(extras.derivatives.len(), 3);
+        assert_eq!(extras.derivatives[0].dimensions, (64, 32));
+        assert!(extras.perceptual_hash.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_derivatives_in_folder_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = [
+            ("photos/a.tif", encode(400, 300, ImageFormat::Tiff)),
+            ("photos/b.jpg", encode(200, 100, ImageFormat::Jpeg)),
+        ];
+        let derivatives = DerivativeSettings {
+            sizes: vec![size("thumb", 64), size("medium", 256)],
+            layout: DerivativeLayout::Folders,
+        };
+        let output = run(dir.path(), &entries, derivatives).await;
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        let names = entry_names(&output.output_path);
+        assert_eq!(
+            names,
+            [
+                "medium/photos/a.jpg",
+                "medium/photos/b.jpg",
+                "photos/a.jpg",
+                "photos/b.jpg",
+                "report.json",
+                "thumb/photos/a.jpg",
+                "thumb/photos/b.jpg",
+            ]
+        );
+        assert_eq!(dimensions(&mut archive, "photos/a.jpg"), (400, 300));
+        assert_eq!(dimensions(&mut archive, "thumb/photos/a.jpg"), (64, 48));
+        assert_eq!(dimensions(&mut archive, "medium/photos/a.jpg"), (256, 192));
+        // Copied images get derivatives too, and small ones aren't enlarged
+        assert_eq!(dimensions(&mut archive, "thumb/photos/b.jpg"), (64, 32));
+        assert_eq!(dimensions(&mut archive, "medium/photos/b.jpg"), (200, 100));
+
+        let records = &output.report.derivatives;
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].original_path, "photos/a.tif");
+        assert_eq!(records[0].full_size_path, "photos/a.jpg");
+        assert_eq!(records[0].name, "thumb");
+        assert_eq!(records[0].output_path, "thumb/photos/a.jpg");
+        assert_eq!((records[0].width, records[0].height), (64, 48));
+        assert_eq!(records[3].original_path, "photos/b.jpg");
+        assert_eq!(records[3].output_path, "medium/photos/b.jpg");
+        // Derivatives don't count as files of their own
+        assert_eq!(output.report.stats.files_included, 2);
+    }
+
+    #[tokio::test]
+    async fn test_suffixed_derivatives_follow_renamed_images() {
+        let dir = tempfile::tempdir().unwrap();
+        // Both become a.jpg, so the second is renamed
+        let entries = [
+            ("a.tif", encode(300, 300, ImageFormat::Tiff)),
+            ("a.bmp", encode(300, 300, ImageFormat::Bmp)),
+        ];
+        let derivatives = DerivativeSettings {
+            sizes: vec![size("thumb", 100)],
+            layout: DerivativeLayout::Suffix,
+        };
+        let output = run(dir.path(), &entries, derivatives).await;
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["a-1.jpg", "a-1_thumb.jpg", "a.jpg", "a_thumb.jpg", "report.json"]);
+
+        let paths: Vec<(&str, &str)> = output
+            .report
+            .derivatives
+            .iter()
+            .map(|record| (record.full_size_path.as_str(), record.output_path.as_str()))
+            .collect();
+        assert_eq!(paths, [("a.jpg", "a_thumb.jpg"), ("a-1.jpg", "a-1_thumb.jpg")]);
+    }
+}
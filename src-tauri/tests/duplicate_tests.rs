@@ -0,0 +1,130 @@
+// Tests for finding exact and near duplicate images and the keep-one policy
+
+mod common;
+
+#[cfg(test)]
+mod duplicate_tests {
+    use crate::common::{encode, entry_names, zip_of, SilentReporter};
+    use file_sorter_lib::processor::options::{DuplicatePolicy, DuplicateSettings, JobOptions, NestedZipMode};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::report::DuplicateKind;
+    use file_sorter_lib::utils::duplicates;
+    use image::imageops::FilterType;
+    use image::{DynamicImage, GrayImage, ImageFormat};
+
+    /// A smooth pattern of bright and dark patches; `phase` shifts it into a
+    /// different picture
+    fn pattern(size: u32, phase: f64) -> DynamicImage {
+        let img = GrayImage::from_fn(size, size, |x, y| {
+            let (x, y) = (x as f64 / size as f64, y as f64 / size as f64);
+            let value = 128.0 + 100.0 * (x * 9.0 + phase).sin() * (y * 7.0 + phase * 2.0).cos();
+            image::Luma([value as u8])
+        });
+        DynamicImage::ImageLuma8(img)
+    }
+
+    #[test]
+    fn test_groups() {
+        let a = duplicates::content_hash(b"a");
+        let b = duplicates::content_hash(b"b");
+        assert_eq!(duplicates::exact_groups(&[a, b, a, b, a]), [vec![0, 2, 4], vec![1, 3]]);
+        assert!(duplicates::exact_groups(&[a, b]).is_empty());
+
+        // 0 and 2 are each a bit from 1, so all three are grouped though 0 and 2 are two apart
+        let hashes = [0b0000, 0xffff, 0b0001, 0b0011, 0xfffe];
+        assert_eq!(duplicates::near_groups(&hashes, 1), [vec![0, 2, 3], vec![1, 4]]);
+        assert!(duplicates::near_groups(&hashes, 0).is_empty());
+    }
+
+    #[test]
+    fn test_difference_hash_of_copies() {
+        let original = pattern(256, 0.0);
+        let hash = duplicates::difference_hash(&original);
+
+        // Smaller and re-encoded copies stay close, another picture doesn't
+        let smaller = original.resize(100, 100, FilterType::Triangle);
+        let reencoded = image::load_from_memory(&encode(&original.to_rgb8(), ImageFormat::Jpeg)).unwrap();
+        assert!(duplicates::distance(hash, duplicates::difference_hash(&smaller)) <= 5);
+        assert!(duplicates::distance(hash, duplicates::difference_hash(&reencoded)) <= 5);
+        assert!(duplicates::distance(hash, duplicates::difference_hash(&pattern(256, 2.0))) > 10);
+    }
+
+    #[tokio::test]
+    async fn test_job_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        let photo = encode(&pattern(64, 0.0).to_rgb8(), ImageFormat::Jpeg);
+        let entries = [
+            ("a.jpg", photo.clone()),
+            // The same photo under another name, and in a nested zip
+            ("b.jpg", photo.clone()),
+            ("day2.zip", zip_of(&[("c.jpg", photo.clone())])),
+            // A smaller copy, and a different picture
+            ("d.png", encode(&pattern(32, 0.0).to_rgb8(), ImageFormat::Png)),
+            ("e.png", encode(&pattern(64, 2.0).to_rgb8(), ImageFormat::Png)),
+        ];
+        std::fs::write(&input, zip_of(&entries)).unwrap();
+
+        let run = |policy| {
+            let options = JobOptions {
+                nested_zip_mode: NestedZipMode::Flatten,
+                duplicates: DuplicateSettings {
+                    policy,
+                    near_duplicates: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            JobInfo::new(input.to_string_lossy().to_string(), options)
+        };
+        // Every copy is written and reported
+        let output = process_job(&SilentReporter, &run(DuplicatePolicy::KeepAllAndReport), dir.path())
+            .await
+            .unwrap();
+        assert_eq!(
+            entry_names(&output.output_path),
+            ["a.jpg", "b.jpg", "d.png", "day2/c.jpg", "e.png", "report.json"]
+        );
+        let groups = &output.report.duplicates;
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+        assert_eq!(groups[0].hash, duplicates::to_hex(&duplicates::content_hash(&photo)));
+        let files: Vec<_> = groups[0]
+            .files
+            .iter()
+            .map(|file| (file.original_path.as_str(), file.output_path.as_deref()))
+            .collect();
+        assert_eq!(
+            files,
+            [("a.jpg", Some("a.jpg")), ("b.jpg", Some("b.jpg")), ("day2.zip/c.jpg", Some("day2/c.jpg"))]
+        );
+        // Exact copies are listed once among the near duplicates
+        assert_eq!(groups[1].kind, DuplicateKind::Near);
+        let near: Vec<_> = groups[1].files.iter().map(|file| file.original_path.as_str()).collect();
+        assert_eq!(near, ["a.jpg", "d.png"]);
+
+        // Only the first copy is written; near duplicates are all kept
+        let output = process_job(&SilentReporter, &run(DuplicatePolicy::KeepOne), dir.path())
+            .await
+            .unwrap();
+        assert_eq!(entry_names(&output.output_path), ["a.jpg", "d.png", "e.png", "report.json"]);
+        let skipped: Vec<_> = output
+            .report
+            .skipped
+            .iter()
+            .map(|record| (record.path.as_str(), record.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [("b.jpg", "Duplicate of a.jpg"), ("day2.zip/c.jpg", "Duplicate of a.jpg")]
+        );
+        let outputs: Vec<_> = output.report.duplicates[0]
+            .files
+            .iter()
+            .map(|file| file.output_path.as_deref())
+            .collect();
+        assert_eq!(outputs, [Some("a.jpg"), None, None]);
+        assert_eq!(output.report.duplicates.len(), 2);
+    }
+}
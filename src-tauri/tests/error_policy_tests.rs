@@ -0,0 +1,84 @@
+// Tests for per-job error policies: failing fast, or carrying on past images that
+// can't be converted and listing them in the report
+
+mod common;
+
+#[cfg(test)]
+mod error_policy_tests {
+    use crate::common::{bmp, entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::options::{ErrorPolicy, JobOptions};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::{JobInfo, JobOutput, JobStatus};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+
+    const BROKEN_TIFF: &[u8] = b"not really a tiff";
+
+    /// Zip with a good `photo.bmp` and a corrupt `broken.tiff`
+    fn write_input_zip(path: &Path) {
+        write_zip(path, &[("broken.tiff", BROKEN_TIFF.to_vec()), ("photo.bmp", bmp())]);
+    }
+
+    async fn process_with(error_policy: ErrorPolicy) -> (tempfile::TempDir, anyhow::Result<JobOutput>) {
+        let output_dir = tempfile::tempdir().unwrap();
+        let input = output_dir.path().join("input.zip");
+        write_input_zip(&input);
+        let options = JobOptions {
+            error_policy,
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+
+        let result = process_job(&SilentReporter, &job, output_dir.path()).await;
+        (output_dir, result)
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_aborts_on_bad_image() {
+        let (_dir, result) = process_with(ErrorPolicy::FailFast).await;
+
+        let error = format!("{:#}", result.unwrap_err());
+        assert!(error.contains("broken.tiff"));
+    }
+
+    #[tokio::test]
+    async fn test_skip_and_report_leaves_image_out() {
+        let (_dir, result) = process_with(ErrorPolicy::SkipAndReport).await;
+        let output = result.unwrap();
+
+        assert_eq!(entry_names(&output.output_path), ["photo.jpg", "report.json"]);
+        assert_eq!(output.report.warning_count(), 1);
+        assert_eq!(output.report.stats.files_failed, 1);
+        assert_eq!(output.report.stats.files_converted, 1);
+        let failed = &output.report.failed[0];
+        assert_eq!(failed.original_path, "broken.tiff");
+        assert_eq!(failed.output_path, None);
+        assert!(!failed.error.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_copy_original_keeps_file_unchanged() {
+        let (_dir, result) = process_with(ErrorPolicy::CopyOriginalAndReport).await;
+        let output = result.unwrap();
+
+        assert_eq!(
+            entry_names(&output.output_path),
+            ["broken.tiff", "photo.jpg", "report.json"]
+        );
+        assert_eq!(output.report.failed[0].output_path.as_deref(), Some("broken.tiff"));
+        assert_eq!(output.report.stats.files_included, 2);
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        let mut copied = Vec::new();
+        archive.by_name("broken.tiff").unwrap().read_to_end(&mut copied).unwrap();
+        assert_eq!(copied, BROKEN_TIFF);
+    }
+
+    #[test]
+    fn test_completed_with_warnings_status_name() {
+        let json = serde_json::to_string(&JobStatus::CompletedWithWarnings).unwrap();
+
+        assert_eq!(json, "\"completedWithWarnings\"");
+    }
+}
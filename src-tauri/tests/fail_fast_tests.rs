@@ -1,58 +1,110 @@
 // Integration tests for fail-fast behavior
 // These tests verify that processing aborts on errors without creating partial outputs
 
+mod common;
+
 #[cfg(test)]
 mod fail_fast_tests {
+    use crate::common::{bmp, zip_of, SilentReporter};
+    use file_sorter_lib::processor::options::{
+        CollisionPolicy, JobOptions, NestedZipMode, OutputMode, OutputSettings,
+    };
     use file_sorter_lib::processor::temp_manager::TempManager;
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
     use std::fs;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
+
+    /// Good images either side of a corrupt TIFF inside a nested zip, so the job
+    /// has written output and extracted to its temp folder by the time it fails
+    fn write_input_zip(path: &Path) {
+        let nested = zip_of(&[("broken.tiff", b"not really a tiff".to_vec())]);
+        let entries = [("a.bmp", bmp()), ("day2.zip", nested), ("z.bmp", bmp())];
+        fs::write(path, zip_of(&entries)).unwrap();
+    }
+
+    fn job(input: &Path, mode: OutputMode, collision_policy: CollisionPolicy) -> JobInfo {
+        let options = JobOptions {
+            nested_zip_mode: NestedZipMode::Flatten,
+            output: Some(OutputSettings {
+                mode,
+                collision_policy,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        JobInfo::new(input.to_string_lossy().to_string(), options)
+    }
+
+    fn names_in(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn temp_dir_of(job: &JobInfo) -> PathBuf {
+        std::env::temp_dir().join(format!("file-sorter-{}", job.id))
+    }
 
     #[test]
     fn test_temp_manager_cleanup() {
-        let job_id = "test-cleanup";
-        let temp_path = {
-            let temp_manager = TempManager::new(job_id).unwrap();
-            let path = temp_manager.get_path().to_path_buf();
-            
-            // Verify temp directory was created
-            assert!(path.exists());
-            
-            // Create a test file
-            let test_file = path.join("test.txt");
-            fs::write(&test_file, b"test").unwrap();
-            assert!(test_file.exists());
-            
-            path
-        }; // temp_manager is dropped here
-
-        // After drop, temp directory should be cleaned up
-        // Note: This may not work immediately on all platforms due to async cleanup
-        // but it demonstrates the intent
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        // The directory should be cleaned up
-        // (In production, this happens automatically on Drop)
+        let temp_manager = TempManager::new("test-cleanup").unwrap();
+        let path = temp_manager.get_path().to_path_buf();
+        let nested = temp_manager.create_subdir("nested").unwrap();
+        fs::write(nested.join("0.zip"), b"test").unwrap();
+        assert!(path.exists());
+
+        drop(temp_manager);
+        assert!(!path.exists());
     }
 
-    #[test]
-    fn test_temp_subdirectories() {
-        let job_id = "test-subdirs";
-        let temp_manager = TempManager::new(job_id).unwrap();
-        
-        let extract_dir = temp_manager.get_extract_dir().unwrap();
-        let staging_dir = temp_manager.get_staging_dir().unwrap();
-        
-        assert!(extract_dir.exists());
-        assert!(staging_dir.exists());
-        assert_ne!(extract_dir, staging_dir);
+    #[tokio::test]
+    async fn test_failed_job_leaves_nothing_behind() {
+        for mode in [OutputMode::Zip, OutputMode::Directory] {
+            let dir = tempfile::tempdir().unwrap();
+            let input = dir.path().join("input.zip");
+            write_input_zip(&input);
+            let job = job(&input, mode, CollisionPolicy::Suffix);
+
+            let error = process_job(&SilentReporter, &job, dir.path()).await.unwrap_err();
+            assert!(format!("{:#}", error).contains("broken.tiff"));
+
+            // No output, no partial output, and no extracted nested zip
+            assert_eq!(names_in(dir.path()), ["input.zip"]);
+            assert!(!temp_dir_of(&job).exists());
+        }
     }
 
-    #[test]
-    fn test_output_zip_path() {
-        let job_id = "test-output";
-        let temp_manager = TempManager::new(job_id).unwrap();
-        
-        let output_path = temp_manager.get_output_zip_path();
-        assert!(output_path.to_string_lossy().contains("output.zip"));
+    #[tokio::test]
+    async fn test_failed_job_keeps_existing_output() {
+        for mode in [OutputMode::Zip, OutputMode::Directory] {
+            let dir = tempfile::tempdir().unwrap();
+            let input = dir.path().join("input.zip");
+            write_input_zip(&input);
+            let existing = match mode {
+                OutputMode::Zip => {
+                    fs::write(dir.path().join("input-converted.zip"), b"earlier run").unwrap();
+                    dir.path().join("input-converted.zip")
+                }
+                OutputMode::Directory => {
+                    fs::create_dir(dir.path().join("input-converted")).unwrap();
+                    fs::write(dir.path().join("input-converted/a.jpg"), b"earlier run").unwrap();
+                    dir.path().join("input-converted/a.jpg")
+                }
+            };
+            let job = job(&input, mode, CollisionPolicy::Overwrite);
+
+            assert!(process_job(&SilentReporter, &job, dir.path()).await.is_err());
+
+            assert_eq!(fs::read(&existing).unwrap(), b"earlier run");
+            let expected = match mode {
+                OutputMode::Zip => ["input-converted.zip", "input.zip"],
+                OutputMode::Directory => ["input-converted", "input.zip"],
+            };
+            assert_eq!(names_in(dir.path()), expected);
+        }
     }
 }
@@ -0,0 +1,117 @@
+// Tests for recognising image formats from their contents rather than their extension
+
+mod common;
+
+#[cfg(test)]
+mod format_tests {
+    use crate::common::{self, entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter};
+    use file_sorter_lib::processor::options::JobOptions;
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::format::SourceFormat;
+    use image::{ImageFormat, RgbImage};
+    use std::path::Path;
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        common::encode(&RgbImage::from_pixel(8, 8, image::Rgb([200, 100, 50])), format)
+    }
+
+    /// Leading `ftyp` box of an ISO base media file with the given brands
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len() as u32;
+        let mut data = size.to_be_bytes().to_vec();
+        data.extend_from_slice(b"ftyp");
+        data.extend_from_slice(major);
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            data.extend_from_slice(*brand);
+        }
+        data
+    }
+
+    #[test]
+    fn test_detects_formats_from_contents() {
+        let cases = [
+            (ImageFormat::Jpeg, SourceFormat::Jpeg),
+            (ImageFormat::Png, SourceFormat::Png),
+            (ImageFormat::Gif, SourceFormat::Gif),
+            (ImageFormat::WebP, SourceFormat::Webp),
+            (ImageFormat::Tiff, SourceFormat::Tiff),
+            (ImageFormat::Bmp, SourceFormat::Bmp),
+        ];
+        for (encoded_as, expected) in cases {
+            assert_eq!(SourceFormat::detect(&encode(encoded_as)), Some(expected));
+        }
+
+        assert_eq!(SourceFormat::detect(&ftyp(b"heic", &[b"mif1", b"heic"])), Some(SourceFormat::Heic));
+        assert_eq!(SourceFormat::detect(&ftyp(b"avif", &[b"mif1", b"avif"])), Some(SourceFormat::Avif));
+        assert_eq!(SourceFormat::detect(&ftyp(b"isom", &[b"mp41"])), None);
+        assert_eq!(SourceFormat::detect(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_bmp_header_must_add_up() {
+        let bmp = encode(ImageFormat::Bmp);
+        assert_eq!(SourceFormat::detect(&bmp), Some(SourceFormat::Bmp));
+
+        // Text that happens to start with BM
+        assert_eq!(SourceFormat::detect(b"BMX meeting notes, 4th of May"), None);
+        let with = |at: usize, bytes: &[u8]| {
+            let mut data = bmp.clone();
+            data[at..at + bytes.len()].copy_from_slice(bytes);
+            data
+        };
+        assert_eq!(SourceFormat::detect(&with(2, &[10, 0, 0, 0])), None);
+        assert_eq!(SourceFormat::detect(&with(6, &[1, 0])), None);
+        assert_eq!(SourceFormat::detect(&with(14, &[41, 0, 0, 0])), None);
+        assert_eq!(SourceFormat::detect(&bmp[..16]), None);
+    }
+
+    #[test]
+    fn test_extension_ignores_case() {
+        assert_eq!(SourceFormat::from_path(Path::new("IMG.JPG")), Some(SourceFormat::Jpeg));
+        assert_eq!(SourceFormat::from_path(Path::new("scan.TIF")), Some(SourceFormat::Tiff));
+        assert_eq!(SourceFormat::from_path(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn test_mislabelled_webp_is_converted() {
+        let mut output = Vec::new();
+        let result = ImageConverter::new()
+            .process_bytes(Path::new("photo.png"), &encode(ImageFormat::WebP), &mut output)
+            .unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { ref original_format, .. } if original_format == "WEBP"
+        ));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[tokio::test]
+    async fn test_job_fixes_extensions_and_reports_mismatches() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_zip(&input, &[
+            ("IMG.JPG", encode(ImageFormat::Jpeg)),
+            ("photo.png", encode(ImageFormat::WebP)),
+            ("scan.jpg", encode(ImageFormat::Png)),
+        ]);
+
+        let job = JobInfo::new(input.to_string_lossy().to_string(), JobOptions::default());
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["IMG.JPG", "photo.jpg", "report.json", "scan.png"]);
+
+        let mismatches = &output.report.format_mismatches;
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].original_path, "photo.png");
+        assert_eq!(mismatches[0].declared_extension, "png");
+        assert_eq!(mismatches[0].detected_format, "WEBP");
+        assert_eq!(mismatches[1].original_path, "scan.jpg");
+        assert_eq!(mismatches[1].detected_format, "PNG");
+        assert_eq!(output.report.stats.files_converted, 1);
+    }
+}
@@ -0,0 +1,158 @@
+// Tests for folder and loose-file job inputs
+
+mod common;
+
+#[cfg(test)]
+mod input_tests {
+    use crate::common::{entry_names, SilentReporter};
+    use file_sorter_lib::processor::input::{group_paths, list_directory, JobInput};
+    use file_sorter_lib::processor::options::{JobOptions, NestedZipMode};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use image::RgbImage;
+    use std::fs;
+    use std::path::Path;
+
+    fn write_bmp(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50])).save(path).unwrap();
+    }
+
+    /// `photos/` holding `a.bmp`, `day1/b.bmp` and `notes.txt`
+    fn photo_folder(root: &Path) -> std::path::PathBuf {
+        let folder = root.join("photos");
+        write_bmp(&folder.join("a.bmp"));
+        write_bmp(&folder.join("day1").join("b.bmp"));
+        fs::write(folder.join("notes.txt"), "hello").unwrap();
+        folder
+    }
+
+    #[test]
+    fn test_group_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = photo_folder(dir.path());
+        let paths = vec![
+            dir.path().join("one.zip").to_string_lossy().to_string(),
+            folder.join("a.bmp").to_string_lossy().to_string(),
+            folder.to_string_lossy().to_string(),
+            folder.join("notes.txt").to_string_lossy().to_string(),
+        ];
+
+        let grouped = group_paths(paths.clone());
+
+        let jobs = grouped.jobs;
+        assert_eq!(jobs.len(), 3);
+        assert_eq!(jobs[0], (paths[0].clone(), JobInput::Zip));
+        assert_eq!(jobs[1], (paths[2].clone(), JobInput::Directory));
+        assert_eq!(
+            jobs[2],
+            (
+                paths[2].clone(),
+                JobInput::Files {
+                    paths: vec![paths[1].clone()]
+                }
+            )
+        );
+        // Loose files that aren't images don't make a job
+        assert_eq!(grouped.ignored, [paths[3].clone()]);
+        assert!(group_paths(vec![paths[3].clone()]).jobs.is_empty());
+    }
+
+    #[test]
+    fn test_list_directory_is_recursive_and_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = photo_folder(dir.path());
+
+        let names: Vec<String> = list_directory(&folder)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(names, ["a.bmp", "day1/b.bmp", "notes.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_directory_skips_symlinked_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let folder = photo_folder(dir.path());
+        // A link back up the tree would otherwise be walked forever
+        std::os::unix::fs::symlink(&folder, folder.join("day1").join("loop")).unwrap();
+        std::os::unix::fs::symlink(folder.join("a.bmp"), folder.join("linked.bmp")).unwrap();
+
+        let names: Vec<String> = list_directory(&folder)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+
+        assert_eq!(names, ["a.bmp", "day1/b.bmp", "linked.bmp", "notes.txt"]);
+    }
+
+    #[tokio::test]
+    async fn test_directory_input_mirrors_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let folder = photo_folder(dir.path());
+        let job = JobInfo::new(folder.to_string_lossy().to_string(), JobOptions::default());
+        assert_eq!(job.input, JobInput::Directory);
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        assert!(output.output_path.ends_with("photos-converted.zip"));
+        assert_eq!(
+            entry_names(&output.output_path),
+            ["a.jpg", "day1/b.jpg", "notes.txt", "report.json"]
+        );
+        assert_eq!(output.report.stats.files_converted, 2);
+        // Source files are read in place and left alone
+        assert!(folder.join("a.bmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_loose_files_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        write_bmp(&dir.path().join("first/IMG_1.bmp"));
+        write_bmp(&dir.path().join("second/IMG_1.bmp"));
+        let paths = vec![
+            dir.path().join("first/IMG_1.bmp").to_string_lossy().to_string(),
+            dir.path().join("second/IMG_1.bmp").to_string_lossy().to_string(),
+        ];
+        let (input_path, input) = group_paths(paths).jobs.remove(0);
+        let job = JobInfo::with_input(input_path, input, JobOptions::default());
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        // Named after the folder of the first file; same-named files get the usual suffix
+        assert!(output.output_path.ends_with("first-converted.zip"));
+        assert_eq!(
+            entry_names(&output.output_path),
+            ["IMG_1-1.jpg", "IMG_1.jpg", "report.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zip_in_folder_follows_nested_zip_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let folder = dir.path().join("photos");
+        fs::create_dir_all(&folder).unwrap();
+        let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/basic.zip");
+        fs::copy(fixture, folder.join("day1.zip")).unwrap();
+        write_bmp(&folder.join("cover.bmp"));
+        let options = JobOptions {
+            nested_zip_mode: NestedZipMode::Flatten,
+            ..Default::default()
+        };
+        let job = JobInfo::new(folder.to_string_lossy().to_string(), options);
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert!(names.contains(&"cover.jpg".to_string()));
+        assert!(names.iter().any(|name| name.starts_with("day1/")));
+        assert!(!names.contains(&"day1.zip".to_string()));
+    }
+}
@@ -0,0 +1,138 @@
+// Tests for saving the job queue and history between app sessions
+
+#[cfg(test)]
+mod job_store_tests {
+    use file_sorter_lib::processor::job_store::{JobStore, StoredJobs};
+    use file_sorter_lib::processor::options::JobOptions;
+    use file_sorter_lib::processor::{JobInfo, JobStatus, ProcessorState};
+    use file_sorter_lib::report::ReportBuilder;
+    use std::path::Path;
+
+    fn store_in(dir: &tempfile::TempDir) -> JobStore {
+        JobStore::new(dir.path().join("data").join("jobs.json"))
+    }
+
+    #[test]
+    fn test_missing_file_loads_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = store_in(&dir).load();
+
+        assert!(stored.queue.is_empty());
+        assert!(stored.history.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(&dir);
+        let mut finished = JobInfo::new("/tmp/old.zip".to_string(), JobOptions::default());
+        finished.status = JobStatus::Success;
+        finished.output_path = Some("/tmp/old-converted.zip".to_string());
+
+        store
+            .save(&StoredJobs {
+                queue: vec![JobInfo::new("/tmp/new.zip".to_string(), JobOptions::default())],
+                history: vec![finished],
+            })
+            .unwrap();
+        let stored = store.load();
+
+        assert_eq!(stored.queue.len(), 1);
+        assert_eq!(stored.queue[0].input_path, "/tmp/new.zip");
+        assert_eq!(stored.history[0].output_path.as_deref(), Some("/tmp/old-converted.zip"));
+        assert!(!store.path().with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn test_unreadable_file_set_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = store_in(&dir);
+        std::fs::create_dir_all(store.path().parent().unwrap()).unwrap();
+        // Cut short by a crash, and saved by a version with a status this one doesn't know
+        for garbage in [r#"{"queue": [{"id": "a", "inpu"#, r#"{"queue": [{"status": "paused"}]}"#] {
+            std::fs::write(store.path(), garbage).unwrap();
+
+            let state = ProcessorState::with_store(store_in(&dir));
+
+            assert!(!state.has_pending());
+            assert!(state.list_history().is_empty());
+            let aside = store.path().with_file_name("jobs.json.corrupt");
+            assert_eq!(std::fs::read_to_string(aside).unwrap(), garbage);
+            // The empty queue replaces it
+            assert!(store.load().queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_interrupted_jobs_are_requeued() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut interrupted = JobInfo::new("/tmp/a.zip".to_string(), JobOptions::default());
+        interrupted.status = JobStatus::Processing;
+        store_in(&dir)
+            .save(&StoredJobs {
+                queue: vec![interrupted],
+                history: Vec::new(),
+            })
+            .unwrap();
+
+        let state = ProcessorState::with_store(store_in(&dir));
+
+        assert!(state.has_pending());
+        assert_eq!(state.list_jobs()[0].status, JobStatus::Pending);
+        // The reset is saved straight away
+        assert_eq!(store_in(&dir).load().queue[0].status, JobStatus::Pending);
+    }
+
+    #[test]
+    fn test_reports_saved_apart_from_jobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut finished = JobInfo::new("/tmp/old.zip".to_string(), JobOptions::default());
+        finished.status = JobStatus::Success;
+        let mut report = ReportBuilder::new(Path::new("/tmp/old.zip"));
+        report.increment_scanned();
+        finished.report = Some(report.build());
+        // Saved by an older version, with the report inline
+        store_in(&dir)
+            .save(&StoredJobs {
+                queue: Vec::new(),
+                history: vec![finished.clone()],
+            })
+            .unwrap();
+
+        let state = ProcessorState::with_store(store_in(&dir));
+
+        let saved = std::fs::read_to_string(store_in(&dir).path()).unwrap();
+        assert!(!saved.contains("conversionSettings"));
+        let history = state.list_history();
+        assert!(history[0].report.is_none());
+        assert_eq!(history[0].summary.as_ref().unwrap().stats.files_scanned, 1);
+        let report = state.job_report(&finished.id).unwrap().unwrap();
+        assert_eq!(report.stats.files_scanned, 1);
+        assert!(state.job_report("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_finished_jobs_kept_in_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = ProcessorState::with_store(store_in(&dir));
+        let jobs = state
+            .add_jobs(vec!["/tmp/a.zip".into(), "/tmp/b.zip".into()], JobOptions::default())
+            .unwrap()
+            .jobs;
+
+        state.get_next_pending().unwrap();
+        state.mark_cancelled(&jobs[0].id);
+        state.clear_finished();
+
+        let history = state.list_history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].status, JobStatus::Cancelled);
+        assert!(history[0].finished_at.is_some());
+        assert_eq!(state.list_jobs().len(), 1);
+
+        // A new session sees the same queue and history
+        let restored = ProcessorState::with_store(store_in(&dir));
+        assert_eq!(restored.list_jobs()[0].id, jobs[1].id);
+        assert_eq!(restored.list_history()[0].id, jobs[0].id);
+    }
+}
@@ -0,0 +1,160 @@
+// Tests for lossless JPEG rotation given truncated, corrupt and unsupported files
+
+#[cfg(test)]
+mod jpeg_transform_tests {
+    use file_sorter_lib::utils::jpeg_transform;
+    use image::metadata::Orientation;
+    use image::RgbImage;
+    use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    const SOF0: u8 = 0xC0;
+    const DHT: u8 = 0xC4;
+    const SOS: u8 = 0xDA;
+    const DRI: u8 = 0xDD;
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| image::Rgb([(x * 7) as u8, (y * 5) as u8, ((x + y) * 3) as u8]))
+    }
+
+    fn jpeg(sampling: SamplingFactor, restart_interval: u16, progressive: bool) -> Vec<u8> {
+        let img = gradient(40, 24);
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, 90);
+        encoder.set_sampling_factor(sampling);
+        encoder.set_restart_interval(restart_interval);
+        encoder.set_progressive(progressive);
+        encoder.encode(img.as_raw(), 40, 24, ColorType::Rgb).unwrap();
+        data
+    }
+
+    /// Offset of the first segment with `marker`, at its 0xFF byte
+    fn find_segment(data: &[u8], marker: u8) -> usize {
+        let mut pos = 2;
+        while data[pos + 1] != marker {
+            assert_ne!(data[pos + 1], SOS, "no segment {:02X} before the scan", marker);
+            pos += 2 + usize::from(u16::from_be_bytes([data[pos + 2], data[pos + 3]]));
+        }
+        pos
+    }
+
+    fn with_bytes(data: &[u8], at: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        data[at..at + bytes.len()].copy_from_slice(bytes);
+        data
+    }
+
+    /// Rotate `data` on another thread, failing the test if that panics or doesn't
+    /// finish in time. Returns the error message, if any.
+    fn try_transform(data: Vec<u8>) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let result = jpeg_transform::transform(&data, Orientation::Rotate90);
+            let _ = sender.send(result.map(|_| ()).map_err(|e| format!("{:#}", e)));
+        });
+        match receiver.recv_timeout(Duration::from_secs(10)) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => panic!("transform didn't finish"),
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("transform panicked"),
+        }
+    }
+
+    fn transform_error(data: Vec<u8>) -> String {
+        try_transform(data).expect_err("corrupt JPEG was transformed")
+    }
+
+    #[test]
+    fn test_truncated_files_rejected() {
+        let source = jpeg(SamplingFactor::F_2_2, 2, false);
+        assert!(try_transform(source.clone()).is_ok());
+
+        for len in 0..source.len() {
+            let error = transform_error(source[..len].to_vec());
+            assert!(!error.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bad_huffman_tables_rejected() {
+        let source = jpeg(SamplingFactor::F_1_1, 0, false);
+        let dht = find_segment(&source, DHT);
+        let len = usize::from(u16::from_be_bytes([source[dht + 2], source[dht + 3]]));
+
+        // Too short for the code counts, or for the symbols they promise
+        let error = transform_error(with_bytes(&source, dht + 2, &[0, 10]));
+        assert!(error.contains("Huffman table"), "{}", error);
+        let error = transform_error(with_bytes(&source, dht + 2, &(len as u16 - 1).to_be_bytes()));
+        assert!(error.contains("Huffman table") || error.contains("marker"), "{}", error);
+        // Longer than the file, or shorter than its own length field
+        assert!(transform_error(with_bytes(&source, dht + 2, &[0xFF, 0xF0])).contains("Truncated"));
+        transform_error(with_bytes(&source, dht + 2, &[0, 1]));
+        // More than 256 codes, and a table id out of range
+        let error = transform_error(with_bytes(&source, dht + 5, &[0xFF; 16]));
+        assert!(error.contains("Huffman table"), "{}", error);
+        assert!(transform_error(with_bytes(&source, dht + 4, &[0x07])).contains("Invalid Huffman table"));
+    }
+
+    #[test]
+    fn test_bad_frame_headers_rejected() {
+        let source = jpeg(SamplingFactor::F_2_1, 0, false);
+        let sof = find_segment(&source, SOF0);
+
+        // Length cutting off the component specs
+        let error = transform_error(with_bytes(&source, sof + 2, &[0, 8]));
+        assert!(error.contains("Truncated JPEG frame header"), "{}", error);
+        // More components than the segment holds
+        let error = transform_error(with_bytes(&source, sof + 9, &[200]));
+        assert!(error.contains("Truncated JPEG frame header"), "{}", error);
+        assert!(transform_error(with_bytes(&source, sof + 5, &[0, 0])).contains("no dimensions"));
+        assert!(transform_error(with_bytes(&source, sof + 11, &[0x50])).contains("Invalid JPEG component"));
+        assert!(transform_error(with_bytes(&source, sof + 4, &[12])).contains("12-bit"));
+        // A few hundred bytes can't hold a 65535x65535 image, so it isn't allocated
+        let error = transform_error(with_bytes(&source, sof + 5, &[0xFF, 0xFF, 0xFF, 0xFF]));
+        assert!(error.contains("larger than its data"), "{}", error);
+    }
+
+    #[test]
+    fn test_bad_restart_markers_rejected() {
+        let source = jpeg(SamplingFactor::F_1_1, 1, false);
+        assert!(try_transform(source.clone()).is_ok());
+
+        // A restart marker turned into another marker, or one missing where expected
+        let scan = find_segment(&source, SOS);
+        let restart = scan + source[scan..].windows(2).position(|w| w == [0xFF, 0xD0]).unwrap();
+        let error = transform_error(with_bytes(&source, restart + 1, &[0xC8]));
+        assert!(error.contains("Missing JPEG restart marker"), "{}", error);
+
+        let dri = find_segment(&source, DRI);
+        let error = transform_error(with_bytes(&source, dri + 2, &[0, 2]));
+        assert!(error.contains("Truncated restart interval"), "{}", error);
+    }
+
+    #[test]
+    fn test_unsupported_files_rejected() {
+        let error = transform_error(jpeg(SamplingFactor::F_1_1, 0, true));
+        assert!(error.contains("Progressive"), "{}", error);
+        assert!(transform_error(b"GIF89a".to_vec()).contains("Not a JPEG"));
+    }
+
+    #[test]
+    fn test_corrupt_bytes_never_panic() {
+        // Deterministic noise over every part of the file, headers included
+        let source = jpeg(SamplingFactor::F_2_2, 3, false);
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+        for _ in 0..2000 {
+            let mut data = source.clone();
+            for _ in 0..1 + next() % 4 {
+                let at = next() % data.len();
+                data[at] = next() as u8;
+            }
+            let _ = try_transform(data);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// Tests for EXIF metadata transfer during conversion
+
+#[cfg(test)]
+mod metadata_tests {
+    use exif::experimental::Writer;
+    use exif::{Field, In, Reader, Tag, Value};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ExifOutcome, ImageConverter};
+    use file_sorter_lib::utils::metadata::MetadataHandler;
+    use image::{ImageEncoder, RgbImage};
+    use std::fs::File;
+    use std::io::{BufReader, Cursor};
+    use std::path::Path;
+
+    const CAPTURE_DATE: &[u8] = b"2023:06:15 14:30:00";
+
+    fn date_field() -> Field {
+        Field {
+            tag: Tag::DateTimeOriginal,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![CAPTURE_DATE.to_vec()]),
+        }
+    }
+
+    fn exif_block() -> Vec<u8> {
+        exif_block_of(&[date_field()])
+    }
+
+    fn exif_block_of(fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner()
+    }
+
+    fn read_capture_date(path: &Path) -> Option<Vec<u8>> {
+        let file = File::open(path).unwrap();
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .ok()?;
+        match &exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?.value {
+            Value::Ascii(values) => values.first().cloned(),
+            _ => None,
+        }
+    }
+
+    fn write_webp_with_exif(path: &Path) {
+        write_webp_with(path, exif_block());
+    }
+
+    fn write_webp_with(path: &Path, exif: Vec<u8>) {
+        let img = RgbImage::from_pixel(4, 4, image::Rgb([200, 100, 50]));
+        let mut encoder = image::codecs::webp::WebPEncoder::new_lossless(File::create(path).unwrap());
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder
+            .write_image(img.as_raw(), 4, 4, image::ExtendedColorType::Rgb8)
+            .unwrap();
+    }
+
+    fn write_tiff_with_exif(path: &Path) {
+        let fields = [
+            Field { tag: Tag::ImageWidth, ifd_num: In::PRIMARY, value: Value::Long(vec![1]) },
+            Field { tag: Tag::ImageLength, ifd_num: In::PRIMARY, value: Value::Long(vec![1]) },
+            Field { tag: Tag::BitsPerSample, ifd_num: In::PRIMARY, value: Value::Short(vec![8, 8, 8]) },
+            Field { tag: Tag::Compression, ifd_num: In::PRIMARY, value: Value::Short(vec![1]) },
+            Field { tag: Tag::PhotometricInterpretation, ifd_num: In::PRIMARY, value: Value::Short(vec![2]) },
+            Field { tag: Tag::SamplesPerPixel, ifd_num: In::PRIMARY, value: Value::Short(vec![3]) },
+            Field { tag: Tag::RowsPerStrip, ifd_num: In::PRIMARY, value: Value::Long(vec![1]) },
+            date_field(),
+        ];
+        let strip: &[u8] = &[255, 0, 0];
+        let strips = [strip];
+
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        writer.set_strips(&strips, In::PRIMARY);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        std::fs::write(path, buf.into_inner()).unwrap();
+    }
+
+    #[test]
+    fn test_webp_exif_carried_to_jpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.webp");
+        let output = dir.path().join("photo.jpg");
+        write_webp_with_exif(&input);
+
+        let result = ImageConverter::new().process_image(&input, &output).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { exif: ExifOutcome::Carried, .. }
+        ));
+        assert_eq!(read_capture_date(&output).as_deref(), Some(CAPTURE_DATE));
+    }
+
+    #[test]
+    fn test_oversized_exif_reported_as_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.webp");
+        let output = dir.path().join("photo.jpg");
+        let description = Field {
+            tag: Tag::ImageDescription,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![vec![b'x'; 70_000]]),
+        };
+        write_webp_with(&input, exif_block_of(&[date_field(), description]));
+
+        let result = ImageConverter::new().process_image(&input, &output).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { exif: ExifOutcome::Dropped, .. }
+        ));
+        assert_eq!(read_capture_date(&output), None);
+    }
+
+    #[test]
+    fn test_tiff_exif_carried_to_jpeg() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("scan.tiff");
+        let output = dir.path().join("scan.jpg");
+        write_tiff_with_exif(&input);
+
+        let result = ImageConverter::new().process_image(&input, &output).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { exif: ExifOutcome::Carried, .. }
+        ));
+        assert_eq!(read_capture_date(&output).as_deref(), Some(CAPTURE_DATE));
+
+        // Strip layout of the source TIFF must not leak into the JPEG's EXIF
+        let file = File::open(&output).unwrap();
+        let exif = Reader::new()
+            .read_from_container(&mut BufReader::new(file))
+            .unwrap();
+        assert!(exif.get_field(Tag::StripOffsets, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::BitsPerSample, In::PRIMARY).is_none());
+    }
+
+    #[test]
+    fn test_missing_exif_reported_as_not_preserved() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("plain.bmp");
+        let output = dir.path().join("plain.jpg");
+        RgbImage::from_pixel(2, 2, image::Rgb([0, 0, 0]))
+            .save(&input)
+            .unwrap();
+
+        let result = ImageConverter::new().process_image(&input, &output).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { exif: ExifOutcome::None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_heif_exif_block_header_stripped() {
+        let tiff = exif_block();
+
+        let mut block = vec![0, 0, 0, 6];
+        block.extend_from_slice(b"Exif\0\0");
+        block.extend_from_slice(&tiff);
+
+        assert_eq!(MetadataHandler::exif_from_heif_block(&block), Some(tiff));
+        assert_eq!(MetadataHandler::exif_from_heif_block(&[0, 0]), None);
+        assert_eq!(MetadataHandler::exif_from_heif_block(&[0, 0, 0, 9, 1]), None);
+    }
+}
@@ -0,0 +1,173 @@
+// Tests for applying the EXIF orientation, when converting and losslessly for copied JPEGs
+
+mod common;
+
+#[cfg(test)]
+mod orientation_tests {
+    use crate::common::{entry_names, write_zip, SilentReporter};
+    use exif::experimental::Writer;
+    use exif::{Field, In, Reader, Tag, Value};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ExifOutcome, ImageConverter};
+    use file_sorter_lib::processor::options::{ConversionSettings, JobOptions, TargetFormat};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::jpeg_transform;
+    use image::metadata::Orientation;
+    use image::{DynamicImage, RgbImage};
+    use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+    use std::fs::File;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    /// Four differently coloured quadrants, so every rotation and flip looks different
+    fn quadrants(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| match (x < width / 2, y < height / 2) {
+            (true, true) => image::Rgb([220, 30, 30]),
+            (false, true) => image::Rgb([30, 220, 30]),
+            (true, false) => image::Rgb([30, 30, 220]),
+            (false, false) => image::Rgb([230, 230, 40]),
+        })
+    }
+
+    fn orientation_exif(orientation: u16) -> Vec<u8> {
+        let field = Field {
+            tag: Tag::Orientation,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(vec![orientation]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        buf.into_inner()
+    }
+
+    fn jpeg(img: &RgbImage, orientation: u16, sampling: SamplingFactor, progressive: bool) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, 95);
+        encoder.set_sampling_factor(sampling);
+        encoder.set_progressive(progressive);
+        // Cameras commonly write restart markers
+        encoder.set_restart_interval(3);
+        encoder.add_exif_metadata(&orientation_exif(orientation)).unwrap();
+        let (width, height) = (img.width() as u16, img.height() as u16);
+        encoder.encode(img.as_raw(), width, height, ColorType::Rgb).unwrap();
+        data
+    }
+
+    fn exif_orientation(data: &[u8]) -> Option<u32> {
+        let exif = Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+        exif.get_field(Tag::Orientation, In::PRIMARY)?.value.get_uint(0)
+    }
+
+    fn mean_difference(a: &RgbImage, b: &RgbImage) -> f64 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        let total: u64 = a
+            .as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(&x, &y)| u64::from(x.abs_diff(y)))
+            .sum();
+        total as f64 / a.as_raw().len() as f64
+    }
+
+    /// What a viewer shows for a JPEG: its pixels turned by its EXIF orientation
+    fn displayed(data: &[u8]) -> RgbImage {
+        let mut img = image::load_from_memory(data).unwrap();
+        img.apply_orientation(jpeg_transform::orientation(data));
+        img.to_rgb8()
+    }
+
+    #[test]
+    fn test_converted_images_are_rotated_upright() {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::Png,
+            reencode_supported: true,
+            ..Default::default()
+        };
+        let source = jpeg(&quadrants(32, 16), 6, SamplingFactor::F_1_1, false);
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings)
+            .process_bytes(Path::new("photo.jpg"), &source, &mut output)
+            .unwrap();
+
+        assert!(matches!(result, ConversionResult::Converted { exif: ExifOutcome::Carried, .. }));
+        let converted = image::load_from_memory(&output).unwrap().to_rgb8();
+        assert_eq!(converted.dimensions(), (16, 32));
+        // Rotated 90 degrees clockwise, the top-left (red) quadrant ends up top right
+        assert!(converted.get_pixel(12, 4).0[0] > 180);
+        assert_eq!(exif_orientation(&output), Some(1));
+    }
+
+    #[test]
+    fn test_lossless_transform_matches_every_orientation() {
+        for sampling in [SamplingFactor::F_1_1, SamplingFactor::F_2_2] {
+            for orientation in 1..=8 {
+                let source = jpeg(&quadrants(48, 32), orientation, sampling, false);
+                let transformed = jpeg_transform::transform(&source, jpeg_transform::orientation(&source)).unwrap();
+
+                assert!(!transformed.trimmed);
+                assert_eq!(jpeg_transform::orientation(&transformed.data), Orientation::NoTransforms);
+                assert_eq!(exif_orientation(&transformed.data), Some(1));
+                let difference = mean_difference(&displayed(&transformed.data), &displayed(&source));
+                assert!(difference < 2.0, "orientation {}: mean difference {}", orientation, difference);
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_edge_blocks_trimmed() {
+        // 4:2:0 MCUs are 16x16, so mirroring a 40-pixel-tall image drops 8 rows
+        let source = jpeg(&quadrants(48, 40), 6, SamplingFactor::F_2_2, false);
+        let transformed = jpeg_transform::transform(&source, Orientation::Rotate90).unwrap();
+
+        assert!(transformed.trimmed);
+        let img = image::load_from_memory(&transformed.data).unwrap();
+        assert_eq!((img.width(), img.height()), (32, 48));
+    }
+
+    #[tokio::test]
+    async fn test_job_rotates_copied_jpegs() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        let upright = DynamicImage::ImageRgb8(quadrants(32, 16));
+        let rotated = jpeg(&quadrants(32, 16), 8, SamplingFactor::F_1_1, false);
+        let progressive = jpeg(&quadrants(32, 16), 3, SamplingFactor::F_1_1, true);
+        write_zip(&input, &[
+            ("rotated.jpg", rotated),
+            ("progressive.jpg", progressive.clone()),
+            ("upright.jpg", jpeg(&upright.to_rgb8(), 1, SamplingFactor::F_1_1, false)),
+        ]);
+
+        let options = JobOptions {
+            conversion: ConversionSettings {
+                rotate_copied_jpegs: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["progressive.jpg", "report.json", "rotated.jpg", "upright.jpg"]);
+
+        let mut data = Vec::new();
+        std::io::copy(&mut archive.by_name("rotated.jpg").unwrap(), &mut data).unwrap();
+        assert_eq!(image::load_from_memory(&data).unwrap().height(), 32);
+        let mut data = Vec::new();
+        std::io::copy(&mut archive.by_name("progressive.jpg").unwrap(), &mut data).unwrap();
+        assert_eq!(data, progressive);
+
+        let reoriented = &output.report.reoriented;
+        assert_eq!(reoriented.len(), 2);
+        assert_eq!(reoriented[0].original_path, "rotated.jpg");
+        assert_eq!(reoriented[0].orientation, 8);
+        assert!(reoriented[0].error.is_none());
+        assert_eq!(reoriented[1].original_path, "progressive.jpg");
+        assert!(reoriented[1].error.is_some());
+        assert_eq!(output.report.warning_count(), 1);
+        assert_eq!(output.report.stats.files_converted, 0);
+    }
+}
@@ -0,0 +1,294 @@
+// Tests for output settings: file name templates, collision policies and saved preferences
+
+mod common;
+
+#[cfg(test)]
+mod output_tests {
+    use crate::common::SilentReporter;
+    use file_sorter_lib::preferences::PreferencesStore;
+    use file_sorter_lib::processor::options::{
+        CollisionPolicy, JobOptions, OutputDestination, OutputMode, OutputSettings,
+    };
+    use file_sorter_lib::processor::temp_manager::PartialOutput;
+    use file_sorter_lib::processor::zip_handler::{process_job, OutputExists};
+    use file_sorter_lib::processor::JobInfo;
+    use std::path::Path;
+
+    fn job_with(output: OutputSettings) -> JobInfo {
+        let input = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/basic.zip");
+        JobInfo::new(
+            input.to_string_lossy().to_string(),
+            JobOptions {
+                output: Some(output),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn policy(collision_policy: CollisionPolicy) -> OutputSettings {
+        OutputSettings {
+            collision_policy,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_filename_template_tokens() {
+        let settings = OutputSettings {
+            filename_template: "{stem}_{date}_{job_id}".to_string(),
+            ..Default::default()
+        };
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        let name = settings.file_name(Path::new("/photos/trip.zip"), "abc");
+
+        assert_eq!(name, format!("trip_{}_abc.zip", today));
+        assert_eq!(OutputSettings::default().file_name(Path::new("trip.zip"), "abc"), "trip-converted.zip");
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_templates() {
+        let with_template = |template: &str| OutputSettings {
+            filename_template: template.to_string(),
+            ..Default::default()
+        };
+
+        assert!(with_template("{stem}").validate().is_ok());
+        assert!(with_template("  ").validate().is_err());
+        assert!(with_template("../{stem}").validate().is_err());
+        assert!(with_template(".").validate().is_err());
+        assert!(with_template(" .. ").validate().is_err());
+
+        // A stem that would make the folder the destination itself falls back to `output`
+        let folder = OutputSettings {
+            filename_template: "{stem}".to_string(),
+            ..folder_output()
+        };
+        assert_eq!(folder.file_name(Path::new("/photos/..zip"), "abc"), "output");
+    }
+
+    #[tokio::test]
+    async fn test_invalid_output_settings_rejected_by_job() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let settings = OutputSettings {
+            filename_template: "..".to_string(),
+            collision_policy: CollisionPolicy::Overwrite,
+            ..folder_output()
+        };
+
+        assert!(process_job(&SilentReporter, &job_with(settings), output_dir.path()).await.is_err());
+        assert!(output_dir.path().exists());
+    }
+
+    #[test]
+    fn test_next_to_input_destination() {
+        let dir = OutputDestination::NextToInput
+            .resolve(Path::new("/photos/trip.zip"))
+            .unwrap();
+
+        assert_eq!(dir, Path::new("/photos"));
+    }
+
+    #[tokio::test]
+    async fn test_suffix_policy_numbers_repeat_runs() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let job = job_with(policy(CollisionPolicy::Suffix));
+
+        process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+        let second = process_job(&SilentReporter, &job, output_dir.path())
+            .await
+            .unwrap()
+            .output_path;
+
+        assert!(second.ends_with("basic-converted-1.zip"));
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_policy_replaces_existing_file() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let existing = output_dir.path().join("basic-converted.zip");
+        std::fs::write(&existing, b"old").unwrap();
+
+        let output = process_job(&SilentReporter, &job_with(policy(CollisionPolicy::Overwrite)), output_dir.path())
+            .await
+            .unwrap();
+
+        assert_eq!(Path::new(&output.output_path), existing);
+        assert_ne!(std::fs::read(&existing).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_skip_policy_leaves_existing_file() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let existing = output_dir.path().join("basic-converted.zip");
+        std::fs::write(&existing, b"old").unwrap();
+
+        let err = process_job(&SilentReporter, &job_with(policy(CollisionPolicy::Skip)), output_dir.path())
+            .await
+            .unwrap_err();
+
+        let OutputExists(path) = err.downcast_ref::<OutputExists>().unwrap();
+        assert_eq!(path, &existing);
+        assert_eq!(std::fs::read(&existing).unwrap(), b"old");
+    }
+
+    fn folder_output() -> OutputSettings {
+        OutputSettings {
+            mode: OutputMode::Directory,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_directory_mode_writes_folder_with_report() {
+        let output_dir = tempfile::tempdir().unwrap();
+
+        let output = process_job(&SilentReporter, &job_with(folder_output()), output_dir.path())
+            .await
+            .unwrap();
+
+        let folder = Path::new(&output.output_path);
+        assert_eq!(folder, output_dir.path().join("basic-converted"));
+        assert!(folder.join("report.json").is_file());
+        assert!(std::fs::read_dir(folder).unwrap().count() > 1);
+        // Nothing but the finished folder is left in the destination
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_directory_mode_suffixes_existing_folder() {
+        let output_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(output_dir.path().join("basic-converted")).unwrap();
+
+        let output = process_job(&SilentReporter, &job_with(folder_output()), output_dir.path())
+            .await
+            .unwrap();
+
+        assert!(output.output_path.ends_with("basic-converted-1"));
+    }
+
+    #[tokio::test]
+    async fn test_directory_mode_overwrite_replaces_folder() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let existing = output_dir.path().join("basic-converted");
+        std::fs::create_dir(&existing).unwrap();
+        std::fs::write(existing.join("stale.jpg"), b"old").unwrap();
+        let settings = OutputSettings {
+            collision_policy: CollisionPolicy::Overwrite,
+            ..folder_output()
+        };
+
+        let output = process_job(&SilentReporter, &job_with(settings), output_dir.path())
+            .await
+            .unwrap();
+
+        assert_eq!(Path::new(&output.output_path), existing);
+        assert!(!existing.join("stale.jpg").exists());
+        assert!(existing.join("report.json").is_file());
+        // The old folder, moved aside while the new one went in, is gone
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_replace_keeps_existing_output() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let existing = output_dir.path().join("basic-converted");
+        std::fs::create_dir(&existing).unwrap();
+        std::fs::write(existing.join("photo.jpg"), b"old").unwrap();
+
+        // Never created, so moving it into place fails
+        let partial = PartialOutput::file(output_dir.path(), "job");
+
+        assert!(partial.finish(&existing).is_err());
+        assert_eq!(std::fs::read(existing.join("photo.jpg")).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_directory_mode_failure_leaves_nothing_behind() {
+        let input_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        std::fs::write(input_dir.path().join("notes.txt"), "no images here").unwrap();
+        let job = JobInfo::new(
+            input_dir.path().to_string_lossy().to_string(),
+            JobOptions {
+                output: Some(folder_output()),
+                ..Default::default()
+            },
+        );
+
+        assert!(process_job(&SilentReporter, &job, output_dir.path()).await.is_err());
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_input_report_json_does_not_clash_with_report() {
+        let input_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        std::fs::write(input_dir.path().join("report.json"), "{}").unwrap();
+        image::RgbImage::new(2, 2).save(input_dir.path().join("a.bmp")).unwrap();
+        let job = JobInfo::new(
+            input_dir.path().to_string_lossy().to_string(),
+            JobOptions {
+                output: Some(folder_output()),
+                ..Default::default()
+            },
+        );
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        let folder = Path::new(&output.output_path);
+        assert_eq!(std::fs::read_to_string(folder.join("report-1.json")).unwrap(), "{}");
+        assert_ne!(std::fs::read_to_string(folder.join("report.json")).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_preferences_saved_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("preferences.json");
+        let output = OutputSettings {
+            mode: OutputMode::Directory,
+            destination: OutputDestination::Folder {
+                path: "/srv/converted".to_string(),
+            },
+            filename_template: "{stem}-{date}".to_string(),
+            collision_policy: CollisionPolicy::Overwrite,
+        };
+
+        let store = PreferencesStore::load(path.clone());
+        assert_eq!(store.get().output, OutputSettings::default());
+        store.set_output(output.clone()).unwrap();
+
+        assert_eq!(PreferencesStore::load(path).get().output, output);
+    }
+
+    #[test]
+    fn test_unreadable_preferences_set_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("preferences.json");
+        std::fs::write(&path, "{\"output\": {\"mode\": ").unwrap();
+
+        let store = PreferencesStore::load(path.clone());
+
+        assert_eq!(store.get().output, OutputSettings::default());
+        assert!(!path.exists());
+        assert!(dir.path().join("preferences.json.corrupt").exists());
+    }
+
+    #[test]
+    fn test_invalid_preferences_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("preferences.json");
+        let store = PreferencesStore::load(path.clone());
+
+        let result = store.set_output(OutputSettings {
+            filename_template: String::new(),
+            ..Default::default()
+        });
+
+        assert!(result.is_err());
+        assert!(!path.exists());
+        assert_eq!(store.get().output, OutputSettings::default());
+    }
+}
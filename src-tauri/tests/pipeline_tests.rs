@@ -0,0 +1,276 @@
+// Integration tests for the zip processing pipeline
+// These tests drive process_job through a ProgressReporter without a Tauri app
+
+#[cfg(test)]
+mod pipeline_tests {
+    use file_sorter_lib::processor::options::{JobOptions, NestedZipMode, NonImagePolicy};
+    use file_sorter_lib::processor::progress::ProgressReporter;
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::{JobInfo, ProcessingPhase, ProgressInfo};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    struct RecordingReporter {
+        updates: Mutex<Vec<ProgressInfo>>,
+        cancelled: AtomicBool,
+    }
+
+    impl RecordingReporter {
+        fn new() -> Self {
+            Self {
+                updates: Mutex::new(Vec::new()),
+                cancelled: AtomicBool::new(false),
+            }
+        }
+    }
+
+    impl ProgressReporter for RecordingReporter {
+        fn report(&self, _job_id: &str, progress: ProgressInfo) {
+            self.updates.lock().unwrap().push(progress);
+        }
+
+        fn is_cancelled(&self) -> bool {
+            self.cancelled.load(Ordering::SeqCst)
+        }
+    }
+
+    fn fixture(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_process_writes_output_to_given_folder() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let reporter = RecordingReporter::new();
+        let job = JobInfo::new(fixture("basic.zip"), JobOptions::default());
+
+        let output_path = process_job(&reporter, &job, output_dir.path())
+            .await
+            .unwrap()
+            .output_path;
+
+        assert!(Path::new(&output_path).exists());
+        assert!(output_path.ends_with("basic-converted.zip"));
+
+        let updates = reporter.updates.lock().unwrap();
+        assert!(matches!(updates.first().unwrap().phase, ProcessingPhase::Scanning));
+        assert!(matches!(updates.last().unwrap().phase, ProcessingPhase::Packaging));
+        assert_eq!(
+            updates
+                .iter()
+                .filter(|p| matches!(p.phase, ProcessingPhase::Converting))
+                .count(),
+            3
+        );
+    }
+
+    async fn process_mixed(policy: NonImagePolicy) -> (tempfile::TempDir, zip::ZipArchive<File>) {
+        let output_dir = tempfile::tempdir().unwrap();
+        let options = JobOptions {
+            non_image_policy: policy,
+            ..Default::default()
+        };
+        let job = JobInfo::new(fixture("mixed.zip"), options);
+
+        let output_path = process_job(&RecordingReporter::new(), &job, output_dir.path())
+            .await
+            .unwrap()
+            .output_path;
+
+        let archive = zip::ZipArchive::new(File::open(output_path).unwrap()).unwrap();
+        (output_dir, archive)
+    }
+
+    fn read_report(archive: &mut zip::ZipArchive<File>) -> serde_json::Value {
+        let mut json = String::new();
+        archive
+            .by_name("report.json")
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_non_images_passed_through() {
+        let (_dir, mut archive) = process_mixed(NonImagePolicy::Passthrough).await;
+
+        assert!(archive.by_name("test1.jpg").is_ok());
+        assert!(archive.by_name("notes.txt").is_ok());
+        assert!(archive.by_name("clips/clip.mov").is_ok());
+
+        let mut notes = String::new();
+        archive
+            .by_name("notes.txt")
+            .unwrap()
+            .read_to_string(&mut notes)
+            .unwrap();
+        assert_eq!(notes, "notes\n");
+
+        let report = read_report(&mut archive);
+        assert_eq!(report["stats"]["filesPassedThrough"], 2);
+        assert_eq!(report["skipped"].as_array().unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_non_images_dropped_and_reported() {
+        let (_dir, mut archive) = process_mixed(NonImagePolicy::DropAndReport).await;
+
+        assert!(archive.by_name("notes.txt").is_err());
+
+        let report = read_report(&mut archive);
+        let skipped = report["skipped"].as_array().unwrap();
+        assert_eq!(skipped.len(), 2);
+        assert!(skipped.iter().any(|s| s["path"] == "clips/clip.mov"));
+    }
+
+    #[tokio::test]
+    async fn test_non_images_dropped_silently() {
+        let (_dir, mut archive) = process_mixed(NonImagePolicy::DropSilently).await;
+
+        assert!(archive.by_name("notes.txt").is_err());
+        assert!(archive.by_name("clips/clip.mov").is_err());
+
+        let report = read_report(&mut archive);
+        assert_eq!(report["skipped"].as_array().unwrap().len(), 0);
+        assert_eq!(report["stats"]["filesPassedThrough"], 0);
+    }
+
+    async fn process_nested(mode: NestedZipMode, max_nested_depth: usize) -> (tempfile::TempDir, zip::ZipArchive<File>) {
+        let output_dir = tempfile::tempdir().unwrap();
+        let options = JobOptions {
+            nested_zip_mode: mode,
+            max_nested_depth,
+            ..Default::default()
+        };
+        let job = JobInfo::new(fixture("zip_of_zips.zip"), options);
+
+        let output_path = process_job(&RecordingReporter::new(), &job, output_dir.path())
+            .await
+            .unwrap()
+            .output_path;
+
+        let archive = zip::ZipArchive::new(File::open(output_path).unwrap()).unwrap();
+        (output_dir, archive)
+    }
+
+    #[tokio::test]
+    async fn test_nested_zips_flattened_into_folders() {
+        let (_dir, mut archive) = process_nested(NestedZipMode::Flatten, 3).await;
+
+        assert!(archive.by_name("test1.jpg").is_ok());
+        assert!(archive.by_name("day1/test1.jpg").is_ok());
+        assert!(archive.by_name("day1/test1.png").is_ok());
+        assert!(archive.by_name("photographers/day2/test1.jpg").is_ok());
+        assert!(archive.by_name("photographers/day2/deeper/test2.jpg").is_ok());
+        assert!(archive.by_name("day1.zip").is_err());
+
+        let report = read_report(&mut archive);
+        assert_eq!(report["stats"]["filesIncluded"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_nested_zips_repacked() {
+        let (_dir, mut archive) = process_nested(NestedZipMode::Repack, 3).await;
+
+        let mut day2_bytes = Vec::new();
+        archive
+            .by_name("photographers/day2.zip")
+            .unwrap()
+            .read_to_end(&mut day2_bytes)
+            .unwrap();
+        let mut day2 = zip::ZipArchive::new(std::io::Cursor::new(day2_bytes)).unwrap();
+        assert!(day2.by_name("test1.jpg").is_ok());
+        assert!(day2.by_name("deeper.zip").is_ok());
+        assert!(day2.by_name("report.json").is_err());
+        assert!(archive.by_name("day1.zip").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_nested_zip_depth_limit_reported() {
+        let (_dir, mut archive) = process_nested(NestedZipMode::Flatten, 1).await;
+
+        assert!(archive.by_name("photographers/day2/test1.jpg").is_ok());
+        assert!(archive.by_name("photographers/day2/deeper/test2.jpg").is_err());
+        // With the default passthrough policy the too-deep zip is kept as is
+        assert!(archive.by_name("photographers/day2/deeper.zip").is_ok());
+
+        let report = read_report(&mut archive);
+        let passed = report["passedThrough"].as_array().unwrap();
+        assert_eq!(passed[0]["originalPath"], "photographers/day2.zip/deeper.zip");
+    }
+
+    async fn entry_names(worker_threads: usize) -> (Vec<String>, serde_json::Value) {
+        let output_dir = tempfile::tempdir().unwrap();
+        let options = JobOptions {
+            nested_zip_mode: NestedZipMode::Flatten,
+            worker_threads: Some(worker_threads),
+            ..Default::default()
+        };
+        let job = JobInfo::new(fixture("zip_of_zips.zip"), options);
+
+        let output_path = process_job(&RecordingReporter::new(), &job, output_dir.path())
+            .await
+            .unwrap()
+            .output_path;
+
+        let mut archive = zip::ZipArchive::new(File::open(output_path).unwrap()).unwrap();
+        let names = (0..archive.len())
+            .map(|i| archive.by_index(i).unwrap().name().to_string())
+            .collect();
+        let mut report = read_report(&mut archive);
+        report["timestamp"] = serde_json::Value::Null;
+        (names, report)
+    }
+
+    #[tokio::test]
+    async fn test_parallel_output_matches_single_worker() {
+        let serial = entry_names(1).await;
+        let parallel = entry_names(4).await;
+
+        assert_eq!(serial, parallel);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_progress_counts_up() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let reporter = RecordingReporter::new();
+        let options = JobOptions {
+            worker_threads: Some(4),
+            ..Default::default()
+        };
+        let job = JobInfo::new(fixture("basic.zip"), options);
+
+        process_job(&reporter, &job, output_dir.path())
+            .await
+            .unwrap();
+
+        let updates = reporter.updates.lock().unwrap();
+        let counts: Vec<usize> = updates
+            .iter()
+            .filter(|p| matches!(p.phase, ProcessingPhase::Converting))
+            .map(|p| p.current_file)
+            .collect();
+        assert_eq!(counts, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_reporter_aborts_without_output() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let reporter = RecordingReporter::new();
+        reporter.cancelled.store(true, Ordering::SeqCst);
+        let job = JobInfo::new(fixture("basic.zip"), JobOptions::default());
+
+        let result = process_job(&reporter, &job, output_dir.path()).await;
+
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 0);
+    }
+}
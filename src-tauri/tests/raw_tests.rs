@@ -0,0 +1,157 @@
+// Tests for camera RAW files: format detection, embedded previews and the RAW policy
+
+mod common;
+
+#[cfg(test)]
+mod raw_tests {
+    use crate::common::{entry_names, write_zip, SilentReporter};
+    use exif::experimental::Writer;
+    use exif::{Field, In, Reader, Tag, Value};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter};
+    use file_sorter_lib::processor::options::{ConversionSettings, JobOptions, RawPolicy};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::format::SourceFormat;
+    use file_sorter_lib::utils::raw::embedded_preview;
+    use image::{ImageFormat, RgbImage};
+    use std::fs::File;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    const CAPTURE_DATE: &[u8] = b"2023:06:15 14:30:00";
+
+    fn preview_jpeg() -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        RgbImage::from_pixel(16, 12, image::Rgb([40, 120, 200]))
+            .write_to(&mut data, ImageFormat::Jpeg)
+            .unwrap();
+        data.into_inner()
+    }
+
+    /// A TIFF laid out like a RAW file with no sensor data: camera tags in IFD0 and
+    /// a JPEG preview in IFD1
+    fn raw_file(preview: &[u8]) -> Vec<u8> {
+        let fields = [
+            Field { tag: Tag::Make, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"NIKON".to_vec()]) },
+            Field { tag: Tag::Model, ifd_num: In::PRIMARY, value: Value::Ascii(vec![b"Z 6".to_vec()]) },
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![CAPTURE_DATE.to_vec()]),
+            },
+        ];
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        writer.set_jpeg(preview, In::THUMBNAIL);
+        let mut buf = Cursor::new(Vec::new());
+        writer.write(&mut buf, true).unwrap();
+        buf.into_inner()
+    }
+
+    fn read_capture_date(data: &[u8]) -> Option<Vec<u8>> {
+        let exif = Reader::new().read_from_container(&mut Cursor::new(data)).ok()?;
+        match &exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?.value {
+            Value::Ascii(values) => values.first().cloned(),
+            _ => None,
+        }
+    }
+
+    fn preview_settings(keep_raw: bool) -> ConversionSettings {
+        ConversionSettings {
+            raw: RawPolicy::EmbeddedPreview,
+            keep_raw,
+            ..Default::default()
+        }
+    }
+
+    fn zip_with(dir: &Path, entries: &[(&str, Vec<u8>)]) -> String {
+        let input = dir.join("input.zip");
+        write_zip(&input, entries);
+        input.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_raw_formats_detected() {
+        let nef = raw_file(&preview_jpeg());
+        assert_eq!(SourceFormat::detect(&nef), Some(SourceFormat::Tiff));
+        assert_eq!(SourceFormat::detect_named(&nef, Path::new("DSC_0001.NEF")), Some(SourceFormat::Nef));
+        assert_eq!(SourceFormat::detect_named(&nef, Path::new("scan.tif")), Some(SourceFormat::Tiff));
+
+        let mut cr2 = b"II*\0\x10\0\0\0CR\x02\0".to_vec();
+        cr2.resize(64, 0);
+        assert_eq!(SourceFormat::detect(&cr2), Some(SourceFormat::Cr2));
+    }
+
+    #[test]
+    fn test_embedded_preview_found() {
+        let preview = preview_jpeg();
+        assert_eq!(embedded_preview(&raw_file(&preview)), Some(&preview[..]));
+        assert_eq!(embedded_preview(b"II*\0\x08\0\0\0\0\0\0\0\0\0"), None);
+        assert_eq!(embedded_preview(&preview), None);
+    }
+
+    #[test]
+    fn test_preview_converted_with_raw_exif() {
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(preview_settings(false))
+            .process_bytes(Path::new("DSC_0001.nef"), &raw_file(&preview_jpeg()), &mut output)
+            .unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Raw { ref original_format, method: RawPolicy::EmbeddedPreview, metadata_preserved: true, .. }
+                if original_format == "NEF"
+        ));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+        assert_eq!(image::load_from_memory(&output).unwrap().width(), 16);
+        assert_eq!(read_capture_date(&output).as_deref(), Some(CAPTURE_DATE));
+    }
+
+    #[tokio::test]
+    async fn test_job_keeps_raw_alongside_conversion() {
+        let dir = tempfile::tempdir().unwrap();
+        let nef = raw_file(&preview_jpeg());
+        let input = zip_with(dir.path(), &[("DSC_0001.nef", nef.clone())]);
+
+        let options = JobOptions {
+            conversion: preview_settings(true),
+            ..Default::default()
+        };
+        let job = JobInfo::new(input, options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["DSC_0001.jpg", "DSC_0001.nef", "report.json"]);
+
+        let mut kept = Vec::new();
+        std::io::copy(&mut archive.by_name("DSC_0001.nef").unwrap(), &mut kept).unwrap();
+        assert_eq!(kept, nef);
+
+        let raw = &output.report.raw;
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].original_path, "DSC_0001.nef");
+        assert_eq!(raw[0].output_path, "DSC_0001.jpg");
+        assert_eq!(raw[0].original_copy_path.as_deref(), Some("DSC_0001.nef"));
+        assert_eq!(raw[0].method, RawPolicy::EmbeddedPreview);
+        assert_eq!(output.report.stats.files_converted, 1);
+    }
+
+    #[tokio::test]
+    async fn test_raw_ignored_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = preview_jpeg();
+        let input = zip_with(dir.path(), &[("DSC_0001.nef", raw_file(&jpeg)), ("IMG_0002.jpg", jpeg)]);
+
+        let job = JobInfo::new(input, JobOptions::default());
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(names, ["DSC_0001.nef", "IMG_0002.jpg", "report.json"]);
+        assert!(output.report.raw.is_empty());
+        assert!(output.report.format_mismatches.is_empty());
+        assert_eq!(output.report.stats.files_converted, 0);
+    }
+}
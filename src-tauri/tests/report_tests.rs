@@ -2,6 +2,7 @@
 
 #[cfg(test)]
 mod report_tests {
+    use file_sorter_lib::processor::image_converter::ExifOutcome;
     use file_sorter_lib::report::{ReportBuilder, ProcessingReport};
     use std::path::Path;
 
@@ -29,7 +30,7 @@ mod report_tests {
             "image1.heic".to_string(),
             "image1.jpg".to_string(),
             "HEIC".to_string(),
-            true,
+            ExifOutcome::Carried,
         );
 
         builder.add_copied("image2.jpg".to_string(), "image2.jpg".to_string());
@@ -57,7 +58,7 @@ mod report_tests {
             "test.heic".to_string(),
             "test.jpg".to_string(),
             "HEIC".to_string(),
-            true,
+            ExifOutcome::Carried,
         );
 
         let json = builder.to_json().unwrap();
@@ -77,7 +78,7 @@ mod report_tests {
             "test.heic".to_string(),
             "test.jpg".to_string(),
             "HEIC".to_string(),
-            true,
+            ExifOutcome::Carried,
         );
 
         let report = builder.build();
@@ -91,9 +92,9 @@ mod report_tests {
         let input_path = Path::new("/path/to/test.zip");
         let mut builder = ReportBuilder::new(input_path);
 
-        builder.add_conversion("img1.heic".to_string(), "img1.jpg".to_string(), "HEIC".to_string(), true);
-        builder.add_conversion("img2.webp".to_string(), "img2.jpg".to_string(), "WEBP".to_string(), true);
-        builder.add_conversion("img3.bmp".to_string(), "img3.jpg".to_string(), "BMP".to_string(), false);
+        builder.add_conversion("img1.heic".to_string(), "img1.jpg".to_string(), "HEIC".to_string(), ExifOutcome::Carried);
+        builder.add_conversion("img2.webp".to_string(), "img2.jpg".to_string(), "WEBP".to_string(), ExifOutcome::Carried);
+        builder.add_conversion("img3.bmp".to_string(), "img3.jpg".to_string(), "BMP".to_string(), ExifOutcome::None);
 
         let report = builder.build();
 
@@ -102,4 +103,37 @@ mod report_tests {
         assert!(report.conversions.iter().any(|c| c.original_format == "WEBP"));
         assert!(report.conversions.iter().any(|c| c.original_format == "BMP"));
     }
+
+    #[test]
+    fn test_missing_and_dropped_exif_noted_apart() {
+        let mut builder = ReportBuilder::new(Path::new("/path/to/test.zip"));
+        builder.add_conversion("a.heic".to_string(), "a.jpg".to_string(), "HEIC".to_string(), ExifOutcome::Carried);
+        builder.add_conversion("b.bmp".to_string(), "b.jpg".to_string(), "BMP".to_string(), ExifOutcome::None);
+        builder.add_conversion("c.tiff".to_string(), "c.jpg".to_string(), "TIFF".to_string(), ExifOutcome::Dropped);
+        builder.add_conversion("d.tiff".to_string(), "d.jpg".to_string(), "TIFF".to_string(), ExifOutcome::Dropped);
+
+        let report = builder.build();
+
+        let notes = &report.metadata_notes;
+        assert!(notes.contains(&"1 converted file(s) had no EXIF metadata to carry over".to_string()));
+        assert!(notes
+            .iter()
+            .any(|note| note.starts_with("EXIF could not be carried over for 2 converted file(s)")));
+    }
+
+    #[test]
+    fn test_passthrough_counted_as_included() {
+        let input_path = Path::new("/path/to/test.zip");
+        let mut builder = ReportBuilder::new(input_path);
+
+        builder.add_passthrough("clip.mov".to_string(), "clip.mov".to_string());
+        builder.add_copied("image.jpg".to_string(), "image.jpg".to_string());
+
+        let report = builder.build();
+
+        assert_eq!(report.stats.files_included, 2);
+        assert_eq!(report.stats.files_passed_through, 1);
+        assert_eq!(report.passed_through[0].original_path, "clip.mov");
+        assert!(report.skipped.is_empty());
+    }
 }
@@ -0,0 +1,178 @@
+// Tests for resizing converted images to per-job size limits
+
+mod common;
+
+#[cfg(test)]
+mod resize_tests {
+    use crate::common::{self, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, Dimensions, ImageConverter};
+    use file_sorter_lib::processor::options::{ConversionSettings, JobOptions, ResizeMode, ResizeSettings};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::resize::{self, Plan};
+    use image::{GenericImageView, ImageFormat, Rgb, RgbImage};
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+
+    /// A width x height image with a red left half and a blue right half
+    fn encode(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let img = RgbImage::from_fn(width, height, |x, _| {
+            if x < width / 2 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        common::encode(&img, format)
+    }
+
+    fn limits(max_long_edge: Option<u32>, max_width: Option<u32>, max_height: Option<u32>) -> ResizeSettings {
+        ResizeSettings {
+            max_long_edge,
+            max_width,
+            max_height,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fit_within_limits() {
+        // A 48MP phone photo
+        let long_edge = limits(Some(2048), None, None);
+        assert_eq!(resize::plan(&long_edge, 8064, 6048), Some(Plan::Scale(2048, 1536)));
+        assert_eq!(resize::plan(&long_edge, 6048, 8064), Some(Plan::Scale(1536, 2048)));
+
+        // The tighter of several limits wins
+        let both = limits(Some(2048), None, Some(1000));
+        assert_eq!(resize::plan(&both, 8064, 6048), Some(Plan::Scale(1333, 1000)));
+
+        let megapixels = ResizeSettings {
+            max_megapixels: Some(12.0),
+            ..Default::default()
+        };
+        let Some(Plan::Scale(width, height)) = resize::plan(&megapixels, 8064, 6048) else {
+            panic!("48MP image not scaled to 12MP");
+        };
+        assert!(width * height <= 12_000_000 && width * height > 11_990_000, "{}x{}", width, height);
+        assert_eq!((width, height), (4000, 3000));
+    }
+
+    #[test]
+    fn test_small_images_not_upscaled() {
+        let settings = limits(Some(2048), None, None);
+        assert_eq!(resize::plan(&settings, 800, 600), None);
+        assert_eq!(resize::plan(&settings, 2048, 1536), None);
+
+        let upscale = ResizeSettings {
+            allow_upscale: true,
+            ..settings
+        };
+        assert_eq!(resize::plan(&upscale, 800, 600), Some(Plan::Scale(2048, 1536)));
+        assert_eq!(resize::plan(&ResizeSettings::default(), 8064, 6048), None);
+    }
+
+    #[test]
+    fn test_fill_and_exact() {
+        let fill = ResizeSettings {
+            mode: ResizeMode::Fill,
+            ..limits(None, Some(200), Some(200))
+        };
+        assert_eq!(
+            resize::plan(&fill, 400, 300),
+            Some(Plan::Fill { scaled: (267, 200), cropped: (200, 200) })
+        );
+        // Without upscaling a smaller image is only cropped to the box
+        assert_eq!(
+            resize::plan(&fill, 300, 150),
+            Some(Plan::Fill { scaled: (300, 150), cropped: (200, 150) })
+        );
+
+        let exact = ResizeSettings {
+            mode: ResizeMode::Exact,
+            ..limits(None, Some(200), Some(200))
+        };
+        assert_eq!(resize::plan(&exact, 400, 300), Some(Plan::Scale(200, 200)));
+        assert_eq!(resize::plan(&exact, 400, 100), Some(Plan::Scale(200, 100)));
+
+        // Cropping keeps the centre: red on the left, blue on the right
+        let img = image::load_from_memory(&encode(400, 300, ImageFormat::Png)).unwrap();
+        let filled = resize::resize(img, &fill);
+        assert_eq!(filled.dimensions(), (200, 200));
+        assert_eq!(filled.to_rgb8().get_pixel(10, 100).0, [255, 0, 0]);
+        assert_eq!(filled.to_rgb8().get_pixel(190, 100).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn test_converted_image_resized() {
+        let settings = ConversionSettings {
+            resize: limits(Some(100), None, None),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings)
+            .process_bytes(Path::new("photo.tif"), &encode(400, 300, ImageFormat::Tiff), &mut output)
+            .unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted {
+                dimensions: Dimensions { original: (400, 300), output: (100, 75) },
+                ..
+            }
+        ));
+        let img = image::load_from_memory_with_format(&output, ImageFormat::Jpeg).unwrap();
+        assert_eq!(img.dimensions(), (100, 75));
+    }
+
+    #[tokio::test]
+    async fn test_copied_images_resized_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        let small = encode(80, 60, ImageFormat::Jpeg);
+        write_zip(&input, &[
+            ("big.jpg", encode(400, 300, ImageFormat::Jpeg)),
+            ("big.png", encode(300, 400, ImageFormat::Png)),
+            ("small.jpg", small.clone()),
+        ]);
+
+        let options = JobOptions {
+            conversion: ConversionSettings {
+                resize: ResizeSettings {
+                    include_copied: true,
+                    ..limits(Some(100), None, None)
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        let mut read = |name: &str| {
+            let mut data = Vec::new();
+            archive.by_name(name).unwrap().read_to_end(&mut data).unwrap();
+            data
+        };
+        // Each keeps its own format
+        let jpeg = image::load_from_memory_with_format(&read("big.jpg"), ImageFormat::Jpeg).unwrap();
+        assert_eq!(jpeg.dimensions(), (100, 75));
+        let png = image::load_from_memory_with_format(&read("big.png"), ImageFormat::Png).unwrap();
+        assert_eq!(png.dimensions(), (75, 100));
+        assert_eq!(read("small.jpg"), small);
+
+        let report = &output.report;
+        assert_eq!(report.stats.files_converted, 2);
+        let sizes: Vec<_> = report
+            .dimensions
+            .iter()
+            .map(|r| (r.output_path.as_str(), r.original_width, r.original_height, r.output_width, r.output_height))
+            .collect();
+        assert_eq!(sizes, [("big.jpg", 400, 300, 100, 75), ("big.png", 300, 400, 75, 100)]);
+        assert!(report
+            .metadata_notes
+            .iter()
+            .any(|note| note == "2 image(s) resized to the job's size limits"));
+    }
+}
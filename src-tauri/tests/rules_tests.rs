@@ -0,0 +1,294 @@
+// Tests for sort rules: loading, matching on metadata and placing files by the first match
+
+mod common;
+
+#[cfg(test)]
+mod rules_tests {
+    use crate::common::{entry_names, SilentReporter};
+    use exif::experimental::Writer;
+    use exif::{Field, In, Rational, Tag, Value};
+    use file_sorter_lib::processor::options::{JobOptions, SortSettings};
+    use file_sorter_lib::processor::rules::{self, FileFacts, RuleMatch, Shape, SortRule};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::metadata::{MetadataHandler, PhotoInfo};
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use jpeg_encoder::{ColorType, Encoder};
+    use std::fs::{self, File};
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+    use zip::write::FileOptions;
+
+    fn ascii(tag: Tag, value: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        }
+    }
+
+    fn gps() -> Field {
+        let degrees = |d| Rational { num: d, denom: 1 };
+        Field {
+            tag: Tag::GPSLatitude,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![degrees(51), degrees(30), degrees(0)]),
+        }
+    }
+
+    /// A width x height JPEG carrying `fields` in its EXIF block
+    fn jpeg(width: u16, height: u16, fields: &[Field]) -> Vec<u8> {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let img = RgbImage::from_pixel(width.into(), height.into(), image::Rgb([90, 160, 60]));
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, 90);
+        encoder.add_exif_metadata(&exif.into_inner()).unwrap();
+        encoder.encode(img.as_raw(), width, height, ColorType::Rgb).unwrap();
+        data
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbImage::from_pixel(width, height, image::Rgb([90, 160, 60]));
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut data, ImageFormat::Png).unwrap();
+        data.into_inner()
+    }
+
+    fn rule(name: &str, conditions: RuleMatch, folder: &str) -> SortRule {
+        SortRule {
+            name: name.to_string(),
+            conditions,
+            folder: folder.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rules_loaded_from_toml_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("rules.toml");
+        fs::write(
+            &toml_path,
+            r#"
+[[rules]]
+name = "iphone"
+folder = "phones/{model}"
+match = { make = "Apple", hasGps = true }
+
+[[rules]]
+name = "raw"
+folder = "raw/{year}"
+
+[rules.match]
+format = ["DNG", "CR2"]
+minWidth = 3000
+orientation = "landscape"
+"#,
+        )
+        .unwrap();
+        let json_path = dir.path().join("rules.json");
+        fs::write(
+            &json_path,
+            r#"{ "rules": [
+                { "name": "iphone", "folder": "phones/{model}", "match": { "make": "Apple", "hasGps": true } },
+                { "name": "raw", "folder": "raw/{year}",
+                  "match": { "format": ["DNG", "CR2"], "minWidth": 3000, "orientation": "landscape" } }
+            ] }"#,
+        )
+        .unwrap();
+
+        let expected = vec![
+            rule(
+                "iphone",
+                RuleMatch {
+                    make: Some("Apple".to_string()),
+                    has_gps: Some(true),
+                    ..Default::default()
+                },
+                "phones/{model}",
+            ),
+            rule(
+                "raw",
+                RuleMatch {
+                    format: vec!["DNG".to_string(), "CR2".to_string()],
+                    min_width: Some(3000),
+                    orientation: Some(Shape::Landscape),
+                    ..Default::default()
+                },
+                "raw/{year}",
+            ),
+        ];
+        assert_eq!(rules::load(&toml_path).unwrap(), expected);
+        assert_eq!(rules::load(&json_path).unwrap(), expected);
+
+        fs::write(&toml_path, "[[rules]]\nfolder = \"no name\"\n").unwrap();
+        assert!(rules::load(&toml_path).is_err());
+    }
+
+    #[test]
+    fn test_conditions_match_metadata() {
+        let iphone = FileFacts {
+            photo: PhotoInfo {
+                make: Some("Apple".to_string()),
+                model: Some("iPhone 15 Pro".to_string()),
+                has_gps: true,
+                fields: [("Software".to_string(), "17.1".to_string())].into(),
+                ..Default::default()
+            },
+            format: Some("HEIC".to_string()),
+            size: Some((3024, 4032)),
+        };
+        let path = Path::new("day1.zip/IMG_0001.heic");
+        let matches = |conditions: RuleMatch, facts: &FileFacts| conditions.matches(path, facts);
+
+        assert!(matches(RuleMatch::default(), &iphone));
+        // Text patterns are globs and ignore case
+        assert!(matches(RuleMatch { make: Some("apple".to_string()), ..Default::default() }, &iphone));
+        assert!(matches(RuleMatch { model: Some("iPhone*".to_string()), ..Default::default() }, &iphone));
+        assert!(!matches(RuleMatch { model: Some("Pixel*".to_string()), ..Default::default() }, &iphone));
+        assert!(matches(
+            RuleMatch { format: vec!["jpeg".to_string(), "heic".to_string()], ..Default::default() },
+            &iphone
+        ));
+        assert!(matches(
+            RuleMatch { min_width: Some(3000), max_height: Some(4032), ..Default::default() },
+            &iphone
+        ));
+        assert!(!matches(RuleMatch { min_height: Some(5000), ..Default::default() }, &iphone));
+        assert!(matches(RuleMatch { orientation: Some(Shape::Portrait), ..Default::default() }, &iphone));
+        assert!(!matches(RuleMatch { orientation: Some(Shape::Landscape), ..Default::default() }, &iphone));
+        assert!(!matches(RuleMatch { has_gps: Some(false), ..Default::default() }, &iphone));
+        // Patterns without a slash match the name, with one the whole input path
+        assert!(matches(RuleMatch { filename: Some("IMG_*".to_string()), ..Default::default() }, &iphone));
+        assert!(matches(RuleMatch { filename: Some("day*.zip/*.heic".to_string()), ..Default::default() }, &iphone));
+        assert!(!matches(RuleMatch { filename: Some("*.jpg".to_string()), ..Default::default() }, &iphone));
+        // Other EXIF fields by tag name, which ignores case like the pattern
+        let exif = |tag: &str, pattern: &str| RuleMatch {
+            exif: [(tag.to_string(), pattern.to_string())].into(),
+            ..Default::default()
+        };
+        assert!(matches(exif("software", "17.*"), &iphone));
+        assert!(!matches(exif("Software", "16.*"), &iphone));
+        assert!(!matches(exif("Artist", "*"), &iphone));
+
+        // Files without the metadata a condition asks about don't match it
+        let unknown = FileFacts::default();
+        assert!(!matches(RuleMatch { make: Some("*".to_string()), ..Default::default() }, &unknown));
+        assert!(!matches(RuleMatch { orientation: Some(Shape::Square), ..Default::default() }, &unknown));
+        assert!(matches(RuleMatch { has_gps: Some(false), ..Default::default() }, &unknown));
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let settings = |rules| SortSettings {
+            template: "{year}/{filename}".to_string(),
+            rules,
+        };
+        let any = || RuleMatch::default();
+
+        assert!(settings(vec![rule("a", any(), "a/{model}"), rule("b", any(), "")]).validate().is_ok());
+        assert!(settings(vec![rule(" ", any(), "a")]).validate().is_err());
+        assert!(settings(vec![rule("a", any(), "a"), rule("a", any(), "b")]).validate().is_err());
+        assert!(settings(vec![rule("a", any(), "{camera}")]).validate().is_err());
+        assert!(settings(vec![rule("a", any(), "../a")]).validate().is_err());
+        let bad_glob = RuleMatch { model: Some("[iPhone".to_string()), ..Default::default() };
+        assert!(settings(vec![rule("a", bad_glob, "a")]).validate().is_err());
+        let bad_field = RuleMatch { exif: [("Artist".to_string(), "[Jane".to_string())].into(), ..Default::default() };
+        assert!(settings(vec![rule("a", bad_field, "a")]).validate().is_err());
+        let bad_size = RuleMatch { min_width: Some(200), max_width: Some(100), ..Default::default() };
+        assert!(settings(vec![rule("a", bad_size, "a")]).validate().is_err());
+    }
+
+    #[test]
+    fn test_photo_info_from_exif() {
+        let fields = [ascii(Tag::Make, "Canon"), ascii(Tag::Model, "EOS R5"), ascii(Tag::Artist, "Jane Doe"), gps()];
+        let info = MetadataHandler::photo_info(&jpeg(8, 8, &fields));
+        assert_eq!(info.make.as_deref(), Some("Canon"));
+        assert_eq!(info.model.as_deref(), Some("EOS R5"));
+        assert_eq!(info.lens, None);
+        assert!(info.has_gps);
+        assert_eq!(info.fields.get("Artist").map(String::as_str), Some("Jane Doe"));
+        assert_eq!(info.fields.get("Model").map(String::as_str), Some("EOS R5"));
+        assert_eq!(MetadataHandler::photo_info(&png(8, 8)), PhotoInfo::default());
+    }
+
+    #[tokio::test]
+    async fn test_job_sorted_by_first_matching_rule() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        let iphone = [ascii(Tag::Make, "Apple"), ascii(Tag::Model, "iPhone 15 Pro"), gps()];
+        let entries = [
+            // Portrait, but the iPhone rule comes first
+            ("a.jpg", jpeg(30, 40, &iphone)),
+            ("b.jpg", jpeg(40, 30, &[ascii(Tag::Make, "Canon")])),
+            ("c.png", png(30, 40)),
+            ("notes.txt", b"notes".to_vec()),
+        ];
+        let mut writer = zip::ZipWriter::new(File::create(&input).unwrap());
+        for (name, data) in &entries {
+            let options = FileOptions::default()
+                .last_modified_time(zip::DateTime::from_date_and_time(2022, 8, 1, 9, 0, 0).unwrap());
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let sorting = SortSettings {
+            template: "{year}/{filename}".to_string(),
+            rules: vec![
+                rule(
+                    "iphone",
+                    RuleMatch { make: Some("Apple".to_string()), ..Default::default() },
+                    "phones/{model}",
+                ),
+                rule(
+                    "portraits",
+                    RuleMatch { orientation: Some(Shape::Portrait), ..Default::default() },
+                    "portraits",
+                ),
+            ],
+        };
+        let run = |dry_run| {
+            let options = JobOptions {
+                sorting: Some(sorting.clone()),
+                dry_run,
+                ..Default::default()
+            };
+            JobInfo::new(input.to_string_lossy().to_string(), options)
+        };
+
+        // A dry run reports where everything would go and writes nothing
+        let output = process_job(&SilentReporter, &run(true), dir.path()).await.unwrap();
+        assert!(output.report.dry_run);
+        assert!(!Path::new(&output.output_path).exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        let sorted: Vec<_> = output
+            .report
+            .sorted
+            .iter()
+            .map(|r| (r.original_path.as_str(), r.output_path.as_str(), r.rule.as_deref()))
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                ("a.jpg", "phones/iPhone 15 Pro/a.jpg", Some("iphone")),
+                ("b.jpg", "2022/b.jpg", None),
+                ("c.png", "portraits/c.png", Some("portraits")),
+                ("notes.txt", "2022/notes.txt", None),
+            ]
+        );
+
+        let output = process_job(&SilentReporter, &run(false), dir.path()).await.unwrap();
+        assert!(!output.report.dry_run);
+        let names = entry_names(&output.output_path);
+        assert_eq!(
+            names,
+            ["2022/b.jpg", "2022/notes.txt", "phones/iPhone 15 Pro/a.jpg", "portraits/c.png", "report.json"]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// Tests for sorting the output into folders by capture date
+
+mod common;
+
+#[cfg(test)]
+mod sorting_tests {
+    use crate::common::{entry_names, SilentReporter};
+    use chrono::{NaiveDate, NaiveDateTime};
+    use exif::experimental::Writer;
+    use exif::{Field, In, Tag, Value};
+    use file_sorter_lib::processor::options::{JobOptions, SortSettings};
+    use file_sorter_lib::processor::rules::FileFacts;
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::report::DateSource;
+    use file_sorter_lib::utils::metadata::MetadataHandler;
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use jpeg_encoder::{ColorType, Encoder};
+    use std::fs::File;
+    use std::io::{Cursor, Write};
+    use std::path::{Path, PathBuf};
+    use zip::write::FileOptions;
+
+    fn date(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    /// A JPEG whose EXIF block holds `value` under `tag`
+    fn jpeg_dated(tag: Tag, value: &[u8]) -> Vec<u8> {
+        let field = Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.to_vec()]),
+        };
+        let mut writer = Writer::new();
+        writer.push_field(&field);
+        let mut exif = Cursor::new(Vec::new());
+        writer.write(&mut exif, false).unwrap();
+
+        let img = RgbImage::from_pixel(8, 8, image::Rgb([90, 160, 60]));
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, 90);
+        encoder.add_exif_metadata(&exif.into_inner()).unwrap();
+        encoder.encode(img.as_raw(), 8, 8, ColorType::Rgb).unwrap();
+        data
+    }
+
+    fn tiff() -> Vec<u8> {
+        let img = RgbImage::from_pixel(8, 8, image::Rgb([90, 160, 60]));
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut data, ImageFormat::Tiff).unwrap();
+        data.into_inner()
+    }
+
+    fn template(template: &str) -> SortSettings {
+        SortSettings {
+            template: template.to_string(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Where the template alone sends a file at `path`
+    fn sorted(settings: &SortSettings, path: &Path, taken: Option<NaiveDateTime>) -> PathBuf {
+        settings.destination(path, path, taken, &FileFacts::default()).0
+    }
+
+    #[test]
+    fn test_template_paths() {
+        let path = Path::new("photos/holiday/IMG_0001.heic");
+        let taken = Some(date(2024, 5, 1, 14, 3));
+
+        assert_eq!(
+            sorted(&template("{year}/{month}/{day}/{filename}"), path, taken),
+            PathBuf::from("2024/05/01/IMG_0001.heic")
+        );
+        assert_eq!(
+            sorted(&template("{folder}/{year}-{month}/{hour}{minute}_{stem}.{ext}"), path, taken),
+            PathBuf::from("photos/holiday/2024-05/1403_IMG_0001.heic")
+        );
+        assert_eq!(
+            sorted(&template("{year}/{month}/{filename}"), path, None),
+            PathBuf::from("unknown/unknown/IMG_0001.heic")
+        );
+        // A file at the root has no folder, which leaves no empty path segment
+        assert_eq!(
+            sorted(&template("{folder}/{year}/{filename}"), Path::new("a.jpg"), taken),
+            PathBuf::from("2024/a.jpg")
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates_rejected() {
+        assert!(template("{year}/{month}/{filename}").validate().is_ok());
+        assert!(template("{year}/{stem}.{ext}").validate().is_ok());
+        assert!(template("").validate().is_err());
+        assert!(template("{year}/{month}").validate().is_err());
+        assert!(template("{year}/{week}/{filename}").validate().is_err());
+        assert!(template("{year/{filename}").validate().is_err());
+        assert!(template("../{year}/{filename}").validate().is_err());
+        assert!(template("/{year}/{filename}").validate().is_err());
+    }
+
+    #[test]
+    fn test_capture_date_from_exif() {
+        let original = jpeg_dated(Tag::DateTimeOriginal, b"2023:06:15 14:30:00");
+        assert_eq!(MetadataHandler::photo_info(&original).taken, Some(date(2023, 6, 15, 14, 30)));
+
+        let digitized = jpeg_dated(Tag::DateTimeDigitized, b"2022:01:02 03:04:00");
+        assert_eq!(MetadataHandler::photo_info(&digitized).taken, Some(date(2022, 1, 2, 3, 4)));
+
+        // Cameras without a clock set write zeros
+        let blank = jpeg_dated(Tag::DateTimeOriginal, b"0000:00:00 00:00:00");
+        assert_eq!(MetadataHandler::photo_info(&blank).taken, None);
+        assert_eq!(MetadataHandler::photo_info(&tiff()).taken, None);
+    }
+
+    #[tokio::test]
+    async fn test_job_sorted_by_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        let modified = |year, month, day| zip::DateTime::from_date_and_time(year, month, day, 10, 0, 0).unwrap();
+        let taken = b"2023:06:15 14:30:00";
+        let entries = [
+            ("dcim/a.jpg", jpeg_dated(Tag::DateTimeOriginal, taken), modified(2020, 1, 1)),
+            // No EXIF, so the entry's modified time is used; it's converted on the way
+            ("dcim/b.tif", tiff(), modified(2021, 3, 4)),
+            ("other/a.jpg", jpeg_dated(Tag::DateTimeOriginal, taken), modified(2020, 1, 1)),
+            // The default zip time means none was recorded
+            ("notes.txt", b"notes".to_vec(), zip::DateTime::default()),
+        ];
+        let mut writer = zip::ZipWriter::new(File::create(&input).unwrap());
+        for (name, data, time) in &entries {
+            writer
+                .start_file(*name, FileOptions::default().last_modified_time(*time))
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let options = JobOptions {
+            sorting: Some(template("{year}/{month}/{filename}")),
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        let names = entry_names(&output.output_path);
+        assert_eq!(
+            names,
+            ["2021/03/b.jpg", "2023/06/a-1.jpg", "2023/06/a.jpg", "report.json", "unknown/unknown/notes.txt"]
+        );
+
+        let sorted: Vec<_> = output
+            .report
+            .sorted
+            .iter()
+            .map(|r| (r.original_path.as_str(), r.output_path.as_str(), r.date.as_deref(), r.date_source))
+            .collect();
+        assert_eq!(
+            sorted,
+            [
+                ("dcim/a.jpg", "2023/06/a.jpg", Some("2023-06-15T14:30:00"), DateSource::Exif),
+                ("dcim/b.tif", "2021/03/b.jpg", Some("2021-03-04T10:00:00"), DateSource::Modified),
+                ("other/a.jpg", "2023/06/a-1.jpg", Some("2023-06-15T14:30:00"), DateSource::Exif),
+                ("notes.txt", "unknown/unknown/notes.txt", None, DateSource::Unknown),
+            ]
+        );
+    }
+}
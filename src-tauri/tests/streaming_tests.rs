@@ -0,0 +1,128 @@
+// Tests for writing the output straight to its destination: raw copies of
+// unchanged entries, no partial files on failure, and the free-space check
+
+mod common;
+
+#[cfg(test)]
+mod streaming_tests {
+    use crate::common::{bmp, SilentReporter};
+    use file_sorter_lib::processor::options::{ErrorPolicy, JobOptions};
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use file_sorter_lib::utils::disk;
+    use std::fs::File;
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+    use zip::write::FileOptions;
+    use zip::CompressionMethod;
+
+    /// Zip holding `photo.bmp` and whatever `(name, contents, method)` entries are given
+    fn write_input_zip(path: &Path, extra: &[(&str, &[u8], CompressionMethod)]) {
+        let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+        writer.start_file("photo.bmp", FileOptions::default()).unwrap();
+        writer.write_all(&bmp()).unwrap();
+        for (name, contents, method) in extra {
+            let options = FileOptions::default().compression_method(*method);
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_entries_are_copied_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_input_zip(&input, &[("notes.txt", b"kept as is", CompressionMethod::Stored)]);
+        let job = JobInfo::new(input.to_string_lossy().to_string(), JobOptions::default());
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&output.output_path).unwrap()).unwrap();
+        // A re-compressed copy would use the writer's Deflate setting
+        assert_eq!(archive.by_name("notes.txt").unwrap().compression(), CompressionMethod::Stored);
+        assert!(archive.by_name("photo.jpg").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_output_zip_written_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_input_zip(&input, &[]);
+        let job = JobInfo::new(input.to_string_lossy().to_string(), JobOptions::default());
+
+        let output = process_job(&SilentReporter, &job, output_dir.path()).await.unwrap();
+
+        // Only the finished zip is left in the destination
+        let names: Vec<_> = std::fs::read_dir(output_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(names, [Path::new(&output.output_path)]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_leaves_no_partial_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("broken.zip");
+        write_input_zip(&input, &[("broken.tiff", b"not a tiff", CompressionMethod::Deflated)]);
+        let job = JobInfo::new(input.to_string_lossy().to_string(), JobOptions::default());
+
+        assert!(process_job(&SilentReporter, &job, output_dir.path()).await.is_err());
+        assert_eq!(std::fs::read_dir(output_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_entry_claiming_huge_size_fails_cleanly() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("huge.zip");
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("huge.bmp", options).unwrap();
+        writer.write_all(&bmp()).unwrap();
+        let mut data = writer.finish().unwrap().into_inner();
+
+        // Claim 4 GB uncompressed in the local and central headers
+        let claimed = 0xF000_0000u32.to_le_bytes();
+        let local = data.windows(4).position(|w| w == b"PK\x03\x04").unwrap();
+        data[local + 22..local + 26].copy_from_slice(&claimed);
+        let central = data.windows(4).position(|w| w == b"PK\x01\x02").unwrap();
+        data[central + 24..central + 28].copy_from_slice(&claimed);
+        std::fs::write(&input, data).unwrap();
+
+        // A dry run skips the free-space check, which the claimed size would fail
+        let options = JobOptions {
+            error_policy: ErrorPolicy::SkipAndReport,
+            dry_run: true,
+            ..Default::default()
+        };
+        let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+        let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+
+        assert_eq!(output.report.failed.len(), 1);
+        assert!(output.report.failed[0].error.contains("larger than the 1.0 GB limit"));
+    }
+
+    #[test]
+    fn test_free_space_is_known() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let available = disk::available_space(dir.path());
+
+        if cfg!(unix) {
+            assert!(available.unwrap() > 0);
+        } else {
+            assert!(available.is_none());
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(disk::format_size(512), "512 bytes");
+        assert_eq!(disk::format_size(1_500_000), "1.5 MB");
+        assert_eq!(disk::format_size(2_000_000_000), "2.0 GB");
+    }
+}
@@ -0,0 +1,141 @@
+// Tests for transparent images: the transparency policy for JPEG output and the report
+
+mod common;
+
+#[cfg(test)]
+mod transparency_tests {
+    use crate::common::{entry_names, write_zip, SilentReporter};
+    use file_sorter_lib::processor::image_converter::{ConversionResult, ImageConverter, TransparencyHandling};
+    use file_sorter_lib::processor::options::{
+        AlphaFormat, ConversionSettings, ErrorPolicy, JobOptions, TargetFormat, TransparencyPolicy,
+    };
+    use file_sorter_lib::processor::zip_handler::process_job;
+    use file_sorter_lib::processor::JobInfo;
+    use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+    use std::path::Path;
+
+    /// A red square whose left half is fully transparent, or fully opaque throughout
+    fn tiff(transparent: bool) -> Vec<u8> {
+        let img = RgbaImage::from_fn(16, 16, |x, _| {
+            let alpha = if transparent && x < 8 { 0 } else { 255 };
+            Rgba([255, 0, 0, alpha])
+        });
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(img).write_to(&mut data, ImageFormat::Tiff).unwrap();
+        data.into_inner()
+    }
+
+    fn convert(transparency: TransparencyPolicy, source: &[u8]) -> anyhow::Result<(ConversionResult, Vec<u8>)> {
+        let settings = ConversionSettings {
+            target_format: TargetFormat::Jpeg,
+            transparency,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        let result = ImageConverter::with_settings(settings).process_bytes(Path::new("logo.tif"), source, &mut output)?;
+        Ok((result, output))
+    }
+
+    #[test]
+    fn test_opaque_alpha_ignored() {
+        let (result, output) = convert(TransparencyPolicy::Fail, &tiff(false)).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { transparency: None, ref extension, .. } if extension == "jpg"
+        ));
+        assert_eq!(image::guess_format(&output).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn test_composited_onto_background() {
+        let policy = TransparencyPolicy::Composite { background: [0, 0, 255] };
+        let (result, output) = convert(policy, &tiff(true)).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted { transparency: Some(TransparencyHandling::Composited), .. }
+        ));
+        let img = image::load_from_memory(&output).unwrap().to_rgb8();
+        let [r, g, b] = img.get_pixel(2, 8).0;
+        assert!(r < 20 && g < 20 && b > 230, "background {:?}", [r, g, b]);
+        let [r, g, b] = img.get_pixel(13, 8).0;
+        assert!(r > 230 && g < 20 && b < 20, "foreground {:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn test_alpha_kept_in_another_format() {
+        let policy = TransparencyPolicy::KeepAlpha { format: AlphaFormat::Png };
+        let (result, output) = convert(policy, &tiff(true)).unwrap();
+
+        assert!(matches!(
+            result,
+            ConversionResult::Converted {
+                transparency: Some(TransparencyHandling::ChangedFormat),
+                ref extension,
+                ..
+            } if extension == "png"
+        ));
+        let img = image::load_from_memory_with_format(&output, ImageFormat::Png).unwrap();
+        let img = img.as_rgba8().unwrap();
+        assert_eq!(img.get_pixel(2, 8).0[3], 0);
+        assert_eq!(img.get_pixel(13, 8).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_fail_policy_rejects_transparent_images() {
+        let Err(error) = convert(TransparencyPolicy::Fail, &tiff(true)) else {
+            panic!("transparent image converted to JPEG");
+        };
+        assert!(format!("{:#}", error).contains("transparent"), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_job_reports_transparency() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("input.zip");
+        write_zip(&input, &[("logo.tif", tiff(true)), ("photo.tif", tiff(false))]);
+
+        for (transparency, handling, output_path) in [
+            (
+                TransparencyPolicy::KeepAlpha { format: AlphaFormat::WebpLossless },
+                Some(TransparencyHandling::ChangedFormat),
+                Some("logo.webp"),
+            ),
+            (TransparencyPolicy::Fail, None, None),
+        ] {
+            let options = JobOptions {
+                conversion: ConversionSettings {
+                    transparency,
+                    ..Default::default()
+                },
+                error_policy: ErrorPolicy::SkipAndReport,
+                ..Default::default()
+            };
+            let job = JobInfo::new(input.to_string_lossy().to_string(), options);
+            let output = process_job(&SilentReporter, &job, dir.path()).await.unwrap();
+            let report = &output.report;
+
+            let names = entry_names(&output.output_path);
+            let mut expected: Vec<&str> = output_path.into_iter().chain(["photo.jpg", "report.json"]).collect();
+            expected.sort();
+            assert_eq!(names, expected, "{:?}", transparency);
+
+            match handling {
+                Some(handling) => {
+                    assert_eq!(report.transparency.len(), 1);
+                    assert_eq!(report.transparency[0].original_path, "logo.tif");
+                    assert_eq!(Some(report.transparency[0].output_path.as_str()), output_path);
+                    assert_eq!(report.transparency[0].handling, handling);
+                    assert!(report.failed.is_empty());
+                }
+                None => {
+                    assert!(report.transparency.is_empty());
+                    assert_eq!(report.failed.len(), 1);
+                    assert_eq!(report.failed[0].original_path, "logo.tif");
+                }
+            }
+        }
+    }
+}
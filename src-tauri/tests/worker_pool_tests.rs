@@ -0,0 +1,94 @@
+// Tests for the bounded worker pool used to convert images in parallel
+
+#[cfg(test)]
+mod worker_pool_tests {
+    use file_sorter_lib::processor::worker_pool;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_every_task_runs_once() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let tasks: Vec<usize> = (0..50).collect();
+
+        let mut results = worker_pool::spawn(tasks, 4, stop, || (), |_, task| task * 2);
+
+        let mut received = Vec::new();
+        while let Some((idx, value)) = results.recv().await {
+            assert_eq!(value, idx * 2);
+            received.push(idx);
+        }
+        received.sort();
+        assert_eq!(received, (0..50).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_stop_flag_halts_workers() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let tasks: Vec<usize> = (0..1000).collect();
+
+        let worker_stop = Arc::clone(&stop);
+        let mut results = worker_pool::spawn(tasks, 2, Arc::clone(&stop), || (), move |_, task| {
+            if *task == 5 {
+                worker_stop.store(true, Ordering::SeqCst);
+            }
+            *task
+        });
+
+        let mut count = 0;
+        while results.recv().await.is_some() {
+            count += 1;
+        }
+        assert!(count < 1000);
+    }
+
+    #[tokio::test]
+    async fn test_slow_task_bounds_results_ahead_of_it() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let tasks: Vec<usize> = (0..100).collect();
+
+        let mut results = worker_pool::spawn(tasks, 3, stop, || (), |_, task| {
+            if *task == 0 {
+                std::thread::sleep(Duration::from_millis(300));
+            }
+            *task
+        });
+
+        // Only tasks within two per thread of the slow one run before it's received
+        let mut ahead = 0;
+        while let Some((idx, _)) = results.recv().await {
+            if idx == 0 {
+                break;
+            }
+            ahead += 1;
+        }
+        assert!(ahead <= 5, "{} results arrived ahead of the first", ahead);
+
+        let mut rest = 0;
+        while results.recv().await.is_some() {
+            rest += 1;
+        }
+        assert_eq!(ahead + rest, 99);
+    }
+
+    #[tokio::test]
+    async fn test_worker_state_is_per_thread() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let tasks: Vec<usize> = (0..20).collect();
+
+        // Each worker counts the tasks it handled; the counts add up to the total
+        let mut results = worker_pool::spawn(tasks, 3, stop, || 0usize, |seen, _| {
+            *seen += 1;
+            *seen
+        });
+
+        let mut firsts = 0;
+        while let Some((_, seen)) = results.recv().await {
+            if seen == 1 {
+                firsts += 1;
+            }
+        }
+        assert!((1..=3).contains(&firsts));
+    }
+}
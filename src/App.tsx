@@ -3,6 +3,8 @@ import { DropZone } from "./components/DropZone";
 import { QueueList } from "./components/QueueList";
 import { ProgressBar } from "./components/ProgressBar";
 import { ActionButtons } from "./components/ActionButtons";
+import { HistoryList } from "./components/HistoryList";
+import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import type { JobInfo, ProgressInfo } from "./types";
 
@@ -11,6 +13,11 @@ export function App() {
   const [currentProgress, setCurrentProgress] = useState<ProgressInfo | null>(null);
 
   useEffect(() => {
+    // Restore the queue saved by the last session
+    invoke<JobInfo[]>("list_jobs")
+      .then(setJobs)
+      .catch((error) => console.error("Failed to load the queue:", error));
+
     // Listen for progress updates
     const progressUnlisten = listen<ProgressInfo>("processing-progress", (event) => {
       setCurrentProgress(event.payload);
@@ -24,13 +31,19 @@ export function App() {
     });
 
     // Listen for job completion
-    const completeUnlisten = listen<{ jobId: string; outputPath: string }>(
+    const completeUnlisten = listen<{ jobId: string; outputPath: string; warningCount: number }>(
       "job-complete",
       (event) => {
+        const { jobId, outputPath, warningCount } = event.payload;
         setJobs((prev) =>
           prev.map((job) =>
-            job.id === event.payload.jobId
-              ? { ...job, status: "success", outputPath: event.payload.outputPath }
+            job.id === jobId
+              ? {
+                  ...job,
+                  status: warningCount > 0 ? "completedWithWarnings" : "success",
+                  outputPath,
+                  warningCount,
+                }
               : job
           )
         );
@@ -53,10 +66,26 @@ export function App() {
       }
     );
 
+    // Listen for jobs skipped because their output already exists
+    const skippedUnlisten = listen<{ jobId: string; outputPath: string }>(
+      "job-skipped",
+      (event) => {
+        setJobs((prev) =>
+          prev.map((job) =>
+            job.id === event.payload.jobId
+              ? { ...job, status: "skipped", outputPath: event.payload.outputPath }
+              : job
+          )
+        );
+        setCurrentProgress(null);
+      }
+    );
+
     return () => {
       progressUnlisten.then((unlisten) => unlisten());
       completeUnlisten.then((unlisten) => unlisten());
       failedUnlisten.then((unlisten) => unlisten());
+      skippedUnlisten.then((unlisten) => unlisten());
     };
   }, []);
 
@@ -65,14 +94,20 @@ export function App() {
   };
 
   const handleCancel = async () => {
-    const { invoke } = await import("@tauri-apps/api/core");
-    await invoke("cancel_current");
+    try {
+      await invoke("cancel_current");
+    } catch (error) {
+      console.error("Failed to cancel job:", error);
+    }
   };
 
   const handleClearFinished = async () => {
-    const { invoke } = await import("@tauri-apps/api/core");
-    await invoke("clear_finished");
-    setJobs((prev) => prev.filter((job) => job.status === "pending" || job.status === "processing"));
+    try {
+      await invoke("clear_finished");
+      setJobs((prev) => prev.filter((job) => job.status === "pending" || job.status === "processing"));
+    } catch (error) {
+      console.error("Failed to clear finished jobs:", error);
+    }
   };
 
   return (
@@ -90,10 +125,12 @@ export function App() {
         onCancel={handleCancel}
         onClearFinished={handleClearFinished}
         hasActiveJob={jobs.some((j) => j.status === "processing")}
-        hasFinishedJobs={jobs.some((j) => j.status === "success" || j.status === "failed" || j.status === "cancelled")}
+        hasFinishedJobs={jobs.some((j) => j.status !== "pending" && j.status !== "processing")}
       />
 
       <QueueList jobs={jobs} />
+
+      <HistoryList />
     </div>
   );
 }
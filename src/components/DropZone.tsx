@@ -1,26 +1,30 @@
 import { useState } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 import { open } from "@tauri-apps/plugin-dialog";
-import type { JobInfo } from "../types";
+import type { Enqueued, JobInfo } from "../types";
 
 interface DropZoneProps {
   onFilesAdded: (jobs: JobInfo[]) => void;
 }
 
+const IMAGE_EXTENSIONS = ["jpg", "jpeg", "png", "gif", "heic", "heif", "webp", "tiff", "tif", "bmp", "avif", "dng", "cr2", "nef", "arw"];
+
 export function DropZone({ onFilesAdded }: DropZoneProps) {
   const [isDragging, setIsDragging] = useState(false);
 
   const handleFiles = async (paths: string[]) => {
-    // Filter only .zip files
-    const zipPaths = paths.filter((path) => path.toLowerCase().endsWith(".zip"));
-    
-    if (zipPaths.length === 0) {
+    if (paths.length === 0) {
       return;
     }
 
+    // The backend makes one job per zip or folder and one for any loose images
     try {
-      const jobs = await invoke<JobInfo[]>("enqueue_zips", { paths: zipPaths });
+      const { jobs, ignored } = await invoke<Enqueued>("enqueue_zips", { paths });
       onFilesAdded(jobs);
+      if (ignored.length > 0) {
+        const names = ignored.map((path) => path.split(/[\\/]/).pop()).join(", ");
+        alert(`Skipped ${ignored.length} file(s) that aren't zips, folders or images: ${names}`);
+      }
     } catch (error) {
       console.error("Failed to enqueue files:", error);
       alert(`Error: ${error}`);
@@ -33,8 +37,8 @@ export function DropZone({ onFilesAdded }: DropZoneProps) {
         multiple: true,
         filters: [
           {
-            name: "Zip Files",
-            extensions: ["zip"],
+            name: "Zip Files and Images",
+            extensions: ["zip", ...IMAGE_EXTENSIONS],
           },
         ],
       });
@@ -49,6 +53,21 @@ export function DropZone({ onFilesAdded }: DropZoneProps) {
     }
   };
 
+  const handleChooseFolder = async (e: MouseEvent) => {
+    e.stopPropagation();
+    try {
+      const selected = await open({ directory: true, multiple: true });
+
+      if (selected && Array.isArray(selected)) {
+        await handleFiles(selected);
+      } else if (selected) {
+        await handleFiles([selected]);
+      }
+    } catch (error) {
+      console.error("Failed to open folder dialog:", error);
+    }
+  };
+
   const handleDragOver = (e: DragEvent) => {
     e.preventDefault();
     e.stopPropagation();
@@ -86,14 +105,21 @@ export function DropZone({ onFilesAdded }: DropZoneProps) {
     >
       <div style={{ pointerEvents: "none" }}>
         <div style={{ fontSize: "3rem", marginBottom: "1rem" }}>📦</div>
-        <h2>Drop Zip Files Here</h2>
+        <h2>Drop Zip Files, Folders or Images Here</h2>
         <p>
           or click to choose files
         </p>
         <p style={{ marginTop: "0.5rem", fontSize: "0.875rem", opacity: 0.7 }}>
-          Only .zip files will be processed
+          Each zip and folder becomes its own job; loose images are converted together
         </p>
       </div>
+      <button
+        onClick={handleChooseFolder}
+        className="btn btn-secondary"
+        style={{ marginTop: "1rem", padding: "0.25rem 0.75rem", fontSize: "0.75rem" }}
+      >
+        Choose Folder
+      </button>
     </div>
   );
 }
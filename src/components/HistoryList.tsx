@@ -0,0 +1,97 @@
+import { useState } from "preact/hooks";
+import { invoke } from "@tauri-apps/api/core";
+import { QueueItem } from "./QueueItem";
+import type { JobInfo, ProcessingReport } from "../types";
+
+export function HistoryList() {
+  const [history, setHistory] = useState<JobInfo[] | null>(null);
+  const [reports, setReports] = useState<Record<string, ProcessingReport | null>>({});
+
+  const handleToggle = async () => {
+    if (history) {
+      setHistory(null);
+      return;
+    }
+
+    // Loaded each time it's opened, as jobs move here when finished ones are cleared
+    try {
+      setHistory(await invoke<JobInfo[]>("list_job_history"));
+    } catch (error) {
+      console.error("Failed to load job history:", error);
+      alert(`Error: ${error}`);
+    }
+  };
+
+  const handleShowReport = async (jobId: string) => {
+    try {
+      const report = await invoke<ProcessingReport | null>("get_job_report", { jobId });
+      setReports((prev) => ({ ...prev, [jobId]: report }));
+    } catch (error) {
+      console.error("Failed to load report:", error);
+      alert(`Error: ${error}`);
+    }
+  };
+
+  return (
+    <div className="queue-container">
+      <div style={{ display: "flex", justifyContent: "space-between", alignItems: "center" }}>
+        <h2>History</h2>
+        <button onClick={handleToggle} className="btn btn-secondary">
+          {history ? "Hide History" : "Show History"}
+        </button>
+      </div>
+
+      {history && history.length === 0 && (
+        <p style={{ color: "var(--text-secondary)" }}>No finished jobs yet</p>
+      )}
+
+      {history && history.length > 0 && (
+        <div className="queue-list">
+          {history.map((job) => (
+            <div key={job.id}>
+              <QueueItem job={job} />
+              {job.id in reports ? (
+                <ReportDetails report={reports[job.id]} />
+              ) : (
+                <button
+                  onClick={() => handleShowReport(job.id)}
+                  className="btn btn-secondary"
+                  style={{ padding: "0.25rem 0.75rem", fontSize: "0.75rem", margin: "0.5rem 0" }}
+                >
+                  Show Report
+                </button>
+              )}
+            </div>
+          ))}
+        </div>
+      )}
+    </div>
+  );
+}
+
+function ReportDetails({ report }: { report: ProcessingReport | null }) {
+  // Jobs that failed or were cancelled have no report
+  if (!report) {
+    return (
+      <div style={{ fontSize: "0.875rem", color: "var(--text-secondary)", margin: "0.5rem 0" }}>
+        No report for this job
+      </div>
+    );
+  }
+
+  const failed = report.failed ?? [];
+
+  return (
+    <div style={{ fontSize: "0.875rem", color: "var(--text-secondary)", margin: "0.5rem 0" }}>
+      {report.dryRun && <div>Dry run: nothing was written</div>}
+      {report.metadataNotes.map((note) => (
+        <div key={note}>{note}</div>
+      ))}
+      {failed.map((record) => (
+        <div key={record.originalPath} style={{ color: "var(--error-color)" }}>
+          {record.originalPath}: {record.error}
+        </div>
+      ))}
+    </div>
+  );
+}
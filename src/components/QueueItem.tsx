@@ -20,10 +20,14 @@ export function QueueItem({ job }: QueueItemProps) {
     pending: "Pending",
     processing: "Processing",
     success: "Success",
+    completedWithWarnings: "Completed with warnings",
     failed: "Failed",
     cancelled: "Cancelled",
+    skipped: "Skipped",
   }[job.status];
 
+  const warningCount = job.warningCount ?? job.summary?.warningCount ?? 0;
+
   const fileName = job.inputPath.split(/[\\/]/).pop() || job.inputPath;
 
   return (
@@ -51,10 +55,16 @@ export function QueueItem({ job }: QueueItemProps) {
         </div>
       )}
 
-      {job.status === "success" && job.outputPath && (
+      {job.status === "completedWithWarnings" && (
+        <div style={{ marginTop: "0.5rem", fontSize: "0.875rem", color: "var(--warning-color)" }}>
+          {warningCount} warning{warningCount === 1 ? "" : "s"}; see report.json for details
+        </div>
+      )}
+
+      {(job.status === "success" || job.status === "completedWithWarnings" || job.status === "skipped") && job.outputPath && (
         <div style={{ marginTop: "1rem", display: "flex", alignItems: "center", justifyContent: "space-between", gap: "1rem" }}>
           <div style={{ fontSize: "0.875rem", color: "var(--text-secondary)", overflow: "hidden", textOverflow: "ellipsis", whiteSpace: "nowrap" }}>
-            Saved to: {job.outputPath}
+            {job.status === "skipped" ? "Already exists: " : "Saved to: "}{job.outputPath}
           </div>
           <button
             onClick={handleOpenFolder}
@@ -1,12 +1,283 @@
 export interface JobInfo {
   id: string;
   inputPath: string;
-  status: "pending" | "processing" | "success" | "failed" | "cancelled";
+  input: JobInput;
+  status:
+    | "pending"
+    | "processing"
+    | "success"
+    | "completedWithWarnings"
+    | "failed"
+    | "cancelled"
+    | "skipped";
   progress?: ProgressInfo;
   outputPath?: string;
   error?: string;
+  options: JobOptions;
+  createdAt: string;
+  finishedAt?: string | null;
+  // Only while the job is in the queue; finished jobs' reports come from get_job_report
+  report?: ProcessingReport | null;
+  // Counts from the report, kept when the job is saved
+  summary?: JobSummary | null;
+  // From the job-complete event; jobs restored from a previous session use the summary
+  warningCount?: number;
 }
 
+// Returned by enqueue_zips: the new jobs, and loose files left out as not images
+export interface Enqueued {
+  jobs: JobInfo[];
+  ignored: string[];
+}
+
+export interface JobSummary {
+  stats: {
+    filesScanned: number;
+    filesIncluded: number;
+    filesConverted: number;
+    filesPassedThrough: number;
+    filesSkipped: number;
+    filesFailed: number;
+  };
+  warningCount: number;
+}
+
+// Only the fields the UI reads; see report.rs for the full shape
+export interface ProcessingReport {
+  timestamp: string;
+  inputZip: string;
+  // Set for dry runs, whose paths are where files would have gone
+  dryRun?: boolean;
+  metadataNotes: string[];
+  failed?: FailedRecord[];
+  animated?: AnimatedRecord[];
+  raw?: RawRecord[];
+  reoriented?: ReorientedRecord[];
+  colorProfiles?: ColorProfileRecord[];
+  highBitDepth?: BitDepthRecord[];
+  transparency?: TransparencyRecord[];
+  dimensions?: DimensionsRecord[];
+  derivatives?: DerivativeRecord[];
+  sorted?: SortedRecord[];
+  duplicates?: DuplicateGroup[];
+  formatMismatches?: FormatMismatchRecord[];
+}
+
+export interface FailedRecord {
+  originalPath: string;
+  // Set when the original was copied into the output
+  outputPath?: string | null;
+  error: string;
+}
+
+export interface AnimatedRecord {
+  originalPath: string;
+  outputPath: string;
+  originalFormat: string;
+  frames: number;
+  handling: "keep" | "convertAnimated" | "firstFrame";
+}
+
+export interface RawRecord {
+  originalPath: string;
+  outputPath: string;
+  // Set when the RAW file was kept in the output as well
+  originalCopyPath?: string | null;
+  originalFormat: string;
+  method: "embeddedPreview" | "develop";
+  metadataPreserved: boolean;
+}
+
+export interface ReorientedRecord {
+  originalPath: string;
+  outputPath: string;
+  // EXIF Orientation value the original had, 2-8
+  orientation: number;
+  trimmed: boolean;
+  // Set when the JPEG couldn't be rotated and was copied unchanged
+  error?: string | null;
+}
+
+export interface ColorProfileRecord {
+  originalPath: string;
+  outputPath: string;
+  // e.g. "Display P3"; null if the profile has no description
+  profileName?: string | null;
+  handling: "embedded" | "convertedToSrgb" | "dropped";
+}
+
+export interface BitDepthRecord {
+  originalPath: string;
+  outputPath: string;
+  // 32 for floating point
+  sourceBits: number;
+  hdr?: "pq" | "hlg" | "appleGainMap" | null;
+  // 16 when written as 16-bit PNG
+  outputBits: number;
+  dithered: boolean;
+}
+
+export interface TransparencyRecord {
+  originalPath: string;
+  outputPath: string;
+  // "changedFormat" when written as PNG or lossless WebP instead of the target format
+  handling: "kept" | "changedFormat" | "composited";
+}
+
+export interface DimensionsRecord {
+  originalPath: string;
+  outputPath: string;
+  originalWidth: number;
+  originalHeight: number;
+  outputWidth: number;
+  outputHeight: number;
+}
+
+export interface DerivativeRecord {
+  originalPath: string;
+  // The full-size image written for the same entry
+  fullSizePath: string;
+  name: string;
+  outputPath: string;
+  width: number;
+  height: number;
+}
+
+export interface SortedRecord {
+  originalPath: string;
+  outputPath: string;
+  // e.g. "2024-05-01T14:03:00"; null for undated files
+  date: string | null;
+  dateSource: "exif" | "modified" | "unknown";
+  // Name of the sort rule that placed it; null when it followed the template
+  rule?: string | null;
+}
+
+export interface DuplicateGroup {
+  kind: "exact" | "near";
+  // SHA-256 for exact groups, the first file's difference hash for near ones
+  hash: string;
+  // In input order; outputPath is null for copies left out
+  files: { originalPath: string; outputPath: string | null }[];
+}
+
+export interface FormatMismatchRecord {
+  originalPath: string;
+  // Empty if the file had no extension
+  declaredExtension: string;
+  detectedFormat: string;
+}
+
+export type JobInput =
+  | { kind: "zip" }
+  | { kind: "directory" }
+  | { kind: "files"; paths: string[] };
+
+export interface JobOptions {
+  nonImagePolicy: "passthrough" | "dropAndReport" | "dropSilently";
+  nestedZipMode: "ignore" | "flatten" | "repack";
+  maxNestedDepth: number;
+  workerThreads?: number | null;
+  conversion: ConversionSettings;
+  derivatives: DerivativeSettings;
+  // Omitted to keep the input's folder structure
+  sorting?: SortSettings | null;
+  duplicates?: DuplicateSettings;
+  // Report where files would go without writing anything
+  dryRun?: boolean;
+  errorPolicy: "failFast" | "skipAndReport" | "copyOriginalAndReport";
+  // Omitted to use the saved output preferences
+  output?: OutputSettings | null;
+}
+
+export interface DerivativeSettings {
+  // e.g. [{ name: "thumb", maxLongEdge: 256 }]
+  sizes: { name: string; maxLongEdge: number }[];
+  layout: "folders" | "suffix";
+}
+
+export interface DuplicateSettings {
+  policy?: "keepAll" | "keepAllAndReport" | "keepOne";
+  // Also report images that look alike
+  nearDuplicates?: boolean;
+  // Perceptual hash bits that may differ; defaults to 5
+  maxDistance?: number;
+}
+
+export interface SortSettings {
+  // e.g. "{year}/{month}/{day}/{filename}"; defaults to "{folder}/{filename}"
+  template?: string;
+  // Tried in order before the template; the first match picks the folder
+  rules?: SortRule[];
+}
+
+export interface SortRule {
+  name: string;
+  // All must hold; text is a case-insensitive glob pattern
+  match?: {
+    make?: string;
+    model?: string;
+    lens?: string;
+    format?: string[];
+    minWidth?: number;
+    maxWidth?: number;
+    minHeight?: number;
+    maxHeight?: number;
+    orientation?: "landscape" | "portrait" | "square";
+    hasGps?: boolean;
+    // Other EXIF fields by tag name, e.g. { Software: "Lightroom*" }
+    exif?: Record<string, string>;
+    filename?: string;
+  };
+  // e.g. "cameras/{model}/{year}"
+  folder: string;
+}
+
+export interface OutputSettings {
+  mode: "zip" | "directory";
+  destination:
+    | { kind: "downloads" }
+    | { kind: "nextToInput" }
+    | { kind: "folder"; path: string };
+  // Without ".zip"; supports {stem}, {date} and {job_id}
+  filenameTemplate: string;
+  collisionPolicy: "suffix" | "overwrite" | "skip";
+}
+
+export interface ConversionSettings {
+  targetFormat: "jpeg" | "png" | "webpLossless" | "webpLossy" | "avif";
+  quality: number;
+  chromaSubsampling: "yuv444" | "yuv422" | "yuv420";
+  reencodeSupported: boolean;
+  animation: "keep" | "convertAnimated" | "firstFrame";
+  raw: "ignore" | "embeddedPreview" | "develop";
+  keepRaw: boolean;
+  rotateCopiedJpegs: boolean;
+  colorProfile: "embed" | "convertToSrgb";
+  highBitDepthPng: boolean;
+  transparency: TransparencyPolicy;
+  resize: ResizeSettings;
+}
+
+// Unset limits don't apply
+export interface ResizeSettings {
+  maxLongEdge?: number | null;
+  maxWidth?: number | null;
+  maxHeight?: number | null;
+  maxMegapixels?: number | null;
+  mode: "fit" | "fill" | "exact";
+  filter: "nearest" | "triangle" | "catmullRom" | "gaussian" | "lanczos3";
+  allowUpscale: boolean;
+  // Also resize JPEG, PNG and files already in the target format
+  includeCopied: boolean;
+}
+
+export type TransparencyPolicy =
+  // RGB, 0-255 each
+  | { kind: "composite"; background: [number, number, number] }
+  | { kind: "keepAlpha"; format: "png" | "webpLossless" }
+  | { kind: "fail" };
+
 export interface ProgressInfo {
   currentFile: number;
   totalFiles: number;